@@ -84,7 +84,14 @@ fn main() {
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_os::init())
-        .manage(Arc::new(Mutex::new(media_api::PlaybackRegistry::new())))
+        .setup(|app| {
+            let cache_dir = app.path().app_cache_dir()
+                .inspect_err(|e| log::warn!("no cache directory: {e}"))
+                .ok()
+                .map(|x| x.join("samples"));
+            app.manage(Arc::new(Mutex::new(media_api::PlaybackRegistry::new(cache_dir))));
+            Ok(())
+        })
         .manage(Mutex::new(history::HistoryState { undo: vec![], redo: vec![] }))
         .invoke_handler(tauri::generate_handler![
             media_api::media_version,
@@ -94,18 +101,36 @@ fn main() {
             media_api::open_audio,
             media_api::open_video,
             media_api::open_audio_sampler,
+            media_api::open_audio_spectrogram,
             media_api::open_video_sampler,
             media_api::extract_subtitles,
+            media_api::extract_subtitle_streams,
+            media_api::find_closed_captions,
+            media_api::recognize_subtitles,
+            media_api::detect_speech,
+            media_api::estimate_sync,
+            media_api::align_audio,
             media_api::seek_media,
             media_api::seek_media_byte,
             media_api::seek_audio,
             media_api::seek_video,
             media_api::skip_until,
+            media_api::seek_exact,
             media_api::sample_automatic,
             media_api::get_frames_automatic,
             media_api::video_set_size,
+            media_api::video_set_deinterlace,
+            media_api::get_sampled_data,
+            media_api::scan_keyframes,
+            media_api::open_thumbnailer,
+            media_api::get_thumbnails,
+            media_api::get_still,
+            media_api::snap_to_speech,
+            media_api::get_audio_waveform,
             media_api::get_keyframe_before,
             media_api::get_frame_before,
+            media_api::export_keyframes,
+            media_api::import_keyframes,
             media_api::test_performance,
             media_api::media_config,
             redirect_log::set_log_filter_level,
@@ -9,4 +9,24 @@ pub mod subtitles;
 pub mod session;
 
 mod aggregation_tree;
+mod ass;
+mod cache;
+mod cea608;
+mod cea708;
+mod color;
+mod deinterlace;
 mod disjoint_interval_set;
+mod fft;
+mod fingerprint;
+mod frame_cache;
+mod keyframes;
+mod ocr;
+mod shots;
+mod snap;
+mod still;
+mod sync;
+mod tonemap;
+mod vad;
+
+#[cfg(test)]
+mod testing;
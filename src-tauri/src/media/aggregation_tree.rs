@@ -111,8 +111,9 @@ where
         &self.data[self.leaf_start..]
     }
 
-    /// Retrieves a slice representing a single level of the tree.
-    #[expect(unused)]
+    /// Retrieves a slice representing a single level of the tree, where each
+    /// node aggregates `resolution` consecutive leaves. Nodes past the end of
+    /// the data hold the initial value.
     pub fn get_level(&self, resolution: usize) -> &[N] {
         debug_assert!(resolution.is_power_of_two(), "Resolution must be a power of 2");
         debug_assert!(resolution <= self.length, "Resolution cannot be greater than length");
@@ -121,7 +122,7 @@ where
         let layer = self.layers - level;
 
         let start_idx = 2usize.pow(layer - 1) - 1;
-        let end_idx = (2usize.pow(layer) - 1).min(self.data.len() - 1);
+        let end_idx = (2usize.pow(layer) - 1).min(self.data.len());
 
         &self.data[start_idx..end_idx]
     }
@@ -158,7 +159,35 @@ where
         let right_child_idx = index * 2 + 2;
 
         let l = self.data[left_child_idx];
-        let r = self.data[right_child_idx];
-        self.data[index] = (self.aggregator)(l, r);
+        // the last leaf may have no sibling
+        self.data[index] = match self.data.get(right_child_idx) {
+            Some(&r) => (self.aggregator)(l, r),
+            None => l,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_level() {
+        let mut tree = AggregationTree::new(5, |a: i32, b: i32| a + b, 0);
+        tree.set(&[1, 2, 3, 4, 5], 0);
+
+        assert_eq!(tree.get_level(1), &[1, 2, 3, 4, 5]);
+        assert_eq!(tree.get_level(2), &[3, 7, 5, 0]);
+        assert_eq!(tree.get_level(4), &[10, 5]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_partial_update() {
+        let mut tree = AggregationTree::new(6, i32::max, 0);
+        tree.set(&[4, 1], 2);
+        tree.set(&[9], 5);
+
+        assert_eq!(tree.get_level(2), &[0, 4, 9, 0]);
+        assert_eq!(tree.get_level(4), &[4, 9]);
+    }
+}
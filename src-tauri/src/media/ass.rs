@@ -0,0 +1,371 @@
+use log::warn;
+use num_traits::ToPrimitive;
+
+/// The style fields of ASS, in the order of the default `Format` line.
+const V4_PLUS_FORMAT: [&str; 23] = [
+    "Name", "Fontname", "Fontsize", "PrimaryColour", "SecondaryColour",
+    "OutlineColour", "BackColour", "Bold", "Italic", "Underline", "StrikeOut",
+    "ScaleX", "ScaleY", "Spacing", "Angle", "BorderStyle", "Outline", "Shadow",
+    "Alignment", "MarginL", "MarginR", "MarginV", "Encoding",
+];
+
+/// `[V4++ Styles]` has no `Format` line of its own to go by.
+const V4_PLUS_PLUS_FORMAT: [&str; 25] = [
+    "Name", "Fontname", "Fontsize", "PrimaryColour", "SecondaryColour",
+    "OutlineColour", "BackColour", "Bold", "Italic", "Underline", "StrikeOut",
+    "ScaleX", "ScaleY", "Spacing", "Angle", "BorderStyle", "Outline", "Shadow",
+    "Alignment", "MarginL", "MarginR", "MarginT", "MarginB", "Encoding",
+    "RelativeTo",
+];
+
+/// A dialogue line as ffmpeg's decoders give it, without the timing.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, ts_rs::TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct AssEvent {
+    pub layer: i32,
+    pub style: String,
+    /// the `Name` field
+    pub actor: String,
+    pub margin_l: i32,
+    pub margin_r: i32,
+    pub margin_v: i32,
+    pub effect: String,
+    /// with its override tags
+    pub text: String,
+}
+
+impl AssEvent {
+    /// Reads `ReadOrder,Layer,Style,Name,MarginL,MarginR,MarginV,Effect,Text`,
+    /// the packet format of ASS in Matroska and what ffmpeg decodes all text
+    /// subtitles into.
+    pub fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.splitn(9, ',').collect();
+        let [_, layer, style, actor, margin_l, margin_r, margin_v, effect, text] = fields[..]
+        else {
+            return None;
+        };
+        Some(Self {
+            layer: parse_int(layer)?,
+            style: style.trim().to_owned(),
+            actor: actor.trim().to_owned(),
+            margin_l: parse_int(margin_l)?,
+            margin_r: parse_int(margin_r)?,
+            margin_v: parse_int(margin_v)?,
+            effect: effect.to_owned(),
+            text: text.to_owned(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, ts_rs::TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct AssStyle {
+    pub name: String,
+    pub font_name: String,
+    pub font_size: f64,
+    /// colours are `0xAABBGGRR`, where alpha 0 is opaque
+    pub primary_colour: u32,
+    pub secondary_colour: u32,
+    pub outline_colour: u32,
+    pub back_colour: u32,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strike_out: bool,
+    /// percentages
+    pub scale_x: f64,
+    pub scale_y: f64,
+    pub spacing: f64,
+    /// degrees
+    pub angle: f64,
+    /// 1 for an outline and shadow, 3 for an opaque box
+    pub border_style: i32,
+    pub outline: f64,
+    pub shadow: f64,
+    /// as on a numpad, also for SSA's `V4 Styles`
+    pub alignment: i32,
+    pub margin_l: i32,
+    pub margin_r: i32,
+    pub margin_v: i32,
+    pub encoding: i32,
+}
+
+impl AssStyle {
+    /// The defaults are those of ffmpeg's generated headers.
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            font_name: "Arial".to_owned(),
+            font_size: 16.0,
+            primary_colour: 0x00ff_ffff,
+            secondary_colour: 0x00ff_ffff,
+            outline_colour: 0,
+            back_colour: 0,
+            bold: false,
+            italic: false,
+            underline: false,
+            strike_out: false,
+            scale_x: 100.0,
+            scale_y: 100.0,
+            spacing: 0.0,
+            angle: 0.0,
+            border_style: 1,
+            outline: 1.0,
+            shadow: 0.0,
+            alignment: 2,
+            margin_l: 10,
+            margin_r: 10,
+            margin_v: 10,
+            encoding: 0,
+        }
+    }
+
+    /// Reads the values of a `Style:` line, named by `format`. Fields that
+    /// can't be read keep their defaults; unknown ones are ignored.
+    fn parse(format: &[String], values: &str, legacy: bool) -> Option<Self> {
+        let values: Vec<&str> = values.splitn(format.len(), ',').map(str::trim).collect();
+        if values.len() != format.len() {
+            return None;
+        }
+        let name = format.iter().position(|x| x == "Name").map(|i| values[i])?;
+        let mut style = Self::new(name);
+        let mut margin_t = None;
+        for (field, &value) in format.iter().zip(&values) {
+            let ok = match field.as_str() {
+                "Fontname" => {
+                    value.clone_into(&mut style.font_name);
+                    true
+                }
+                "Fontsize" => set(&mut style.font_size, parse_float(value)),
+                "PrimaryColour" => set(&mut style.primary_colour, parse_colour(value)),
+                "SecondaryColour" => set(&mut style.secondary_colour, parse_colour(value)),
+                // SSA's name for it
+                "OutlineColour" | "TertiaryColour" =>
+                    set(&mut style.outline_colour, parse_colour(value)),
+                "BackColour" => set(&mut style.back_colour, parse_colour(value)),
+                "Bold" => set(&mut style.bold, parse_bool(value)),
+                "Italic" => set(&mut style.italic, parse_bool(value)),
+                "Underline" => set(&mut style.underline, parse_bool(value)),
+                "StrikeOut" => set(&mut style.strike_out, parse_bool(value)),
+                "ScaleX" => set(&mut style.scale_x, parse_float(value)),
+                "ScaleY" => set(&mut style.scale_y, parse_float(value)),
+                "Spacing" => set(&mut style.spacing, parse_float(value)),
+                "Angle" => set(&mut style.angle, parse_float(value)),
+                "BorderStyle" => set(&mut style.border_style, parse_int(value)),
+                "Outline" => set(&mut style.outline, parse_float(value)),
+                "Shadow" => set(&mut style.shadow, parse_float(value)),
+                "Alignment" => {
+                    let alignment = parse_int(value);
+                    set(&mut style.alignment,
+                        if legacy { alignment.and_then(legacy_alignment) } else { alignment })
+                }
+                "MarginL" => set(&mut style.margin_l, parse_int(value)),
+                "MarginR" => set(&mut style.margin_r, parse_int(value)),
+                // V4++ has both vertical margins; ASS only has the one that
+                // applies to the alignment
+                "MarginV" | "MarginB" => set(&mut style.margin_v, parse_int(value)),
+                "MarginT" => {
+                    margin_t = parse_int(value);
+                    margin_t.is_some()
+                }
+                "Encoding" => set(&mut style.encoding, parse_int(value)),
+                _ => true,
+            };
+            if !ok {
+                warn!("ass: style {name}: invalid {field}: {value}");
+            }
+        }
+        if (7..=9).contains(&style.alignment)
+            && let Some(margin) = margin_t
+        {
+            style.margin_v = margin;
+        }
+        Some(style)
+    }
+}
+
+/// What a decoder's `subtitle_header` says: the script info and the styles.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, ts_rs::TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct AssHeader {
+    /// the `[Script Info]` entries, such as `PlayResX`, in order
+    pub script_info: Vec<(String, String)>,
+    pub styles: Vec<AssStyle>,
+}
+
+impl AssHeader {
+    /// Reads the `[Script Info]` and the `[V4+ Styles]`, `[V4++ Styles]` or
+    /// SSA `[V4 Styles]` sections; the rest is ignored.
+    pub fn parse(header: &str) -> Self {
+        let mut result = Self::default();
+        let mut section = "";
+        let mut format: Vec<String> = V4_PLUS_FORMAT.iter().map(|&x| x.to_owned()).collect();
+        for line in header.lines().map(str::trim) {
+            if let Some(name) = line.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+                section = name;
+                if section == "V4++ Styles" {
+                    format = V4_PLUS_PLUS_FORMAT.iter().map(|&x| x.to_owned()).collect();
+                }
+                continue;
+            }
+            let Some((key, value)) = line.split_once(':') else { continue };
+            let value = value.trim();
+            match section {
+                "Script Info" if !line.starts_with(';') =>
+                    result.script_info.push((key.trim().to_owned(), value.to_owned())),
+                "V4+ Styles" | "V4++ Styles" | "V4 Styles" => match key {
+                    "Format" => format = value.split(',').map(|x| x.trim().to_owned()).collect(),
+                    "Style" => match AssStyle::parse(&format, value, section == "V4 Styles") {
+                        Some(style) => result.styles.push(style),
+                        None => warn!("ass: invalid style: {value}"),
+                    },
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        result
+    }
+}
+
+fn set<T>(field: &mut T, value: Option<T>) -> bool {
+    value.map(|x| *field = x).is_some()
+}
+
+/// Integers, allowing for writers that put decimals in them.
+fn parse_int(s: &str) -> Option<i32> {
+    let s = s.trim();
+    s.parse().ok().or_else(|| s.parse::<f64>().ok()?.round().to_i32())
+}
+
+fn parse_float(s: &str) -> Option<f64> {
+    s.trim().parse().ok().filter(|x: &f64| x.is_finite())
+}
+
+/// `-1` is true in ASS, but anything not 0 is taken as such.
+fn parse_bool(s: &str) -> Option<bool> {
+    parse_int(s).map(|x| x != 0)
+}
+
+/// `&HAABBGGRR`, possibly without alpha or with a trailing `&`, or a decimal
+/// number as in SSA, possibly negative.
+fn parse_colour(s: &str) -> Option<u32> {
+    let s = s.trim();
+    if let Some(hex) = s.strip_prefix("&H").or_else(|| s.strip_prefix("&h")) {
+        return u32::from_str_radix(hex.trim_end_matches('&'), 16).ok();
+    }
+    let n: i64 = s.parse().ok()?;
+    // negative values are the same bits as signed
+    (n & 0xffff_ffff).to_u32()
+}
+
+/// SSA numbers alignments 1 to 3 for the bottom, adding 4 for the top and 8
+/// for the middle.
+fn legacy_alignment(x: i32) -> Option<i32> {
+    match x {
+        1..=3 => Some(x),
+        5..=7 => Some(x + 2),
+        9..=11 => Some(x - 5),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_event() {
+        let event = AssEvent::parse("12,1,Sign,Alice,0,20,30,Banner;5,{\\an8}Hi, there").unwrap();
+        assert_eq!(event, AssEvent {
+            layer: 1,
+            style: "Sign".into(),
+            actor: "Alice".into(),
+            margin_l: 0,
+            margin_r: 20,
+            margin_v: 30,
+            effect: "Banner;5".into(),
+            text: "{\\an8}Hi, there".into(),
+        });
+        assert_eq!(AssEvent::parse("0,0,Default,,0,0,0,,").unwrap().text, "");
+        assert!(AssEvent::parse("0,0,Default,,0,0,0").is_none());
+        assert!(AssEvent::parse("0,x,Default,,0,0,0,,a").is_none());
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let header = "\
+[Script Info]
+; comment
+ScriptType: v4.00+
+PlayResX: 1920
+PlayResY: 1080
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, Bold, Italic, Alignment, MarginV
+Style: Default,Noto Sans,48.5,&H80FF0000,-1,0,8,40
+Style: Bad,Arial,big,&HFFFFFF,0,0,2,10
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+";
+        let parsed = AssHeader::parse(header);
+        assert_eq!(parsed.script_info, [
+            ("ScriptType".into(), "v4.00+".into()),
+            ("PlayResX".into(), "1920".into()),
+            ("PlayResY".into(), "1080".into()),
+        ]);
+        assert_eq!(parsed.styles.len(), 2);
+        let style = &parsed.styles[0];
+        assert_eq!(style.name, "Default");
+        assert_eq!(style.font_name, "Noto Sans");
+        assert!((style.font_size - 48.5).abs() < 1e-9);
+        assert_eq!(style.primary_colour, 0x80ff_0000);
+        assert!(style.bold && !style.italic);
+        assert_eq!((style.alignment, style.margin_v), (8, 40));
+        // unlisted fields keep their defaults
+        assert_eq!(style.outline_colour, 0);
+        assert!((style.scale_x - 100.0).abs() < 1e-9);
+        // and so do those that can't be read
+        assert!((parsed.styles[1].font_size - 16.0).abs() < 1e-9);
+        assert_eq!(parsed.styles[1].primary_colour, 0x00ff_ffff);
+    }
+
+    #[test]
+    fn test_parse_ssa_styles() {
+        let header = "\
+[Script Info]
+ScriptType: v4.00
+
+[V4 Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, TertiaryColour, BackColour, Bold, Italic, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, AlphaLevel, Encoding
+Style: Top,Arial,20,16777215,65535,-16777216,0,0,0,1,2,0,6,10,10,10,0,1
+";
+        let parsed = AssHeader::parse(header);
+        let style = &parsed.styles[0];
+        assert_eq!(style.primary_colour, 0x00ff_ffff);
+        assert_eq!(style.outline_colour, 0xff00_0000);
+        // top center
+        assert_eq!(style.alignment, 8);
+        assert_eq!(style.encoding, 1);
+        assert_eq!(legacy_alignment(10), Some(5));
+        assert_eq!(legacy_alignment(4), None);
+    }
+
+    #[test]
+    fn test_parse_v4_plus_plus_styles() {
+        let header = "\
+[V4++ Styles]
+Style: Top,Arial,20,&H00FFFFFF,&H0000FFFF,&H00000000,&H80000000,0,0,0,0,100,100,0,0,1,2,0,8,10,10,30,40,1,0
+Style: Bottom,Arial,20,&H00FFFFFF,&H0000FFFF,&H00000000,&H80000000,0,0,0,0,100,100,0,0,1,2,0,2,10,10,30,40,1,0
+";
+        let parsed = AssHeader::parse(header);
+        let [top, bottom] = &parsed.styles[..] else { panic!() };
+        assert_eq!((top.alignment, top.margin_v, top.encoding), (8, 30, 1));
+        assert_eq!(top.back_colour, 0x8000_0000);
+        assert_eq!((bottom.alignment, bottom.margin_v), (2, 40));
+    }
+}
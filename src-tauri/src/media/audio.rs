@@ -6,7 +6,12 @@ use getset::{CopyGetters, Getters};
 use log::{debug, warn};
 use num_traits::ToPrimitive;
 
-use crate::media::{aggregation_tree::AggregationTree, demux, frame, internal::{check, MediaError}, units};
+use crate::media::{aggregation_tree::AggregationTree, cache, demux, disjoint_interval_set::DisjointIntervalSet, fft::Fft, fingerprint, frame, internal::{check, MediaError}, snap, units, vad};
+
+pub use crate::media::fingerprint::{align, AlignedSegment, AlignmentOptions, Fingerprint};
+pub use crate::media::snap::SnapOptions;
+pub use crate::media::sync::{estimate as estimate_sync, SyncEstimate, SyncOptions};
+pub use crate::media::vad::{SpeechDetectionOptions, SpeechSegment};
 
 #[derive(Getters, CopyGetters)]
 pub struct Decoder {
@@ -128,7 +133,10 @@ impl Decoder {
 #[enum_dispatch]
 pub enum AudioSinkKind {
     Player,
-    Sampler
+    Sampler,
+    Spectrogram,
+    SpeechDetector,
+    Fingerprinter
 }
 
 #[enum_dispatch(AudioSinkKind)]
@@ -138,8 +146,150 @@ pub trait AudioSink {
     fn process(&mut self, frame: frame::Audio) -> Result<(), MediaError>;
 }
 
-pub struct Player {
+/// Selects which source channels end up in the mono signal, for when the
+/// default downmix buries the dialogue under music and effects.
+#[derive(Clone, Debug, serde::Deserialize, ts_rs::TS)]
+#[serde(rename_all = "camelCase", tag = "type")]
+#[ts(export)]
+#[ts(rename = "AudioChannelMix")]
+pub enum ChannelMix {
+    /// front center only, where 5.1 mixes put the dialogue
+    Center,
+    /// average of front left and front right
+    FrontLeftRight,
+    /// one weight per source channel, in layout order
+    Custom { weights: Vec<f32> },
+}
+
+impl ChannelMix {
+    fn weights(&self, layout: ChannelLayout) -> Result<Vec<f32>, MediaError> {
+        use ffmpeg_sys_next::{av_channel_layout_index_from_channel, AVChannel};
+
+        let channels = layout.channels().to_usize().unwrap();
+        let index_of = |channel: AVChannel| {
+            let i = unsafe { av_channel_layout_index_from_channel(&raw const layout.0, channel) };
+            i.to_usize()
+        };
+        let select = |wanted: &[AVChannel]| {
+            let found: Vec<usize> = wanted.iter().filter_map(|&c| index_of(c)).collect();
+            let mut weights = vec![0.0; channels];
+            if found.is_empty() {
+                warn!("audio::ChannelMix: {self:?} not found in layout; using all channels");
+                weights.fill(1.0 / channels.to_f32().unwrap());
+            } else {
+                for &i in &found {
+                    weights[i] = 1.0 / found.len().to_f32().unwrap();
+                }
+            }
+            weights
+        };
+
+        match self {
+            ChannelMix::Center =>
+                Ok(select(&[AVChannel::AV_CHAN_FRONT_CENTER])),
+            ChannelMix::FrontLeftRight =>
+                Ok(select(&[AVChannel::AV_CHAN_FRONT_LEFT, AVChannel::AV_CHAN_FRONT_RIGHT])),
+            ChannelMix::Custom { weights } => {
+                if weights.len() == channels {
+                    Ok(weights.clone())
+                } else {
+                    Err(MediaError::InternalError(format!(
+                        "ChannelMix: got {} weights for {channels} channels", weights.len())))
+                }
+            },
+        }
+    }
+}
+
+/// Converts decoded frames to packed f32 at the source rate, optionally folding
+/// the channels into mono with our own weights instead of swresample's.
+struct Converter {
     resampler: resampling::Context,
+    mix_weights: Option<Vec<f32>>,
+}
+
+impl Converter {
+    /// `layout` is the output layout when not mixing.
+    fn create(
+        decoder: &Decoder, layout: ChannelLayout, mix: Option<&ChannelMix>
+    ) -> Result<Self, MediaError> {
+        let source = source_layout(decoder);
+        let mix_weights = mix.map(|m| m.weights(source)).transpose()?;
+        let output = if mix_weights.is_some() { source } else { layout };
+
+        debug!("audio::Converter::create: {} -> {} channels, mix={mix:?}",
+            source.channels(), output.channels());
+
+        let resampler = check!(software::resampler(
+            (
+                decoder.inner.format(),
+                source,
+                decoder.sample_rate()
+            ),
+            (
+                format::Sample::F32(format::sample::Type::Packed),
+                output,
+                decoder.sample_rate()
+            )
+        ))?;
+        Ok(Self { resampler, mix_weights })
+    }
+
+    fn channel_layout(&self) -> ChannelLayout {
+        if self.mix_weights.is_some() {
+            ChannelLayout::MONO
+        } else {
+            self.resampler.output().channel_layout
+        }
+    }
+
+    fn run(&mut self, input: &frame::AudioData) -> Result<frame::AudioData, MediaError> {
+        let mut processed = frame::AudioData::empty();
+        check!(self.resampler.run(input, &mut processed))?;
+
+        let Some(weights) = &self.mix_weights else {
+            return Ok(processed);
+        };
+
+        let mut mixed = frame::AudioData::new(
+            format::Sample::F32(format::sample::Type::Packed),
+            processed.samples(),
+            ChannelLayout::MONO);
+        mixed.set_rate(processed.rate());
+        mixed.set_pts(processed.pts());
+
+        let output: &mut [f32] = mixed.plane_mut(0);
+        for (out, samples) in
+            output.iter_mut().zip(interleaved(&processed).chunks_exact(weights.len()))
+        {
+            *out = samples.iter().zip(weights).map(|(x, w)| x * w).sum();
+        }
+        Ok(mixed)
+    }
+}
+
+/// Returns the interleaved samples of a packed f32 frame, across all channels.
+///
+/// `AudioData::plane` only covers `samples()` values, which is one channel's worth.
+pub fn interleaved(data: &frame::AudioData) -> &[f32] {
+    debug_assert!(data.format() == format::Sample::F32(format::sample::Type::Packed));
+    let len = data.samples() * usize::from(data.channels());
+    unsafe { std::slice::from_raw_parts(data.data(0).as_ptr().cast(), len) }
+}
+
+/// Returns the decoder's channel layout, guessing a default one if the stream
+/// doesn't specify it.
+fn source_layout(decoder: &Decoder) -> ChannelLayout {
+    let layout = decoder.inner.channel_layout();
+    if layout.is_empty() {
+        ChannelLayout::default(i32::from(decoder.inner.channels()))
+    } else {
+        layout
+    }
+}
+
+pub struct Player {
+    converter: Converter,
     frames: VecDeque<frame::Audio>
 }
 
@@ -153,49 +303,128 @@ impl AudioSink for Player {
     }
 
     fn process(&mut self, mut frame: frame::Audio) -> Result<(), MediaError> {
-        let mut processed = frame::AudioData::empty();
-        check!(self.resampler.run(&frame.decoded, &mut processed))?;
-        frame.decoded = processed;
+        frame.decoded = self.converter.run(&frame.decoded)?;
         self.frames.push_back(frame);
         Ok(())
     }
 }
 
 impl Player {
-    pub fn create(decoder: &Decoder) -> Result<Self, MediaError> {
-        // Same target as original: F32 packed, MONO, keep source rate
-        let resampler = check!(software::resampler(
-            (
-                decoder.inner.format(),
-                decoder.inner.channel_layout(),
-                decoder.sample_rate()
-            ),
-            (
-                format::Sample::F32(format::sample::Type::Packed),
-                ChannelLayout::MONO,
-                decoder.sample_rate()
-            )
-        ))?;
+    /// Creates a player outputting packed f32 at the source rate. The source
+    /// channel layout is kept unless `layout` is given; with a `mix`, the
+    /// output is mono.
+    pub fn create(
+        decoder: &Decoder, layout: Option<ChannelLayout>, mix: Option<&ChannelMix>
+    ) -> Result<Self, MediaError> {
+        let layout = layout.unwrap_or_else(|| source_layout(decoder));
         Ok(Self { 
-            resampler,
+            converter: Converter::create(decoder, layout, mix)?,
             frames: VecDeque::new()
         })
     }
 
+    pub fn channel_layout(&self) -> ChannelLayout {
+        self.converter.channel_layout()
+    }
+
     pub fn get_delta(&mut self) -> VecDeque<frame::Audio> {
         std::mem::take(&mut self.frames)
     }
 }
 
+/// Waveform statistics of a bucket of samples, aggregated in the `Sampler`'s tree.
+#[derive(Clone, Copy, Debug)]
+pub struct Envelope {
+    pub min: f32,
+    pub max: f32,
+    pub sum_squares: f32,
+    pub count: u32,
+}
+
+impl Envelope {
+    pub const EMPTY: Envelope = Envelope {
+        min: f32::INFINITY,
+        max: f32::NEG_INFINITY,
+        sum_squares: 0.0,
+        count: 0,
+    };
+
+    pub fn add(&mut self, sample: f32) {
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+        self.sum_squares += sample * sample;
+        self.count += 1;
+    }
+
+    pub fn merge(a: Envelope, b: Envelope) -> Envelope {
+        Envelope {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+            sum_squares: a.sum_squares + b.sum_squares,
+            count: a.count + b.count,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn rms(&self) -> f32 {
+        if self.is_empty() {
+            0.0
+        } else {
+            (self.sum_squares / self.count.to_f32().unwrap()).sqrt()
+        }
+    }
+}
+
 pub struct Sampler {
-    resampler: resampling::Context,
+    converter: Converter,
     start_time: units::Seconds,
     sample_per_second: usize,
-    intensities: AggregationTree<f32, fn(f32, f32) -> f32>,
+    intensities: AggregationTree<Envelope, fn(Envelope, Envelope) -> Envelope>,
+    /// indices of the buckets that have been completely sampled
+    known_range: DisjointIntervalSet<usize>,
+    pass: Pass,
 
     data: Option<SamplerDeltaData>,
 }
 
+/// Which bucket the sampler is filling since the last `clear`, and from
+/// which one on it has filled every bucket from its start. A bucket is only
+/// known once it has been filled from its start to its end in one pass.
+#[derive(Default)]
+struct Pass {
+    filling: Option<usize>,
+    complete_from: Option<usize>,
+    /// the bucket being filled is already known and was entered partway,
+    /// so it is kept as it is rather than replaced by a part of itself
+    keep: bool,
+}
+
+impl Pass {
+    /// Starts filling bucket `index`; `from_start` if none of its samples
+    /// were missed. Adds the buckets this completes to `known_range`.
+    fn begin(
+        &mut self, index: usize, from_start: bool,
+        known_range: &mut DisjointIntervalSet<usize>
+    ) {
+        let contiguous = from_start && self.filling.is_some_and(|x| x + 1 == index);
+        if contiguous && let Some(first) = self.complete_from {
+            known_range.add(first, index - 1);
+        }
+        self.complete_from = if !from_start {
+            None
+        } else if contiguous {
+            self.complete_from.or(Some(index))
+        } else {
+            Some(index)
+        };
+        self.filling = Some(index);
+        self.keep = !from_start && known_range.contains(index);
+    }
+}
+
 #[derive(Clone, serde::Serialize, Debug, ts_rs::TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
@@ -204,23 +433,38 @@ pub struct SamplerDeltaData {
     pub start_index: usize,
     pub start_time: units::Seconds,
     pub end_time: units::Seconds,
-    pub intensity: Vec<f32>,
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+    pub rms: Vec<f32>,
+}
+
+impl SamplerDeltaData {
+    fn len(&self) -> usize {
+        self.rms.len()
+    }
+
+    fn set(&mut self, index: usize, bucket: &Envelope) {
+        let i = index - self.start_index;
+        if bucket.is_empty() {
+            (self.min[i], self.max[i], self.rms[i]) = (0.0, 0.0, 0.0);
+        } else {
+            (self.min[i], self.max[i], self.rms[i]) = (bucket.min, bucket.max, bucket.rms());
+        }
+    }
+
+    fn push(&mut self, bucket: &Envelope) {
+        self.min.push(0.0);
+        self.max.push(0.0);
+        self.rms.push(0.0);
+        self.set(self.start_index + self.len() - 1, bucket);
+    }
 }
 
 impl Sampler {
-    pub fn create(decoder: &Decoder, sample_per_second: usize) -> Result<Self, MediaError> {
-        let resampler = check!(software::resampler(
-            (
-                decoder.inner.format(),
-                decoder.inner.channel_layout(),
-                decoder.sample_rate()
-            ),
-            (
-                format::Sample::F32(format::sample::Type::Packed),
-                ChannelLayout::MONO,
-                decoder.sample_rate()
-            )
-        ))?;
+    pub fn create(
+        decoder: &Decoder, sample_per_second: usize, mix: Option<&ChannelMix>
+    ) -> Result<Self, MediaError> {
+        let converter = Converter::create(decoder, ChannelLayout::MONO, mix)?;
 
         // capacity: ceil(duration_seconds) * sps
         let capacity = decoder
@@ -228,17 +472,20 @@ impl Sampler {
             .div_ceil(decoder.sample_rate() as usize)
             .saturating_mul(sample_per_second);
 
-        let intensities = AggregationTree::new(capacity, f32::max as fn(f32, f32) -> f32, f32::NAN);
+        let intensities = AggregationTree::new(capacity,
+            Envelope::merge as fn(Envelope, Envelope) -> Envelope, Envelope::EMPTY);
 
         let start_time = decoder.stream_info().start_time_seconds();
 
         debug!("audio::Sampler::create: capacity={capacity}, sps={sample_per_second}, start_time={start_time}");
 
         Ok(Self {
-            resampler,
+            converter,
             start_time,
             sample_per_second,
             intensities,
+            known_range: DisjointIntervalSet::new(),
+            pass: Pass::default(),
             data: None,
         })
     }
@@ -246,11 +493,205 @@ impl Sampler {
     pub fn get_delta(&mut self) -> Option<SamplerDeltaData> {
         std::mem::take(&mut self.data)
     }
+
+    fn time_of_index(&self, index: usize) -> units::Seconds {
+        units::Seconds(self.start_time.0
+            + index.to_f64().unwrap() / self.sample_per_second.to_f64().unwrap())
+    }
+
+    /// Everything sampled so far, one delta for each contiguous range.
+    pub fn known_deltas(&self) -> Vec<SamplerDeltaData> {
+        self.known_range.iter().map(|(first, last)| {
+            let mut data = SamplerDeltaData {
+                start_index: first,
+                start_time: self.time_of_index(first),
+                end_time: self.time_of_index(last + 1),
+                min: Vec::with_capacity(last + 1 - first),
+                max: Vec::with_capacity(last + 1 - first),
+                rms: Vec::with_capacity(last + 1 - first),
+            };
+            for index in first..=last {
+                data.push(&self.intensities.at(index));
+            }
+            data
+        }).collect()
+    }
+
+    /// Serializes the sampled buckets for `cache::store`.
+    pub fn save(&self) -> Vec<u8> {
+        let mut w = cache::Writer::default();
+        w.f64(self.start_time.0);
+        w.u64(self.sample_per_second as u64);
+        w.u64(self.intensities.length as u64);
+
+        let ranges: Vec<_> = self.known_range.iter().collect();
+        w.u64(ranges.len() as u64);
+        for (first, last) in ranges {
+            w.u64(first as u64);
+            w.u64(last as u64);
+            for index in first..=last {
+                let bucket = self.intensities.at(index);
+                w.f32(bucket.min);
+                w.f32(bucket.max);
+                w.f32(bucket.sum_squares);
+                w.u64(u64::from(bucket.count));
+            }
+        }
+        w.finish()
+    }
+
+    /// Loads what `save` produced. Returns `false` and changes nothing if the
+    /// data is malformed or was sampled with different parameters.
+    pub fn restore(&mut self, data: &[u8]) -> bool {
+        let mut r = cache::Reader::new(data);
+        let mut read = || -> Option<Vec<(usize, Vec<Envelope>)>> {
+            if r.f64()?.to_bits() != self.start_time.0.to_bits()
+                || r.u64()? != self.sample_per_second as u64
+                || r.u64()? != self.intensities.length as u64
+            {
+                return None;
+            }
+            let mut ranges = Vec::new();
+            for _ in 0..r.count()? {
+                let first = usize::try_from(r.u64()?).ok()?;
+                let last = usize::try_from(r.u64()?).ok()?;
+                if first > last || last >= self.intensities.length {
+                    return None;
+                }
+                let mut buckets = Vec::with_capacity(last + 1 - first);
+                for _ in first..=last {
+                    buckets.push(Envelope {
+                        min: r.f32()?,
+                        max: r.f32()?,
+                        sum_squares: r.f32()?,
+                        count: u32::try_from(r.u64()?).ok()?,
+                    });
+                }
+                ranges.push((first, buckets));
+            }
+            Some(ranges)
+        };
+        let Some(ranges) = read() else { return false };
+
+        for (first, buckets) in ranges {
+            self.intensities.set(&buckets, first);
+            self.known_range.add(first, first + buckets.len() - 1);
+        }
+        true
+    }
+
+    /// RMS level in dB of buckets `[first, last)`, NaN where not sampled.
+    fn levels_db(&self, first: usize, last: usize) -> Vec<f32> {
+        (first..last).map(|index| {
+            let bucket = self.intensities.at(index);
+            if self.known_range.contains(index) && !bucket.is_empty() {
+                20.0 * bucket.rms().max(1e-6).log10()
+            } else {
+                f32::NAN
+            }
+        }).collect()
+    }
+
+    /// Moves each entry's start to the nearest speech onset and its end to the
+    /// nearest offset within `options.window`, then applies the lead-in and
+    /// lead-out. Boundaries without an edge nearby stay where they are.
+    pub fn snap(
+        &self, entries: &[(units::Seconds, units::Seconds)], options: &SnapOptions
+    ) -> Vec<(units::Seconds, units::Seconds)> {
+        let sps = self.sample_per_second.to_f64().unwrap();
+        let length = self.intensities.length;
+        // negative or NaN values count as 0 (f64::max ignores NaN); nothing
+        // reaches beyond the whole stream
+        let buckets = |x: units::Seconds| (x.0 * sps).max(0.0).min(length.to_f64().unwrap());
+        let radius = buckets(options.window).ceil().to_usize().unwrap();
+        let min_run = buckets(options.min_speech).round().to_usize().unwrap().max(1);
+
+        let edge = |time: units::Seconds, onset: bool| {
+            let center = ((time.0 - self.start_time.0) * sps).round();
+            if !(center >= 0.0 && center < length.to_f64().unwrap()) {
+                return None;
+            }
+            let center = center.to_usize().unwrap();
+            let first = center.saturating_sub(radius);
+            let last = (center + radius + 1).min(length);
+            let db = self.levels_db(first, last);
+            snap::find_edge(&db, center - first, onset, min_run, options.sensitivity)
+                .map(|i| self.time_of_index(first + i))
+        };
+
+        entries.iter().map(|&(start, end)| {
+            let new_start = edge(start, true)
+                .map_or(start, |t| units::Seconds(t.0 - options.lead_in.0));
+            let new_end = edge(end, false)
+                .map_or(end, |t| units::Seconds(t.0 + options.lead_out.0));
+            if new_start < new_end { (new_start, new_end) } else { (start, end) }
+        }).collect()
+    }
+
+    /// Aggregated envelope of `[from, to)` with at least `width` points where
+    /// the sampling resolution allows, read from the tree level whose buckets
+    /// are the coarsest that still satisfy this. Unsampled buckets read as 0.
+    pub fn waveform(
+        &self, from: units::Seconds, to: units::Seconds, width: usize
+    ) -> Option<WaveformData> {
+        let sps = self.sample_per_second.to_f64().unwrap();
+        let length = self.intensities.length;
+        let first = ((from.0 - self.start_time.0) * sps).floor().max(0.0)
+            .to_usize().unwrap().min(length);
+        let last = ((to.0 - self.start_time.0) * sps).ceil().max(0.0)
+            .to_usize().unwrap().min(length);
+        if first >= last || width == 0 {
+            return None;
+        }
+
+        let max_resolution = 1usize << length.ilog2();
+        let resolution = match (last - first) / width {
+            0 => 1,
+            n => (1usize << n.ilog2()).min(max_resolution),
+        };
+        let level = self.intensities.get_level(resolution);
+        let start = first / resolution;
+        let end = last.div_ceil(resolution).min(level.len());
+
+        let mut data = WaveformData {
+            start_time: self.time_of_index(start * resolution),
+            step: units::Seconds(resolution.to_f64().unwrap() / sps),
+            min: Vec::with_capacity(end - start),
+            max: Vec::with_capacity(end - start),
+            rms: Vec::with_capacity(end - start),
+        };
+        for bucket in &level[start..end] {
+            if bucket.is_empty() {
+                data.min.push(0.0);
+                data.max.push(0.0);
+                data.rms.push(0.0);
+            } else {
+                data.min.push(bucket.min);
+                data.max.push(bucket.max);
+                data.rms.push(bucket.rms());
+            }
+        }
+        Some(data)
+    }
+}
+
+/// A stretch of the waveform envelope at some zoom level, one point per `step`.
+#[derive(Clone, serde::Serialize, Debug, ts_rs::TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+#[ts(rename = "AudioWaveformData")]
+pub struct WaveformData {
+    pub start_time: units::Seconds,
+    pub step: units::Seconds,
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+    pub rms: Vec<f32>,
 }
 
 impl AudioSink for Sampler {
     fn clear(&mut self) {
         self.data = None;
+        self.pass = Pass::default();
     }
 
     fn is_empty(&self) -> bool {
@@ -269,7 +710,7 @@ impl AudioSink for Sampler {
         }
 
         let start_index = start_index_signed.to_usize().unwrap();
-        if start_index > self.intensities.length {
+        if start_index >= self.intensities.length {
             // ignore out-of-bound data
             return Ok(());
         }
@@ -280,55 +721,329 @@ impl AudioSink for Sampler {
                 start_index,
                 start_time: frame.meta.time,
                 end_time: frame.meta.time,
-                intensity: Vec::new(),
+                min: Vec::new(),
+                max: Vec::new(),
+                rms: Vec::new(),
             });
         }
 
         let sd = self.data.as_mut().unwrap();
-        let mut processed = frame::AudioData::empty();
-        check!(self.resampler.run(&frame.decoded, &mut processed))?;
+        let processed = self.converter.run(&frame.decoded)?;
 
         let data: &[f32] = processed.plane(0);
-        let mut index = start_index;
-        let mut sum = self.intensities.at(index);
         let rate: f64 = processed.rate().into();
+        // how much of a bucket one sample takes up: the first sample of a
+        // bucket lies less than this past its start unless some were missed
+        let sample_width = sample_per_second_f64 / rate;
+        let mut index = start_index;
+
+        // a bucket is rebuilt when first touched in a pass, so that samples
+        // decoded again aren't counted twice
+        let from_start = |index: usize, position: f64|
+            position - index.to_f64().unwrap() < sample_width;
+        let mut bucket = if self.pass.filling == Some(index) {
+            self.intensities.at(index)
+        } else {
+            let position = sample_per_second_f64 * (frame.meta.time.0 - self.start_time.0);
+            self.pass.begin(index, from_start(index, position), &mut self.known_range);
+            if self.pass.keep { self.intensities.at(index) } else { Envelope::EMPTY }
+        };
 
         for (i, sample) in data.iter().enumerate() {
             let delta_time = i.to_f64().unwrap() / rate;
-            let new_index = 
-                (sample_per_second_f64 * 
-                    (frame.meta.time.0 - self.start_time.0 + delta_time))
-                .to_usize().unwrap();
+            let position = sample_per_second_f64 *
+                (frame.meta.time.0 - self.start_time.0 + delta_time);
+            let new_index = position.to_usize().unwrap();
 
             if new_index != index {
-                self.intensities.set(&[sum], index);
+                self.intensities.set(&[bucket], index);
 
-                let expected_next = sd.start_index + sd.intensity.len();
+                let expected_next = sd.start_index + sd.len();
                 if expected_next > index {
                     if index >= sd.start_index {
-                        sd.intensity[index - sd.start_index] = sum;
+                        sd.set(index, &bucket);
                     }
                 } else {
                     if expected_next < index {
                         // warn!("audio::Sampler::process: {expected_next} < {index}; filling with 0s");
                     }
-                    while sd.start_index + sd.intensity.len() < index {
-                        sd.intensity.push(0.0);
+                    while sd.start_index + sd.len() < index {
+                        sd.push(&Envelope::EMPTY);
                     }
-                    sd.intensity.push(sum);
+                    sd.push(&bucket);
                     sd.end_time = units::Seconds(frame.meta.time.0 + delta_time);
                 }
                 index = new_index;
                 if index >= self.intensities.length {
                     return Ok(());
                 }
-                sum = self.intensities.at(index);
+                self.pass.begin(index, from_start(index, position), &mut self.known_range);
+                bucket = if self.pass.keep { self.intensities.at(index) } else { Envelope::EMPTY };
+            }
+
+            if !self.pass.keep {
+                bucket.add(*sample);
             }
+        }
+
+        self.intensities.set(&[bucket], index);
+        Ok(())
+    }
+}
+
+/// Level reported for silence, also used to fill gaps in `Spectrogram` deltas.
+const SPECTROGRAM_FLOOR_DB: f32 = -120.0;
+
+/// Computes short-time FFT magnitudes of the mono signal at a fixed number of
+/// slots per second. Each slot covers `fft_size` samples starting at its time.
+pub struct Spectrogram {
+    converter: Converter,
+    fft: Fft,
+    start_time: units::Seconds,
+    slot_per_second: usize,
+    sample_rate: f64,
+
+    /// samples not yet consumed; `pending_start` is the index of the first one
+    pending: Vec<f32>,
+    pending_start: usize,
+    next_slot: usize,
+
+    data: Option<SpectrogramDeltaData>,
+}
+
+#[derive(Clone, serde::Serialize, Debug, ts_rs::TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct SpectrogramDeltaData {
+    pub start_index: usize,
+    pub start_time: units::Seconds,
+    pub end_time: units::Seconds,
+    /// number of bins per slot, evenly spaced from DC to the Nyquist frequency
+    pub bins: usize,
+    /// `bins` magnitudes in dBFS for each slot, one slot after another
+    pub magnitude: Vec<f32>,
+}
+
+impl Spectrogram {
+    pub fn create(
+        decoder: &Decoder, slot_per_second: usize, fft_size: usize,
+        mix: Option<&ChannelMix>
+    ) -> Result<Self, MediaError> {
+        if fft_size < 2 || !fft_size.is_power_of_two() {
+            return Err(MediaError::InternalError(
+                format!("audio::Spectrogram: invalid fft size {fft_size}")));
+        }
+        if slot_per_second == 0 {
+            return Err(MediaError::InternalError(
+                "audio::Spectrogram: slot_per_second must be positive".into()));
+        }
+        let converter = Converter::create(decoder, ChannelLayout::MONO, mix)?;
+        let start_time = decoder.stream_info().start_time_seconds();
+
+        debug!("audio::Spectrogram::create: sps={slot_per_second}, fft={fft_size}, start_time={start_time}");
+
+        Ok(Self {
+            converter,
+            fft: Fft::new(fft_size),
+            start_time,
+            slot_per_second,
+            sample_rate: decoder.sample_rate().into(),
+            pending: Vec::new(),
+            pending_start: 0,
+            next_slot: 0,
+            data: None,
+        })
+    }
+
+    pub fn get_delta(&mut self) -> Option<SpectrogramDeltaData> {
+        std::mem::take(&mut self.data)
+    }
+
+    fn slot_start(&self, slot: usize) -> usize {
+        (slot.to_f64().unwrap() * self.sample_rate / self.slot_per_second.to_f64().unwrap())
+            .to_usize().unwrap()
+    }
+
+    fn first_slot_from(&self, sample: usize) -> usize {
+        (sample.to_f64().unwrap() * self.slot_per_second.to_f64().unwrap() / self.sample_rate)
+            .ceil().to_usize().unwrap()
+    }
+
+    fn push_slot(&mut self, slot: usize, magnitudes: &[f32]) {
+        let bins = magnitudes.len();
+        let time = units::Seconds(self.start_time.0
+            + slot.to_f64().unwrap() / self.slot_per_second.to_f64().unwrap());
+        let sd = self.data.get_or_insert_with(|| SpectrogramDeltaData {
+            start_index: slot,
+            start_time: time,
+            end_time: time,
+            bins,
+            magnitude: Vec::new(),
+        });
+
+        let expected_next = sd.start_index + sd.magnitude.len() / bins;
+        if slot < expected_next {
+            // went backwards without a seek; keep what we already sent
+            return;
+        }
+        sd.magnitude.resize((slot - sd.start_index) * bins, SPECTROGRAM_FLOOR_DB);
+        sd.magnitude.extend(magnitudes.iter().map(|&x|
+            (20.0 * x.max(1e-6).log10()).max(SPECTROGRAM_FLOOR_DB)));
+        sd.end_time = time;
+    }
+}
+
+impl AudioSink for Spectrogram {
+    fn clear(&mut self) {
+        self.data = None;
+        self.pending.clear();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_none()
+    }
+
+    fn process(&mut self, frame: frame::Audio) -> Result<(), MediaError> {
+        let offset = frame.meta.time.0 - self.start_time.0;
+        if offset < 0.0 {
+            // ignore out-of-bound data
+            return Ok(());
+        }
+        let first = (offset * self.sample_rate).round().to_usize().unwrap();
 
-            sum = sum.max(sample.abs());
+        let processed = self.converter.run(&frame.decoded)?;
+        let samples: &[f32] = processed.plane(0);
+
+        let expected = self.pending_start + self.pending.len();
+        if self.pending.is_empty() || first.abs_diff(expected) > self.fft.size() {
+            // discontinuity, e.g. after seeking: start over from here
+            self.pending.clear();
+            self.pending_start = first;
+            self.next_slot = self.first_slot_from(first);
+        }
+        // otherwise small timestamp jitters are ignored
+        self.pending.extend_from_slice(samples);
+
+        loop {
+            let start = self.slot_start(self.next_slot);
+            let end = start + self.fft.size();
+            if end > self.pending_start + self.pending.len() {
+                break;
+            }
+            if start >= self.pending_start {
+                let window =
+                    &self.pending[start - self.pending_start..end - self.pending_start];
+                let magnitudes = self.fft.magnitudes(window);
+                self.push_slot(self.next_slot, &magnitudes);
+            }
+            self.next_slot += 1;
         }
 
-        self.intensities.set(&[sum], index);
+        let consumed = self.slot_start(self.next_slot)
+            .saturating_sub(self.pending_start)
+            .min(self.pending.len());
+        self.pending.drain(..consumed);
+        self.pending_start += consumed;
+        Ok(())
+    }
+}
+
+/// Runs voice activity detection over the mono signal.
+pub struct SpeechDetector {
+    converter: Converter,
+    detector: vad::Detector,
+    /// time up to which audio has been processed
+    position: units::Seconds,
+}
+
+impl SpeechDetector {
+    pub fn create(
+        decoder: &Decoder, options: SpeechDetectionOptions,
+        mix: Option<&ChannelMix>
+    ) -> Result<Self, MediaError> {
+        let converter = Converter::create(decoder, ChannelLayout::MONO, mix)?;
+        let detector = vad::Detector::new(decoder.sample_rate().into(), options);
+        Ok(Self {
+            converter,
+            detector,
+            position: decoder.stream_info().start_time_seconds(),
+        })
+    }
+
+    pub fn position(&self) -> units::Seconds {
+        self.position
+    }
+
+    /// Closes the open segment and returns all segments found.
+    pub fn finish(&mut self) -> Vec<SpeechSegment> {
+        self.detector.finish()
+    }
+}
+
+impl AudioSink for SpeechDetector {
+    fn clear(&mut self) {}
+
+    fn is_empty(&self) -> bool {
+        true
+    }
+
+    fn process(&mut self, frame: frame::Audio) -> Result<(), MediaError> {
+        let processed = self.converter.run(&frame.decoded)?;
+        let samples: &[f32] = processed.plane(0);
+        self.detector.feed(frame.meta.time, samples);
+
+        let rate: f64 = processed.rate().into();
+        self.position = units::Seconds(
+            frame.meta.time.0 + samples.len().to_f64().unwrap() / rate);
+        Ok(())
+    }
+}
+
+/// Computes the acoustic fingerprint of the mono signal.
+pub struct Fingerprinter {
+    converter: Converter,
+    fingerprinter: fingerprint::Fingerprinter,
+    /// time up to which audio has been processed
+    position: units::Seconds,
+}
+
+impl Fingerprinter {
+    pub fn create(
+        decoder: &Decoder, mix: Option<&ChannelMix>
+    ) -> Result<Self, MediaError> {
+        let converter = Converter::create(decoder, ChannelLayout::MONO, mix)?;
+        let fingerprinter = fingerprint::Fingerprinter::new(decoder.sample_rate().into());
+        Ok(Self {
+            converter,
+            fingerprinter,
+            position: decoder.stream_info().start_time_seconds(),
+        })
+    }
+
+    pub fn position(&self) -> units::Seconds {
+        self.position
+    }
+
+    pub fn finish(&mut self) -> Fingerprint {
+        self.fingerprinter.finish()
+    }
+}
+
+impl AudioSink for Fingerprinter {
+    fn clear(&mut self) {}
+
+    fn is_empty(&self) -> bool {
+        true
+    }
+
+    fn process(&mut self, frame: frame::Audio) -> Result<(), MediaError> {
+        let processed = self.converter.run(&frame.decoded)?;
+        let samples: &[f32] = processed.plane(0);
+        self.fingerprinter.feed(frame.meta.time, samples);
+
+        let rate: f64 = processed.rate().into();
+        self.position = units::Seconds(
+            frame.meta.time.0 + samples.len().to_f64().unwrap() / rate);
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,182 @@
+use std::{fs, path::{Path, PathBuf}, time::UNIX_EPOCH};
+
+use log::{debug, warn};
+
+use crate::media::{demux, internal::MediaError};
+
+/// Identifies the sampled state of one stream of one version of a file,
+/// produced with particular parameters. Stored in full at the head of a cache
+/// file so that hash collisions are caught on load.
+#[derive(Clone, Debug)]
+pub struct CacheKey(String);
+
+impl CacheKey {
+    /// Returns `None` if the file's modification time is not available.
+    pub fn new(
+        demuxer: &demux::Demuxer, kind: &str, stream: usize, params: &str
+    ) -> Option<Self> {
+        let mtime = demuxer.modified()?
+            .duration_since(UNIX_EPOCH).ok()?
+            .as_nanos();
+        Some(Self(format!("{}|{}|{mtime}|{kind}|{stream}|{params}",
+            demuxer.path().display(), demuxer.byte_size())))
+    }
+
+    fn file_name(&self) -> String {
+        // FNV-1a; unlike `DefaultHasher` it is stable across builds
+        let hash = self.0.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |h, b| {
+            (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        });
+        format!("{hash:016x}.samples")
+    }
+
+    fn file_path(&self, dir: &Path) -> PathBuf {
+        dir.join(self.file_name())
+    }
+}
+
+/// Reads back what was `store`d under `key`, if anything.
+pub fn load(dir: &Path, key: &CacheKey) -> Option<Vec<u8>> {
+    let compressed = fs::read(key.file_path(dir)).ok()?;
+    let data = zstd::decode_all(compressed.as_slice())
+        .inspect_err(|e| warn!("cache::load: zstd decode error {e}"))
+        .ok()?;
+
+    let mut reader = Reader::new(&data);
+    let stored_key = reader.bytes()?;
+    if stored_key != key.0.as_bytes() {
+        debug!("cache::load: key mismatch for {}", key.0);
+        return None;
+    }
+    Some(reader.rest().to_vec())
+}
+
+pub fn store(dir: &Path, key: &CacheKey, payload: &[u8]) -> Result<(), MediaError> {
+    let mut writer = Writer::default();
+    writer.bytes(key.0.as_bytes());
+    writer.0.extend_from_slice(payload);
+
+    let compressed = zstd::encode_all(writer.0.as_slice(), 0)
+        .map_err(|e| MediaError::InternalError(format!("zstd encode error {e}")))?;
+    fs::create_dir_all(dir)
+        .and_then(|()| fs::write(key.file_path(dir), &compressed))
+        .map_err(|e| MediaError::InternalError(e.to_string()))?;
+
+    debug!("cache::store: {} -> {} bytes for {}", writer.0.len(), compressed.len(), key.0);
+    Ok(())
+}
+
+/// Little-endian encoder for cache payloads.
+#[derive(Default)]
+pub struct Writer(Vec<u8>);
+
+impl Writer {
+    pub fn finish(self) -> Vec<u8> {
+        self.0
+    }
+
+    pub fn u64(&mut self, x: u64) {
+        self.0.extend(x.to_le_bytes().iter());
+    }
+
+    pub fn i64(&mut self, x: i64) {
+        self.0.extend(x.to_le_bytes().iter());
+    }
+
+    pub fn f32(&mut self, x: f32) {
+        self.0.extend(x.to_le_bytes().iter());
+    }
+
+    pub fn f64(&mut self, x: f64) {
+        self.0.extend(x.to_le_bytes().iter());
+    }
+
+    pub fn bytes(&mut self, x: &[u8]) {
+        self.u64(u64::try_from(x.len()).unwrap());
+        self.0.extend_from_slice(x);
+    }
+}
+
+/// Counterpart of `Writer`; every read returns `None` past the end.
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (head, rest) = self.data.split_first_chunk::<N>()?;
+        self.data = rest;
+        Some(*head)
+    }
+
+    pub fn u64(&mut self) -> Option<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    pub fn i64(&mut self) -> Option<i64> {
+        self.take().map(i64::from_le_bytes)
+    }
+
+    pub fn f32(&mut self) -> Option<f32> {
+        self.take().map(f32::from_le_bytes)
+    }
+
+    pub fn f64(&mut self) -> Option<f64> {
+        self.take().map(f64::from_le_bytes)
+    }
+
+    /// A length read from the data, rejected if larger than what is left
+    /// could possibly hold.
+    pub fn count(&mut self) -> Option<usize> {
+        usize::try_from(self.u64()?).ok().filter(|&n| n <= self.data.len())
+    }
+
+    pub fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.count()?;
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(head)
+    }
+
+    pub fn rest(self) -> &'a [u8] {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut w = Writer::default();
+        w.u64(7);
+        w.i64(-3);
+        w.f32(0.5);
+        w.f64(f64::NEG_INFINITY);
+        w.bytes(b"abc");
+        let data = w.finish();
+
+        let mut r = Reader::new(&data);
+        assert_eq!(r.u64(), Some(7));
+        assert_eq!(r.i64(), Some(-3));
+        assert_eq!(r.f32(), Some(0.5));
+        assert_eq!(r.f64(), Some(f64::NEG_INFINITY));
+        assert_eq!(r.bytes(), Some(&b"abc"[..]));
+        assert_eq!(r.u64(), None);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut w = Writer::default();
+        w.bytes(b"abcdef");
+        let data = w.finish();
+
+        assert_eq!(Reader::new(&data[..10]).bytes(), None);
+        assert_eq!(Reader::new(&data[..5]).u64(), None);
+    }
+}
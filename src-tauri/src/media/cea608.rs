@@ -0,0 +1,531 @@
+use log::trace;
+
+use crate::media::units::Seconds;
+
+const ROWS: usize = 15;
+const COLUMNS: usize = 32;
+
+/// Characters 0x30 to 0x3f after 0x11
+const SPECIAL: [char; 16] = [
+    '®', '°', '½', '¿', '™', '¢', '£', '♪', 'à', ' ', 'è', 'â', 'ê', 'î', 'ô', 'û',
+];
+/// Characters 0x20 to 0x3f after 0x12
+const EXTENDED_1: [char; 32] = [
+    'Á', 'É', 'Ó', 'Ú', 'Ü', 'ü', '‘', '¡', '*', '’', '—', '©', '℠', '•', '“', '”',
+    'À', 'Â', 'Ç', 'È', 'Ê', 'Ë', 'ë', 'Î', 'Ï', 'ï', 'Ô', 'Ù', 'ù', 'Û', '«', '»',
+];
+/// Characters 0x20 to 0x3f after 0x13
+const EXTENDED_2: [char; 32] = [
+    'Ã', 'ã', 'Í', 'Ì', 'ì', 'Ò', 'ò', 'Õ', 'õ', '{', '}', '\\', '^', '_', '|', '~',
+    'Ä', 'ä', 'Ö', 'ö', 'ß', '¥', '¤', '¦', 'Å', 'å', 'Ø', 'ø', '┌', '┐', '└', '┘',
+];
+
+/// The basic character set is ASCII, save for these.
+fn basic_char(c: u8) -> char {
+    match c {
+        0x2a => 'á',
+        0x5c => 'é',
+        0x5e => 'í',
+        0x5f => 'ó',
+        0x60 => 'ú',
+        0x7b => 'ç',
+        0x7c => '÷',
+        0x7d => 'Ñ',
+        0x7e => 'ñ',
+        0x7f => '█',
+        c => char::from(c),
+    }
+}
+
+/// Strips the odd parity bit, or returns `None` if it's wrong.
+fn strip_parity(b: u8) -> Option<u8> {
+    (b.count_ones() % 2 == 1).then_some(b & 0x7f)
+}
+
+/// The channels, from CC1 to CC4, that A/53 `cc_data` starts captions on.
+/// Text and XDS services don't count.
+pub fn caption_channels(data: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    data.chunks_exact(3).filter_map(|triplet| {
+        let valid = triplet[0] & 0x04 != 0;
+        let field = triplet[0] & 0x03;
+        let (c1, c2) = strip_parity(triplet[1]).zip(strip_parity(triplet[2]))?;
+        // RCL, RU2 to RU4 and RDC, which put a channel in a captioning mode
+        let starts = matches!(c1 & !0x08, 0x14 | 0x15)
+            && matches!(c2, 0x20 | 0x25..=0x27 | 0x29);
+        (valid && field < 2 && starts).then_some(field * 2 + ((c1 >> 3) & 1) + 1)
+    })
+}
+
+/// The row, from 0, that a preamble address code puts the cursor on.
+fn pac_row(c1: u8, c2: u8) -> usize {
+    let second = usize::from(c2 & 0x20 != 0);
+    match c1 {
+        0x11 => second,
+        0x12 => 2 + second,
+        0x15 => 4 + second,
+        0x16 => 6 + second,
+        0x17 => 8 + second,
+        0x10 => 10,
+        0x13 => 11 + second,
+        _ => 13 + second,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    PopOn,
+    PaintOn,
+    /// with the number of rows
+    RollUp(usize),
+    /// text service, not captions; ignored
+    Text,
+}
+
+type Memory = [[Option<char>; COLUMNS]; ROWS];
+
+fn text_of(memory: &Memory) -> String {
+    memory.iter()
+        .map(|row| row.iter().map(|c| c.unwrap_or(' ')).collect::<String>())
+        .map(|row| row.trim().to_owned())
+        .filter(|row| !row.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Caption {
+    pub start: Seconds,
+    pub end: Seconds,
+    pub text: String,
+}
+
+/// What's on screen over time, made into captions as it changes.
+#[derive(Default)]
+pub struct Screen {
+    /// the caption on screen, with when it appeared
+    shown: Option<(Seconds, String)>,
+    captions: Vec<Caption>,
+}
+
+impl Screen {
+    /// Makes `text` the caption on screen from `time`.
+    pub fn show(&mut self, time: Seconds, text: String) {
+        if let Some((start, shown)) = &mut self.shown {
+            if *shown == text {
+                return;
+            }
+            // several changes at once, as in paint-on, make one caption
+            if start.0 >= time.0 {
+                *shown = text;
+                return;
+            }
+            let (start, shown) = self.shown.take().unwrap();
+            self.captions.push(Caption { start, end: time, text: shown });
+        }
+        if !text.is_empty() {
+            self.shown = Some((time, text));
+        }
+    }
+
+    /// Returns the captions that have ended.
+    pub fn take(&mut self) -> Vec<Caption> {
+        std::mem::take(&mut self.captions)
+    }
+}
+
+/// Decodes one channel of EIA/CEA-608 line 21 captions into timed text,
+/// handling pop-on, roll-up and paint-on captions. Styles are dropped.
+pub struct Decoder {
+    /// 0 for field 1, 1 for field 2
+    field: u8,
+    /// 0 for the first data channel of the field, 1 for the second
+    channel: u8,
+    /// the data channel the last control code was for
+    current_channel: u8,
+    /// control codes are sent twice; the second one is dropped
+    last_control: Option<(u8, u8)>,
+
+    mode: Mode,
+    displayed: Memory,
+    non_displayed: Memory,
+    row: usize,
+    column: usize,
+    /// when the row being written in roll-up mode got its first character
+    line_start: Option<Seconds>,
+
+    screen: Screen,
+}
+
+impl Decoder {
+    /// `cc` is from 1 to 4, CC1 and CC2 being in field 1 and CC3 and CC4 in
+    /// field 2.
+    pub fn new(cc: u8) -> Self {
+        let cc = cc.clamp(1, 4) - 1;
+        Self {
+            field: cc / 2,
+            channel: cc % 2,
+            current_channel: 0,
+            last_control: None,
+            mode: Mode::PopOn,
+            displayed: [[None; COLUMNS]; ROWS],
+            non_displayed: [[None; COLUMNS]; ROWS],
+            row: ROWS - 1,
+            column: 0,
+            line_start: None,
+            screen: Screen::default(),
+        }
+    }
+
+    /// Reads `cc_data` in the A/53 format, as in `AV_FRAME_DATA_A53_CC`:
+    /// triplets of a flag byte and two bytes of data, all shown at `time`.
+    pub fn feed_a53(&mut self, time: Seconds, data: &[u8]) {
+        for triplet in data.chunks_exact(3) {
+            let valid = triplet[0] & 0x04 != 0;
+            let cc_type = triplet[0] & 0x03;
+            // types 2 and 3 are CEA-708; see `cea708`
+            if valid && cc_type == self.field {
+                self.feed(time, triplet[1], triplet[2]);
+            }
+        }
+    }
+
+    /// Reads a byte pair of this decoder's field, parity bits included.
+    pub fn feed(&mut self, time: Seconds, b1: u8, b2: u8) {
+        let (Some(c1), Some(c2)) = (strip_parity(b1), strip_parity(b2)) else {
+            trace!("cea608: parity error at {time:?}");
+            return;
+        };
+        if c1 == 0 && c2 == 0 {
+            return;
+        }
+
+        if (0x10..0x20).contains(&c1) {
+            if self.last_control == Some((c1, c2)) {
+                self.last_control = None;
+                return;
+            }
+            self.last_control = Some((c1, c2));
+            self.current_channel = (c1 >> 3) & 1;
+            if self.current_channel == self.channel {
+                self.control(time, c1 & !0x08, c2);
+            }
+            return;
+        }
+        self.last_control = None;
+        if self.current_channel != self.channel || c1 < 0x20 {
+            return;
+        }
+        self.write(time, basic_char(c1));
+        if c2 >= 0x20 {
+            self.write(time, basic_char(c2));
+        }
+    }
+
+    /// Ends the caption on screen at `time`, and returns the captions so far.
+    pub fn finish(&mut self, time: Seconds) -> Vec<Caption> {
+        if let Mode::RollUp(_) = self.mode {
+            self.commit_roll_up();
+        }
+        self.show(time, String::new());
+        self.take()
+    }
+
+    /// Returns the captions that have ended.
+    pub fn take(&mut self) -> Vec<Caption> {
+        self.screen.take()
+    }
+
+    fn control(&mut self, time: Seconds, c1: u8, c2: u8) {
+        match (c1, c2) {
+            // miscellaneous control codes; 0x15 is used in field 2
+            (0x14 | 0x15, 0x20..=0x2f) => self.command(time, c2),
+            // tab offsets
+            (0x17, 0x21..=0x23) => {
+                self.column = (self.column + usize::from(c2 - 0x20)).min(COLUMNS - 1);
+            }
+            // mid-row codes, which show as a space
+            (0x11, 0x20..=0x2f) => self.write(time, ' '),
+            (0x11, 0x30..=0x3f) => self.write(time, SPECIAL[usize::from(c2 - 0x30)]),
+            // extended characters replace the standard one sent before them,
+            // for decoders that don't know them
+            (0x12, 0x20..=0x3f) => {
+                self.backspace();
+                self.write(time, EXTENDED_1[usize::from(c2 - 0x20)]);
+            }
+            (0x13, 0x20..=0x3f) => {
+                self.backspace();
+                self.write(time, EXTENDED_2[usize::from(c2 - 0x20)]);
+            }
+            (_, 0x40..=0x7f) => self.preamble(pac_row(c1, c2), c2),
+            // background and foreground attributes
+            _ => {}
+        }
+    }
+
+    fn command(&mut self, time: Seconds, c2: u8) {
+        match c2 {
+            // RCL
+            0x20 => self.set_mode(Mode::PopOn),
+            // BS
+            0x21 => self.backspace(),
+            // DER
+            0x24 => {
+                let (row, column) = (self.row, self.column);
+                self.target()[row][column..].fill(None);
+            }
+            // RU2, RU3, RU4
+            0x25..=0x27 => self.set_mode(Mode::RollUp(usize::from(c2 - 0x23))),
+            // RDC
+            0x29 => self.set_mode(Mode::PaintOn),
+            // TR, RTD
+            0x2a | 0x2b => self.set_mode(Mode::Text),
+            // EDM
+            0x2c => {
+                if let Mode::RollUp(_) = self.mode {
+                    self.commit_roll_up();
+                }
+                self.displayed = [[None; COLUMNS]; ROWS];
+                self.show(time, String::new());
+            }
+            // CR
+            0x2d => self.carriage_return(),
+            // ENM
+            0x2e => self.non_displayed = [[None; COLUMNS]; ROWS],
+            // EOC
+            0x2f => {
+                std::mem::swap(&mut self.displayed, &mut self.non_displayed);
+                self.show(time, text_of(&self.displayed));
+                self.set_mode(Mode::PopOn);
+            }
+            // AOF, AON, FON
+            _ => {}
+        }
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        if mode == self.mode {
+            return;
+        }
+        match (self.mode, mode) {
+            (Mode::RollUp(_), Mode::RollUp(_)) => {}
+            (_, Mode::RollUp(_)) => {
+                self.displayed = [[None; COLUMNS]; ROWS];
+                self.non_displayed = [[None; COLUMNS]; ROWS];
+                self.row = ROWS - 1;
+                self.column = 0;
+                self.line_start = None;
+            }
+            (Mode::RollUp(_), _) => self.commit_roll_up(),
+            _ => {}
+        }
+        self.mode = mode;
+    }
+
+    fn preamble(&mut self, row: usize, c2: u8) {
+        if let Mode::RollUp(rows) = self.mode
+            && row != self.row
+        {
+            // the window moves along with its contents
+            let old = self.displayed;
+            self.displayed = [[None; COLUMNS]; ROWS];
+            for i in 0..rows.min(row + 1).min(self.row + 1) {
+                self.displayed[row - i] = old[self.row - i];
+            }
+        }
+        self.row = row;
+        // bit 4 means an indent, in multiples of 4 columns
+        self.column = if c2 & 0x10 != 0 { usize::from((c2 & 0x0e) >> 1) * 4 } else { 0 };
+    }
+
+    fn carriage_return(&mut self) {
+        let Mode::RollUp(rows) = self.mode else { return };
+        self.commit_roll_up();
+        let top = self.row + 1 - rows.min(self.row + 1);
+        for i in 0..ROWS {
+            self.displayed[i] = if (top..self.row).contains(&i) {
+                self.displayed[i + 1]
+            } else {
+                [None; COLUMNS]
+            };
+        }
+        self.column = 0;
+    }
+
+    /// Puts the rows in the roll-up window on screen, from when the last one
+    /// started.
+    fn commit_roll_up(&mut self) {
+        if let Some(start) = self.line_start.take() {
+            self.show(start, text_of(&self.displayed));
+        }
+    }
+
+    fn target(&mut self) -> &mut Memory {
+        match self.mode {
+            Mode::PopOn | Mode::Text => &mut self.non_displayed,
+            Mode::PaintOn | Mode::RollUp(_) => &mut self.displayed,
+        }
+    }
+
+    fn write(&mut self, time: Seconds, c: char) {
+        if self.mode == Mode::Text {
+            return;
+        }
+        let (row, column) = (self.row, self.column);
+        self.target()[row][column] = Some(c);
+        self.column = (column + 1).min(COLUMNS - 1);
+        match self.mode {
+            Mode::RollUp(_) => { self.line_start.get_or_insert(time); }
+            Mode::PaintOn => self.show(time, text_of(&self.displayed)),
+            _ => {}
+        }
+    }
+
+    fn backspace(&mut self) {
+        if self.column > 0 {
+            self.column -= 1;
+            let (row, column) = (self.row, self.column);
+            self.target()[row][column] = None;
+        }
+    }
+
+    fn show(&mut self, time: Seconds, text: String) {
+        self.screen.show(time, text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Adds the odd parity bit.
+    fn parity(b: u8) -> u8 {
+        if b.count_ones().is_multiple_of(2) { b | 0x80 } else { b }
+    }
+
+    struct Feeder {
+        decoder: Decoder,
+        time: f64,
+    }
+
+    impl Feeder {
+        fn new() -> Self {
+            Self { decoder: Decoder::new(1), time: 0.0 }
+        }
+
+        /// One byte pair per frame at 30 fps, and control codes twice.
+        fn pair(&mut self, c1: u8, c2: u8) {
+            let data = [0xfc, parity(c1), parity(c2)];
+            self.decoder.feed_a53(Seconds(self.time), &data);
+            self.time += 1.0 / 30.0;
+        }
+
+        fn control(&mut self, c1: u8, c2: u8) {
+            self.pair(c1, c2);
+            self.pair(c1, c2);
+        }
+
+        fn text(&mut self, text: &str) {
+            for pair in text.as_bytes().chunks(2) {
+                self.pair(pair[0], pair.get(1).copied().unwrap_or(0));
+            }
+        }
+
+        fn wait(&mut self, seconds: f64) {
+            self.time += seconds;
+        }
+    }
+
+    fn texts(captions: &[Caption]) -> Vec<&str> {
+        captions.iter().map(|c| c.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_caption_channels() {
+        // padding, a CEA-708 packet, EDM in field 1, and RCL for CC4
+        let data = [0xfc, 0x80, 0x80, 0xfe, 0x03, 0x20, 0xfc, 0x94, 0x2c, 0xfd, 0x9d, 0x20];
+        assert_eq!(caption_channels(&data).collect::<Vec<_>>(), [4]);
+        // RU2 for CC2, but flagged invalid
+        assert_eq!(caption_channels(&[0xf8, 0x1c, 0x25]).count(), 0);
+        assert_eq!(caption_channels(&[0xfc, 0x1c, 0x25]).collect::<Vec<_>>(), [2]);
+    }
+
+    #[test]
+    fn test_pop_on() {
+        let mut f = Feeder::new();
+        // RCL, ENM, PAC row 14, text, PAC row 15, text, EOC
+        f.control(0x14, 0x20);
+        f.control(0x14, 0x2e);
+        f.control(0x14, 0x40);
+        f.text("Hello,");
+        f.control(0x14, 0x60);
+        f.text("world!");
+        let shown = f.time;
+        f.control(0x14, 0x2f);
+        f.wait(2.0);
+        // EDM
+        let cleared = f.time;
+        f.control(0x14, 0x2c);
+        let captions = f.decoder.finish(Seconds(10.0));
+        assert_eq!(texts(&captions), ["Hello,\nworld!"]);
+        assert!((captions[0].start.0 - shown).abs() < 1e-9);
+        assert!((captions[0].end.0 - cleared).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_pop_on_replaced() {
+        let mut f = Feeder::new();
+        for text in ["one", "two"] {
+            f.control(0x14, 0x20);
+            f.control(0x14, 0x2e);
+            f.control(0x14, 0x60);
+            f.text(text);
+            f.control(0x14, 0x2f);
+            f.wait(1.0);
+        }
+        let captions = f.decoder.finish(Seconds(10.0));
+        assert_eq!(texts(&captions), ["one", "two"]);
+        assert_eq!(captions[0].end, captions[1].start);
+        assert_eq!(captions[1].end, Seconds(10.0));
+    }
+
+    #[test]
+    fn test_roll_up() {
+        let mut f = Feeder::new();
+        // RU2, PAC row 15
+        f.control(0x14, 0x25);
+        f.control(0x14, 0x60);
+        for line in ["first", "second", "third"] {
+            f.text(line);
+            f.control(0x14, 0x2d);
+            f.wait(1.0);
+        }
+        let captions = f.decoder.finish(Seconds(10.0));
+        assert_eq!(texts(&captions), ["first", "first\nsecond", "second\nthird"]);
+        assert_eq!(captions[0].end, captions[1].start);
+    }
+
+    #[test]
+    fn test_characters() {
+        let mut f = Feeder::new();
+        f.control(0x14, 0x29);
+        f.control(0x14, 0x60);
+        // é from the basic set, ♪ as a special character, and Ü replacing U
+        f.text("\x5c");
+        f.control(0x11, 0x37);
+        f.text("U");
+        f.control(0x12, 0x24);
+        let captions = f.decoder.finish(Seconds(10.0));
+        assert_eq!(texts(&captions).last(), Some(&"é♪Ü"));
+    }
+
+    #[test]
+    fn test_other_channel_ignored() {
+        let mut f = Feeder::new();
+        // CC2's RCL, then its text
+        f.control(0x1c, 0x20);
+        f.control(0x1c, 0x2e);
+        f.text("other");
+        f.control(0x1c, 0x2f);
+        assert!(f.decoder.finish(Seconds(10.0)).is_empty());
+    }
+}
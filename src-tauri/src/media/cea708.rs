@@ -0,0 +1,455 @@
+use log::trace;
+
+use crate::media::cea608::{Caption, Screen};
+use crate::media::units::Seconds;
+
+const WINDOWS: usize = 8;
+/// The most rows and columns a window can have
+const ROWS: usize = 15;
+const COLUMNS: usize = 42;
+
+/// The size of a DTVCC packet, header included, from its header.
+fn packet_size(header: u8) -> usize {
+    match header & 0x3f {
+        0 => 128,
+        n => usize::from(n) * 2,
+    }
+}
+
+/// How many bytes of parameters follow a C0 code.
+fn c0_length(c: u8) -> usize {
+    match c {
+        0x10..=0x17 => 1,
+        0x18..=0x1f => 2,
+        _ => 0,
+    }
+}
+
+/// How many bytes of parameters follow a C1 command.
+fn c1_length(c: u8) -> usize {
+    match c {
+        0x88..=0x8d => 1,
+        0x90 | 0x92 => 2,
+        0x91 => 3,
+        0x97 => 4,
+        0x98..=0x9f => 6,
+        _ => 0,
+    }
+}
+
+/// How many bytes follow a code after EXT1; those from 0x90 to 0x9f tell in
+/// the byte after them, `next`.
+fn ext1_length(c: u8, next: Option<u8>) -> usize {
+    match c {
+        0x08..=0x0f => 1,
+        0x10..=0x17 => 2,
+        0x18..=0x1f => 3,
+        0x80..=0x87 => 4,
+        0x88..=0x8f => 5,
+        0x90..=0x9f => next.map_or(0, |n| usize::from(n & 0x3f) + 1),
+        _ => 0,
+    }
+}
+
+/// The G2 characters, after EXT1; the rest are unassigned.
+fn g2_char(c: u8) -> Option<char> {
+    Some(match c {
+        0x20 | 0x21 => ' ',
+        0x25 => '…',
+        0x2a => 'Š',
+        0x2c => 'Œ',
+        0x30 => '█',
+        0x31 => '‘',
+        0x32 => '’',
+        0x33 => '“',
+        0x34 => '”',
+        0x35 => '•',
+        0x39 => '™',
+        0x3a => 'š',
+        0x3c => 'œ',
+        0x3d => '℠',
+        0x3f => 'Ÿ',
+        0x76 => '⅛',
+        0x77 => '⅜',
+        0x78 => '⅝',
+        0x79 => '⅞',
+        0x7a => '│',
+        0x7b => '┐',
+        0x7c => '└',
+        0x7d => '─',
+        0x7e => '┘',
+        0x7f => '┌',
+        _ => return None,
+    })
+}
+
+/// Puts DTVCC packets back together from A/53 `cc_data`, and splits them
+/// into service blocks.
+#[derive(Default)]
+pub struct Packets {
+    buffer: Vec<u8>,
+}
+
+impl Packets {
+    /// Reads `cc_data` in the A/53 format, calling `block` with the service
+    /// number and the data of each service block in the packets it ends.
+    pub fn feed_a53(&mut self, data: &[u8], mut block: impl FnMut(u8, &[u8])) {
+        for triplet in data.chunks_exact(3) {
+            let valid = triplet[0] & 0x04 != 0;
+            match triplet[0] & 0x03 {
+                // DTVCC_PACKET_START
+                3 if valid => {
+                    self.end(&mut block);
+                    self.buffer.extend_from_slice(&triplet[1..]);
+                }
+                // DTVCC_PACKET_DATA
+                2 if valid && !self.buffer.is_empty() =>
+                    self.buffer.extend_from_slice(&triplet[1..]),
+                _ => continue,
+            }
+            if self.buffer.len() >= packet_size(self.buffer[0]) {
+                self.end(&mut block);
+            }
+        }
+    }
+
+    /// Splits up the packet so far, even if it's short of its size.
+    fn end(&mut self, block: &mut impl FnMut(u8, &[u8])) {
+        let packet = std::mem::take(&mut self.buffer);
+        let Some(&header) = packet.first() else { return };
+        let packet = &packet[..packet_size(header).min(packet.len())];
+        let mut i = 1;
+        while let Some(&header) = packet.get(i) {
+            let mut service = header >> 5;
+            let size = usize::from(header & 0x1f);
+            i += 1;
+            // a null block header; the rest is padding
+            if service == 0 {
+                break;
+            }
+            if service == 7 {
+                let Some(&extended) = packet.get(i) else { break };
+                service = extended & 0x3f;
+                i += 1;
+            }
+            let end = (i + size).min(packet.len());
+            block(service, &packet[i..end]);
+            i = end;
+        }
+    }
+}
+
+type Memory = [[Option<char>; COLUMNS]; ROWS];
+
+#[derive(Clone)]
+struct Window {
+    visible: bool,
+    /// the vertical anchor, by which windows are ordered on screen
+    anchor: u8,
+    rows: usize,
+    row: usize,
+    column: usize,
+    text: Memory,
+}
+
+impl Window {
+    fn new() -> Self {
+        Self {
+            visible: false,
+            anchor: 0,
+            rows: 1,
+            row: 0,
+            column: 0,
+            text: [[None; COLUMNS]; ROWS],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.text = [[None; COLUMNS]; ROWS];
+        self.row = 0;
+        self.column = 0;
+    }
+
+    fn text(&self) -> String {
+        self.text.iter()
+            .map(|row| row.iter().map(|c| c.unwrap_or(' ')).collect::<String>())
+            .map(|row| row.trim().to_owned())
+            .filter(|row| !row.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Decodes one service of CEA-708 (DTVCC) captions into timed text. The text
+/// of the windows on screen is joined from top to bottom; pens, colours and
+/// positions are dropped, and delays are not waited for.
+pub struct Decoder {
+    service: u8,
+    packets: Packets,
+    windows: [Option<Window>; WINDOWS],
+    current: usize,
+    /// when text started to be written into a visible window without having
+    /// been shown; it is shown at the end of the row, or of the text
+    pending: Option<Seconds>,
+    screen: Screen,
+}
+
+impl Decoder {
+    /// `service` is from 1 to 63, 1 being the primary caption service.
+    pub fn new(service: u8) -> Self {
+        Self {
+            service,
+            packets: Packets::default(),
+            windows: Default::default(),
+            current: 0,
+            pending: None,
+            screen: Screen::default(),
+        }
+    }
+
+    /// Reads `cc_data` in the A/53 format, as in `AV_FRAME_DATA_A53_CC`; the
+    /// packets that end in it are shown at `time`.
+    pub fn feed_a53(&mut self, time: Seconds, data: &[u8]) {
+        let service = self.service;
+        let mut blocks = Vec::new();
+        self.packets.feed_a53(data, |s, block| {
+            if s == service {
+                blocks.push(block.to_vec());
+            }
+        });
+        for block in blocks {
+            self.block(time, &block);
+        }
+    }
+
+    /// Ends the caption on screen at `time`, and returns the captions so far.
+    pub fn finish(&mut self, time: Seconds) -> Vec<Caption> {
+        self.flush();
+        self.screen.show(time, String::new());
+        self.take()
+    }
+
+    /// Returns the captions that have ended.
+    pub fn take(&mut self) -> Vec<Caption> {
+        self.screen.take()
+    }
+
+    fn block(&mut self, time: Seconds, data: &[u8]) {
+        let mut i = 0;
+        while let Some(&c) = data.get(i) {
+            i += 1;
+            i += match c {
+                // EXT1
+                0x10 => {
+                    let Some(&c) = data.get(i) else { break };
+                    i += 1;
+                    if let Some(g2) = g2_char(c) {
+                        self.write(time, g2);
+                    }
+                    ext1_length(c, data.get(i).copied())
+                }
+                0x00..=0x1f => {
+                    self.c0(time, c);
+                    c0_length(c)
+                }
+                0x7f => {
+                    self.write(time, '♪');
+                    0
+                }
+                0x80..=0x9f => {
+                    let length = c1_length(c);
+                    match data.get(i..i + length) {
+                        Some(parameters) => self.c1(time, c, parameters),
+                        None => trace!("cea708: {c:#x} cut short at {time:?}"),
+                    }
+                    length
+                }
+                // G0 is ASCII and G1 is Latin-1
+                c => {
+                    self.write(time, char::from(c));
+                    0
+                }
+            };
+        }
+    }
+
+    fn c0(&mut self, time: Seconds, c: u8) {
+        // ETX
+        if c == 0x03 {
+            return self.flush();
+        }
+        let current = self.current;
+        let Some(window) = &mut self.windows[current] else { return };
+        let visible = window.visible;
+        match c {
+            // BS
+            0x08 => {
+                if window.column > 0 {
+                    window.column -= 1;
+                    window.text[window.row][window.column] = None;
+                }
+            }
+            // FF
+            0x0c => {
+                self.flush();
+                self.windows[current].as_mut().unwrap().clear();
+                return self.update(time);
+            }
+            // CR, which scrolls when at the last row
+            0x0d => {
+                self.flush();
+                let window = self.windows[current].as_mut().unwrap();
+                if window.row + 1 < window.rows {
+                    window.row += 1;
+                } else {
+                    window.text[..window.rows].rotate_left(1);
+                    window.text[window.rows - 1] = [None; COLUMNS];
+                }
+                window.column = 0;
+                return;
+            }
+            // HCR
+            0x0e => {
+                window.text[window.row] = [None; COLUMNS];
+                window.column = 0;
+            }
+            _ => return,
+        }
+        if visible {
+            self.pending.get_or_insert(time);
+        }
+    }
+
+    fn c1(&mut self, time: Seconds, c: u8, parameters: &[u8]) {
+        match c {
+            // CW0 to CW7
+            0x80..=0x87 => self.current = usize::from(c - 0x80),
+            // CLW, DSW, HDW, TGW, DLW
+            0x88..=0x8c => {
+                self.flush();
+                for (i, window) in self.windows.iter_mut().enumerate() {
+                    if parameters[0] & (1 << i) == 0 {
+                        continue;
+                    }
+                    match (c, window.as_mut()) {
+                        (0x8c, _) => *window = None,
+                        (_, None) => {}
+                        (0x88, Some(w)) => w.clear(),
+                        (0x89, Some(w)) => w.visible = true,
+                        (0x8a, Some(w)) => w.visible = false,
+                        (_, Some(w)) => w.visible = !w.visible,
+                    }
+                }
+                self.update(time);
+            }
+            // RST
+            0x8f => {
+                self.flush();
+                self.windows = Default::default();
+                self.update(time);
+            }
+            // SPL
+            0x92 => {
+                if let Some(window) = &mut self.windows[self.current] {
+                    window.row = usize::from(parameters[0] & 0x0f).min(ROWS - 1);
+                    window.column = usize::from(parameters[1] & 0x3f).min(COLUMNS - 1);
+                }
+            }
+            // DF0 to DF7, which also make the window current
+            0x98..=0x9f => {
+                self.flush();
+                self.current = usize::from(c - 0x98);
+                let window = self.windows[self.current].get_or_insert_with(Window::new);
+                window.visible = parameters[0] & 0x20 != 0;
+                window.anchor = parameters[1] & 0x7f;
+                window.rows = usize::from(parameters[3] & 0x0f) + 1;
+                window.row = window.row.min(window.rows - 1);
+                self.update(time);
+            }
+            // DLY, DLC, SPA, SPC and SWA; delays and styles
+            _ => {}
+        }
+    }
+
+    fn write(&mut self, time: Seconds, c: char) {
+        let Some(window) = &mut self.windows[self.current] else { return };
+        window.text[window.row][window.column] = Some(c);
+        window.column = (window.column + 1).min(COLUMNS - 1);
+        if window.visible {
+            self.pending.get_or_insert(time);
+        }
+    }
+
+    /// The text of the visible windows, from top to bottom.
+    fn text(&self) -> String {
+        let mut windows: Vec<_> = self.windows.iter().flatten()
+            .filter(|w| w.visible)
+            .collect();
+        windows.sort_by_key(|w| w.anchor);
+        windows.iter()
+            .map(|w| w.text())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Shows what has been written on screen since it was started.
+    fn flush(&mut self) {
+        if let Some(start) = self.pending.take() {
+            let text = self.text();
+            self.screen.show(start, text);
+        }
+    }
+
+    fn update(&mut self, time: Seconds) {
+        let text = self.text();
+        self.screen.show(time, text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends `data` as one service 1 block in a packet, two bytes a triplet.
+    fn packet(data: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0, 0x20 | u8::try_from(data.len()).unwrap()];
+        packet.extend_from_slice(data);
+        if packet.len() % 2 == 1 {
+            packet.push(0);
+        }
+        packet[0] = u8::try_from(packet.len() / 2).unwrap();
+        packet.chunks(2).enumerate()
+            .flat_map(|(i, pair)| [if i == 0 { 0xff } else { 0xfe }, pair[0], pair[1]])
+            .collect()
+    }
+
+    #[test]
+    fn test_pop_on() {
+        let mut decoder = Decoder::new(1);
+        // DF0, hidden with 2 rows, then text and a row below it
+        decoder.feed_a53(Seconds(0.0), &packet(
+            &[0x98, 0x00, 0x40, 0x00, 0x01, 0x1f, 0x00, b'O', b'n', b'e', 0x0d, b'T', b'w', b'o']));
+        // TGW for window 0 shows it, and again hides it
+        decoder.feed_a53(Seconds(1.0), &packet(&[0x8b, 0x01]));
+        decoder.feed_a53(Seconds(3.0), &packet(&[0x8b, 0x01]));
+        let captions = decoder.finish(Seconds(10.0));
+        assert_eq!(captions, [Caption {
+            start: Seconds(1.0), end: Seconds(3.0), text: "One\nTwo".to_owned()
+        }]);
+    }
+
+    #[test]
+    fn test_roll_up() {
+        let mut decoder = Decoder::new(1);
+        // DF0, visible with 2 rows
+        decoder.feed_a53(Seconds(0.0), &packet(&[0x98, 0x20, 0x40, 0x00, 0x01, 0x1f, 0x00]));
+        for (time, line) in [(0.0, "first"), (1.0, "second"), (2.0, "third")] {
+            let mut data = line.as_bytes().to_vec();
+            data.push(0x0d);
+            decoder.feed_a53(Seconds(time), &packet(&data));
+        }
+        let texts: Vec<_> = decoder.finish(Seconds(10.0)).into_iter().map(|c| c.text).collect();
+        assert_eq!(texts, ["first", "first\nsecond", "second\nthird"]);
+    }
+}
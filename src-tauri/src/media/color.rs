@@ -0,0 +1,201 @@
+use std::ffi::c_int;
+
+use ffmpeg::{color, format, software::scaling};
+use ffmpeg_sys_next::{sws_getCoefficients, sws_setColorspaceDetails};
+use log::debug;
+use num_traits::ToPrimitive;
+
+use crate::media::{frame, internal::{check, MediaError}, tonemap::{Tonemapper, Transfer}};
+
+// `SWS_CS_*` from swscale.h
+const SWS_CS_ITU709: c_int = 1;
+const SWS_CS_FCC: c_int = 4;
+const SWS_CS_ITU601: c_int = 5;
+const SWS_CS_SMPTE240M: c_int = 7;
+const SWS_CS_BT2020: c_int = 9;
+
+/// What a frame says about its colors, as far as converting it to RGB goes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Colorimetry {
+    pub space: color::Space,
+    pub range: color::Range,
+    pub primaries: color::Primaries,
+    pub transfer: color::TransferCharacteristic,
+}
+
+impl Colorimetry {
+    pub fn of(frame: &frame::VideoData) -> Self {
+        Self {
+            space: frame.color_space(),
+            range: frame.color_range(),
+            primaries: frame.color_primaries(),
+            transfer: frame.color_transfer_characteristic(),
+        }
+    }
+
+    pub fn of_decoder(decoder: &ffmpeg::decoder::Video) -> Self {
+        Self {
+            space: decoder.color_space(),
+            range: decoder.color_range(),
+            primaries: decoder.color_primaries(),
+            transfer: decoder.color_transfer_characteristic(),
+        }
+    }
+
+    fn hdr_transfer(&self) -> Option<Transfer> {
+        match self.transfer {
+            color::TransferCharacteristic::SMPTE2084 => Some(Transfer::Pq),
+            color::TransferCharacteristic::ARIB_STD_B67 => Some(Transfer::Hlg),
+            _ => None,
+        }
+    }
+
+    /// The YUV matrix; untagged video is taken as BT.709 if it's HD and
+    /// BT.601 otherwise, as most players do.
+    fn sws_colorspace(&self, height: u32) -> c_int {
+        match self.space {
+            color::Space::BT709 => SWS_CS_ITU709,
+            color::Space::FCC => SWS_CS_FCC,
+            color::Space::BT470BG | color::Space::SMPTE170M => SWS_CS_ITU601,
+            color::Space::SMPTE240M => SWS_CS_SMPTE240M,
+            color::Space::BT2020NCL | color::Space::BT2020CL => SWS_CS_BT2020,
+            _ if height >= 720 => SWS_CS_ITU709,
+            _ => SWS_CS_ITU601,
+        }
+    }
+
+    fn is_full_range(&self, format: format::Pixel) -> bool {
+        self.range == color::Range::JPEG || matches!(format,
+            format::Pixel::YUVJ420P | format::Pixel::YUVJ422P
+            | format::Pixel::YUVJ444P | format::Pixel::YUVJ440P | format::Pixel::YUVJ411P)
+    }
+}
+
+/// Converts frames to RGBA of a given size, following their colorspace and
+/// range and tonemapping HDR to SDR BT.709. Adapts to the frames as they
+/// come, should their format or colorimetry change midway.
+pub struct RgbaConverter {
+    input_format: format::Pixel,
+    input_size: (u32, u32),
+    colorimetry: Colorimetry,
+    output_size: (u32, u32),
+    flags: scaling::Flags,
+
+    scaler: scaling::Context,
+    /// HDR frames are scaled to 16-bit RGB first, then tonemapped with this
+    tonemapper: Option<Tonemapper>,
+}
+
+impl RgbaConverter {
+    pub fn new(
+        format: format::Pixel, size: (u32, u32), colorimetry: Colorimetry,
+        output_size: (u32, u32), flags: scaling::Flags,
+    ) -> Result<Self, MediaError> {
+        let (scaler, tonemapper) =
+            Self::create_scaler(format, size, colorimetry, output_size, flags)?;
+        Ok(Self {
+            input_format: format,
+            input_size: size,
+            colorimetry,
+            output_size,
+            flags,
+            scaler,
+            tonemapper,
+        })
+    }
+
+    pub fn output_size(&self) -> (u32, u32) {
+        self.output_size
+    }
+
+    pub fn input_format(&self) -> format::Pixel {
+        self.input_format
+    }
+
+    pub fn set_output_size(&mut self, size: (u32, u32)) -> Result<(), MediaError> {
+        self.output_size = size;
+        self.recreate()
+    }
+
+    fn recreate(&mut self) -> Result<(), MediaError> {
+        (self.scaler, self.tonemapper) = Self::create_scaler(
+            self.input_format, self.input_size, self.colorimetry,
+            self.output_size, self.flags)?;
+        Ok(())
+    }
+
+    fn create_scaler(
+        format: format::Pixel, size: (u32, u32), colorimetry: Colorimetry,
+        output_size: (u32, u32), flags: scaling::Flags,
+    ) -> Result<(scaling::Context, Option<Tonemapper>), MediaError> {
+        let tonemapper = colorimetry.hdr_transfer().map(|transfer| {
+            debug!("RgbaConverter: tonemapping {:?}, {:?}", transfer, colorimetry.primaries);
+            Tonemapper::new(transfer, colorimetry.primaries == color::Primaries::BT2020)
+        });
+        let output_format =
+            if tonemapper.is_some() { format::Pixel::RGBA64LE } else { format::Pixel::RGBA };
+        let mut scaler = check!(scaling::Context::get(
+            format, size.0, size.1,
+            output_format, output_size.0, output_size.1,
+            flags,
+        ))?;
+
+        let coefficients = unsafe { sws_getCoefficients(colorimetry.sws_colorspace(size.1)) };
+        let result = unsafe {
+            sws_setColorspaceDetails(
+                scaler.as_mut_ptr(),
+                coefficients, c_int::from(colorimetry.is_full_range(format)),
+                // the output is RGB, so this matrix isn't used; its range is full
+                coefficients, 1,
+                0, 1 << 16, 1 << 16)
+        };
+        if result < 0 {
+            // e.g. RGB input, which has nothing to set
+            debug!("RgbaConverter: colorspace details not supported for {format:?}");
+        }
+        Ok((scaler, tonemapper))
+    }
+
+    pub fn run(
+        &mut self, input: &frame::VideoData, output: &mut frame::VideoData
+    ) -> Result<(), MediaError> {
+        let format = input.format();
+        let size = (input.width(), input.height());
+        let colorimetry = Colorimetry::of(input);
+        if format != self.input_format || size != self.input_size
+            || colorimetry != self.colorimetry
+        {
+            debug!("RgbaConverter: input changed to {format:?} {size:?}, {colorimetry:?}");
+            self.input_format = format;
+            self.input_size = size;
+            self.colorimetry = colorimetry;
+            self.recreate()?;
+        }
+
+        let Some(tonemapper) = &self.tonemapper else {
+            return check!(self.scaler.run(input, output));
+        };
+        let mut wide = frame::VideoData::empty();
+        check!(self.scaler.run(input, &mut wide))?;
+
+        let (w, h) = self.output_size;
+        *output = frame::VideoData::new(format::Pixel::RGBA, w, h);
+        let (in_stride, out_stride) = (wide.stride(0), output.stride(0));
+        let row = w.to_usize().unwrap();
+        let wide = wide.data(0);
+        for (y, out) in output.data_mut(0).chunks_mut(out_stride)
+            .take(h.to_usize().unwrap()).enumerate()
+        {
+            let line = &wide[y * in_stride..];
+            for x in 0..row {
+                let c = |i: usize| {
+                    let at = x * 8 + i * 2;
+                    u16::from_le_bytes([line[at], line[at + 1]])
+                };
+                let [r, g, b] = tonemapper.map([c(0), c(1), c(2)]);
+                out[x * 4..x * 4 + 4].copy_from_slice(&[r, g, b, 255]);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+
+use ffmpeg::{error::EAGAIN, filter, format};
+use ffmpeg_sys_next::AVPixelFormat;
+use log::{debug, warn};
+
+use crate::media::{frame, internal::{check, MediaError}};
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, ts_rs::TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub enum DeinterlaceMode {
+    Off,
+    /// every frame, whatever it says about itself
+    On,
+    /// only frames flagged as interlaced, starting from the first of them
+    Auto,
+}
+
+struct Graph {
+    inner: filter::Graph,
+    format: format::Pixel,
+    size: (u32, u32),
+}
+
+impl Graph {
+    fn new(
+        mode: DeinterlaceMode, format: format::Pixel, size: (u32, u32)
+    ) -> Result<Self, MediaError> {
+        // bwdif looks better, but isn't in every build
+        let name = if filter::find("bwdif").is_some() { "bwdif" } else { "yadif" };
+        let deint = if mode == DeinterlaceMode::Auto { "interlaced" } else { "all" };
+        debug!("deinterlacer: {name} on {format:?} {size:?}, deint={deint}");
+
+        let mut inner = filter::Graph::new();
+        let args = format!(
+            "video_size={}x{}:pix_fmt={}:time_base=1/1:pixel_aspect=1/1",
+            size.0, size.1, AVPixelFormat::from(format) as i32);
+        let buffer = filter::find("buffer")
+            .ok_or(MediaError::InternalError("filter not found: buffer".into()))?;
+        let buffersink = filter::find("buffersink")
+            .ok_or(MediaError::InternalError("filter not found: buffersink".into()))?;
+        check!(inner.add(&buffer, "in", &args))?;
+        check!(inner.add(&buffersink, "out", ""))?;
+        // one frame out for each frame in, so that timing stays as it is
+        let spec = format!("{name}=mode=send_frame:parity=auto:deint={deint}");
+        check!(check!(check!(inner.output("in", 0))?.input("out", 0))?.parse(&spec))?;
+        check!(inner.validate())?;
+        Ok(Self { inner, format, size })
+    }
+}
+
+/// Runs frames through a deinterlacing filter. The filter holds on to each
+/// frame until it sees the next, so frames come out one late, and the last
+/// one only with `finish`. They come out with the metadata they went in with.
+pub struct Deinterlacer {
+    mode: DeinterlaceMode,
+    graph: Option<Graph>,
+    /// frames in the filter, by the sequence number they were given as pts,
+    /// along with the tag they were pushed with
+    pending: VecDeque<(i64, frame::FrameMetadata, bool)>,
+    sequence: i64,
+}
+
+impl Deinterlacer {
+    pub fn new(mode: DeinterlaceMode) -> Self {
+        Self {
+            mode,
+            graph: None,
+            pending: VecDeque::new(),
+            sequence: 0,
+        }
+    }
+
+    pub fn mode(&self) -> DeinterlaceMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: DeinterlaceMode) {
+        self.mode = mode;
+        self.reset();
+    }
+
+    /// Drops the frames in the filter, e.g. because of a seek.
+    pub fn reset(&mut self) {
+        self.graph = None;
+        self.pending.clear();
+    }
+
+    /// Puts a frame in and returns those that come out, each with the `tag`
+    /// it was put in with.
+    pub fn push(&mut self, mut frame: frame::Video, tag: bool)
+        -> Result<Vec<(frame::Video, bool)>, MediaError>
+    {
+        let needed = match self.mode {
+            DeinterlaceMode::Off => false,
+            DeinterlaceMode::On => true,
+            DeinterlaceMode::Auto => self.graph.is_some() || frame.decoded.is_interlaced(),
+        };
+        if !needed {
+            return Ok(vec![(frame, tag)]);
+        }
+
+        let format = frame.decoded.format();
+        let size = (frame.decoded.width(), frame.decoded.height());
+        let mut out = Vec::new();
+        if self.graph.as_ref().is_none_or(|g| g.format != format || g.size != size) {
+            out = self.finish()?;
+            self.graph = Some(Graph::new(self.mode, format, size)?);
+        }
+        let graph = &mut self.graph.as_mut().unwrap().inner;
+
+        frame.decoded.set_pts(Some(self.sequence));
+        self.pending.push_back((self.sequence, frame.meta, tag));
+        self.sequence += 1;
+        check!(graph.get("in").unwrap().source().add(&frame.decoded))?;
+        self.drain(&mut out)?;
+        Ok(out)
+    }
+
+    /// Gets the frames still in the filter out.
+    pub fn finish(&mut self) -> Result<Vec<(frame::Video, bool)>, MediaError> {
+        let mut out = Vec::new();
+        let Some(graph) = self.graph.as_mut() else { return Ok(out) };
+        check!(graph.inner.get("in").unwrap().source().flush())?;
+        self.drain(&mut out)?;
+        self.reset();
+        Ok(out)
+    }
+
+    fn drain(&mut self, out: &mut Vec<(frame::Video, bool)>) -> Result<(), MediaError> {
+        let graph = &mut self.graph.as_mut().unwrap().inner;
+        loop {
+            let mut filtered = frame::VideoData::empty();
+            match graph.get("out").unwrap().sink().frame(&mut filtered) {
+                Ok(()) => {}
+                Err(ffmpeg::Error::Other { errno: EAGAIN } | ffmpeg::Error::Eof) =>
+                    return Ok(()),
+                error => check!(error)?,
+            }
+            let sequence = filtered.pts();
+            let mut found = None;
+            while let Some((s, meta, tag)) = self.pending.pop_front() {
+                if Some(s) == sequence {
+                    found = Some((meta, tag));
+                    break;
+                }
+            }
+            let Some((meta, tag)) = found else {
+                warn!("deinterlacer: unexpected frame with pts {sequence:?}");
+                continue;
+            };
+            out.push((frame::Video { meta, decoded: filtered }, tag));
+        }
+    }
+}
@@ -32,6 +32,19 @@ pub struct StreamDescription {
     codec_id: Option<String>
 }
 
+impl StreamDescription {
+    /// A closed caption service carried in a video stream, as a subtitle
+    /// stream at `index`; `codec_id` says which, as in `eia_608 CC1`.
+    pub fn closed_captions(index: usize, codec_id: String) -> Self {
+        Self {
+            r#type: SerializableStreamKind::Subtitle,
+            index,
+            language_code: "--".to_owned(),
+            codec_id: Some(codec_id),
+        }
+    }
+}
+
 #[derive(Clone, Copy, CopyGetters)]
 pub struct StreamInfo {
     #[getset(get_copy = "pub")]
@@ -55,6 +68,7 @@ impl StreamInfo {
 
 pub struct Demuxer {
     input: Box<format::context::Input>,
+    path: std::path::PathBuf,
     metadata: fs::Metadata,
 }
 
@@ -63,17 +77,36 @@ impl Demuxer {
         let input = Box::new(check!(format::input(&path))?);
         let metadata = fs::metadata(path)
             .map_err(|e| MediaError::InternalError(e.to_string()))?;
-        Ok(Demuxer { input, metadata })
+        Ok(Demuxer { input, path: path.to_owned(), metadata })
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
     }
 
     pub fn byte_size(&self) -> u64 {
         self.metadata.len()
     }
 
+    pub fn modified(&self) -> Option<std::time::SystemTime> {
+        self.metadata.modified().ok()
+    }
+
     pub fn duration(&self) -> units::Seconds {
         units::Timestamp(self.input.duration()).to_seconds(units::DEFAULT_TIMEBASE)
     }
 
+    pub fn stream_count(&self) -> usize {
+        self.input.streams().count()
+    }
+
+    pub fn stream_indices(&self, kind: StreamKind) -> Vec<usize> {
+        self.input.streams()
+            .filter(|s| s.parameters().medium() == kind)
+            .map(|s| s.index())
+            .collect()
+    }
+
     pub fn describe_streams(&self) -> Vec<StreamDescription> {
         let mut streams = Vec::<StreamDescription>::new();
         for stream in self.input.streams() {
@@ -34,7 +34,11 @@ where
         self.map.insert(left, right);
     }
 
-    #[expect(unused)]
+    /// The intervals in ascending order, as inclusive `(left, right)` pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (T, T)> + '_ {
+        self.map.iter().map(|(&a, &b)| (a, b))
+    }
+
     pub fn contains(&self, point: T) -> bool {
         self.contains_range(point, point)
     }
@@ -0,0 +1,138 @@
+use std::f32::consts::PI;
+
+use num_traits::ToPrimitive;
+
+/// A radix-2 FFT of a fixed size, with its twiddle factors and window precomputed.
+pub struct Fft {
+    size: usize,
+    /// `(cos, -sin)` of `2πk/size` for `k < size / 2`
+    twiddles: Vec<(f32, f32)>,
+    /// Hann window, used by `magnitudes`
+    window: Vec<f32>,
+    window_sum: f32,
+}
+
+impl Fft {
+    /// # Panics
+    ///
+    /// Panics if `size` is not a power of two greater than 1.
+    pub fn new(size: usize) -> Self {
+        assert!(size > 1 && size.is_power_of_two(), "FFT size must be a power of 2");
+
+        let n = size.to_f32().unwrap();
+        let twiddles = (0..size / 2)
+            .map(|k| {
+                let angle = 2.0 * PI * k.to_f32().unwrap() / n;
+                (angle.cos(), -angle.sin())
+            })
+            .collect();
+        let window: Vec<f32> = (0..size)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i.to_f32().unwrap() / n).cos())
+            .collect();
+        let window_sum = window.iter().sum();
+
+        Self { size, twiddles, window, window_sum }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// In-place forward transform of complex values stored as `(re, im)`.
+    pub fn transform(&self, buf: &mut [(f32, f32)]) {
+        debug_assert_eq!(buf.len(), self.size);
+
+        // bit-reversal permutation
+        let bits = self.size.trailing_zeros();
+        for i in 0..self.size {
+            let j = i.reverse_bits() >> (usize::BITS - bits);
+            if i < j {
+                buf.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= self.size {
+            let stride = self.size / len;
+            for start in (0..self.size).step_by(len) {
+                for k in 0..len / 2 {
+                    let (wr, wi) = self.twiddles[k * stride];
+                    let (ar, ai) = buf[start + k];
+                    let (br, bi) = buf[start + k + len / 2];
+                    let (tr, ti) = (br * wr - bi * wi, br * wi + bi * wr);
+                    buf[start + k] = (ar + tr, ai + ti);
+                    buf[start + k + len / 2] = (ar - tr, ai - ti);
+                }
+            }
+            len *= 2;
+        }
+    }
+
+    /// Hann-windowed amplitude spectrum of real input, `size / 2` bins from DC
+    /// upwards. A full-scale sine gives about 1.0 in its bin.
+    pub fn magnitudes(&self, input: &[f32]) -> Vec<f32> {
+        debug_assert_eq!(input.len(), self.size);
+
+        let mut buf: Vec<(f32, f32)> = input.iter()
+            .zip(&self.window)
+            .map(|(x, w)| (x * w, 0.0))
+            .collect();
+        self.transform(&mut buf);
+
+        let scale = 2.0 / self.window_sum;
+        buf[..self.size / 2].iter()
+            .map(|(re, im)| re.hypot(*im) * scale)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(size: usize, cycles: f32, amplitude: f32) -> Vec<f32> {
+        (0..size)
+            .map(|i| amplitude * (2.0 * PI * cycles * i.to_f32().unwrap()
+                / size.to_f32().unwrap()).sin())
+            .collect()
+    }
+
+    #[test]
+    fn test_transform_matches_dft() {
+        let input: Vec<(f32, f32)> = (0..16)
+            .map(|i| { let x = i.to_f32().unwrap(); (x.sin() + 0.3 * x, (0.7 * x).cos()) })
+            .collect();
+
+        let mut fast = input.clone();
+        Fft::new(16).transform(&mut fast);
+
+        for (k, &(re, im)) in fast.iter().enumerate() {
+            let (mut er, mut ei) = (0.0, 0.0);
+            for (n, &(xr, xi)) in input.iter().enumerate() {
+                let angle = -2.0 * PI * (k * n).to_f32().unwrap() / 16.0;
+                er += xr * angle.cos() - xi * angle.sin();
+                ei += xr * angle.sin() + xi * angle.cos();
+            }
+            assert!((re - er).abs() < 1e-3 && (im - ei).abs() < 1e-3, "bin {k}");
+        }
+    }
+
+    #[test]
+    fn test_sine_peaks_in_its_bin() {
+        let fft = Fft::new(256);
+        let mags = fft.magnitudes(&sine(256, 20.0, 0.5));
+
+        assert_eq!(mags.len(), 128);
+        let peak = mags.iter().enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1)).unwrap().0;
+        assert_eq!(peak, 20);
+        assert!((mags[20] - 0.5).abs() < 0.01);
+        assert!(mags[60] < 1e-3);
+    }
+
+    #[test]
+    fn test_silence() {
+        let fft = Fft::new(64);
+        assert!(fft.magnitudes(&[0.0; 64]).iter().all(|&x| x == 0.0));
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+
+use num_traits::ToPrimitive;
+
+use crate::media::{fft::Fft, units};
+
+/// The signal is decimated to about this rate before analysis.
+const ANALYSIS_RATE: f64 = 5500.0;
+/// Frames span about 0.37 s and start every 1/8 of that.
+const FRAME_SIZE: usize = 2048;
+const HOP: usize = 256;
+/// 33 bands give the 32 bits of each print.
+const BANDS: usize = 33;
+const BAND_RANGE: (f64, f64) = (300.0, 2000.0);
+/// Forward jumps longer than this are not filled in with silence.
+const MAX_GAP_SECONDS: f64 = 10.0;
+
+/// Block matches with more differing bits than this are rejected.
+const MAX_BIT_ERROR_RATE: f64 = 0.35;
+
+/// Per-frame 32-bit prints in the manner of Haitsma and Kalker: each bit is
+/// the sign of the change over time of the energy difference between two
+/// adjacent frequency bands. Robust to re-encoding and level changes.
+pub struct Fingerprint {
+    pub start_time: f64,
+    /// time between consecutive prints
+    pub step: f64,
+    pub prints: Vec<u32>,
+}
+
+pub struct Fingerprinter {
+    fft: Fft,
+    factor: usize,
+    rate: f64,
+    /// FFT bin where each band starts; the last entry ends the last band
+    band_edges: Vec<usize>,
+
+    /// running sum and count of the input samples being decimated
+    accumulator: (f32, usize),
+    /// decimated samples not yet consumed
+    pending: Vec<f32>,
+    /// time of the next input sample expected
+    next_time: Option<f64>,
+    previous: Option<Vec<f32>>,
+    fingerprint: Fingerprint,
+}
+
+impl Fingerprinter {
+    pub fn new(sample_rate: f64) -> Self {
+        let factor = (sample_rate / ANALYSIS_RATE).floor().to_usize().unwrap().max(1);
+        let rate = sample_rate / factor.to_f64().unwrap();
+        let (lo, hi) = BAND_RANGE;
+        let band_edges = (0..=BANDS)
+            .map(|i| {
+                let freq = lo * (hi / lo).powf(i.to_f64().unwrap() / BANDS.to_f64().unwrap());
+                (freq * FRAME_SIZE.to_f64().unwrap() / rate).round().to_usize().unwrap()
+                    .clamp(1, FRAME_SIZE / 2)
+            })
+            .collect();
+        Self {
+            fft: Fft::new(FRAME_SIZE),
+            factor,
+            rate,
+            band_edges,
+            accumulator: (0.0, 0),
+            pending: Vec::new(),
+            next_time: None,
+            previous: None,
+            fingerprint: Fingerprint {
+                start_time: 0.0,
+                step: HOP.to_f64().unwrap() / rate,
+                prints: Vec::new(),
+            },
+        }
+    }
+
+    /// Feeds samples starting at `time`. Gaps are filled with silence and
+    /// overlaps are dropped, so that print `i` is always at
+    /// `start_time + i * step`. Jumps forward longer than `MAX_GAP_SECONDS`
+    /// are taken as continuous.
+    pub fn feed(&mut self, time: units::Seconds, samples: &[f32]) {
+        let input_step = 1.0 / (self.rate * self.factor.to_f64().unwrap());
+        let mut samples = samples;
+        let start = match self.next_time {
+            None => {
+                self.fingerprint.start_time = time.0;
+                time.0
+            }
+            Some(expected) => {
+                let gap = time.0 - expected;
+                if gap > self.fingerprint.step && gap < MAX_GAP_SECONDS {
+                    let missing = (gap / input_step).round().to_usize().unwrap();
+                    self.decimate(std::iter::repeat_n(0.0, missing));
+                    expected + missing.to_f64().unwrap() * input_step
+                } else {
+                    if gap < -self.fingerprint.step {
+                        let overlap = (-gap / input_step).round().to_usize().unwrap();
+                        samples = &samples[overlap.min(samples.len())..];
+                    }
+                    // otherwise only jitter
+                    expected
+                }
+            }
+        };
+        self.decimate(samples.iter().copied());
+        self.next_time = Some(start + samples.len().to_f64().unwrap() * input_step);
+        self.analyze();
+    }
+
+    fn decimate(&mut self, samples: impl Iterator<Item = f32>) {
+        for x in samples {
+            self.accumulator.0 += x;
+            self.accumulator.1 += 1;
+            if self.accumulator.1 == self.factor {
+                self.pending.push(self.accumulator.0 / self.factor.to_f32().unwrap());
+                self.accumulator = (0.0, 0);
+            }
+        }
+    }
+
+    fn analyze(&mut self) {
+        let mut consumed = 0;
+        while self.pending.len() - consumed >= FRAME_SIZE {
+            let magnitudes = self.fft.magnitudes(&self.pending[consumed..consumed + FRAME_SIZE]);
+            let energies: Vec<f32> = self.band_edges.windows(2)
+                .map(|w| magnitudes[w[0]..w[1].max(w[0] + 1)].iter().map(|x| x * x).sum())
+                .collect();
+
+            if let Some(previous) = &self.previous {
+                let mut print = 0u32;
+                for b in 0..BANDS - 1 {
+                    let now = energies[b] - energies[b + 1];
+                    let before = previous[b] - previous[b + 1];
+                    if now - before > 0.0 {
+                        print |= 1 << b;
+                    }
+                }
+                self.fingerprint.prints.push(print);
+            } else {
+                // the first frame only serves as `previous`; shift the start
+                // so that print `i` still belongs to frame `i + 1`
+                self.fingerprint.start_time += self.fingerprint.step;
+            }
+            self.previous = Some(energies);
+            consumed += HOP;
+        }
+        self.pending.drain(..consumed);
+    }
+
+    pub fn finish(&mut self) -> Fingerprint {
+        std::mem::replace(&mut self.fingerprint, Fingerprint {
+            start_time: 0.0,
+            step: HOP.to_f64().unwrap() / self.rate,
+            prints: Vec::new(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize, ts_rs::TS)]
+#[serde(rename_all = "camelCase", default)]
+#[ts(export)]
+pub struct AlignmentOptions {
+    /// length of the pieces matched independently; cuts shorter than this
+    /// may go unnoticed
+    pub block: units::Seconds,
+}
+
+impl Default for AlignmentOptions {
+    fn default() -> Self {
+        Self { block: units::Seconds(3.0) }
+    }
+}
+
+/// Within `[start, end)` of the first file, time `t` corresponds to
+/// `t + offset` in the second.
+#[derive(Clone, serde::Serialize, Debug, ts_rs::TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct AlignedSegment {
+    pub start: units::Seconds,
+    pub end: units::Seconds,
+    pub offset: units::Seconds,
+    /// fraction of matching print bits, from 0.5 (chance) to 1
+    pub similarity: f64,
+}
+
+fn bit_errors(a: &[u32], b: &[u32], i: usize, offset: isize) -> Option<u32> {
+    let j = i.checked_add_signed(offset)?;
+    Some((a[i] ^ *b.get(j)?).count_ones())
+}
+
+/// What leaving a print without a counterpart costs when placing boundaries;
+/// more than a match usually differs by, less than unrelated prints (16).
+const UNMATCHED_BIT_ERRORS: u32 = 11;
+
+/// The index in `[lo, hi]` that minimizes the cost of everything before it
+/// under `left` plus everything from it under `right`.
+fn best_split(
+    lo: usize, hi: usize, left: impl Fn(usize) -> u32, right: impl Fn(usize) -> u32
+) -> usize {
+    let mut total: u32 = (lo..hi).map(&right).sum();
+    let mut best = (total, lo);
+    for i in lo..hi {
+        total = total - right(i) + left(i);
+        if total < best.0 {
+            best = (total, i + 1);
+        }
+    }
+    best.1
+}
+
+/// Mean bit error rate of `a[range]` against `b` shifted by `offset`, or
+/// `None` if less than half of the range has a counterpart.
+fn bit_error_rate(a: &[u32], b: &[u32], range: std::ops::Range<usize>, offset: isize) -> Option<f64> {
+    let len = range.len();
+    let (sum, count) = range
+        .filter_map(|i| bit_errors(a, b, i, offset))
+        .fold((0, 0), |(s, c), e| (s + e, c + 1));
+    (count * 2 >= len && count > 0)
+        .then(|| f64::from(sum) / (32.0 * count.to_f64().unwrap()))
+}
+
+/// Piecewise mapping from the timeline of `a` to that of `b`. Pieces of `a`
+/// with no counterpart in `b` are left out.
+pub fn align(a: &Fingerprint, b: &Fingerprint, options: &AlignmentOptions) -> Vec<AlignedSegment> {
+    let block = (options.block.0 / a.step).round().to_usize().unwrap().max(8);
+    let (pa, pb) = (&a.prints[..], &b.prints[..]);
+
+    let mut index: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (j, &print) in pb.iter().enumerate() {
+        // silence gives all zeros, which says nothing about the position
+        if print != 0 {
+            index.entry(print).or_default().push(j);
+        }
+    }
+
+    // offset in prints for each block, if it matched
+    let blocks: Vec<Option<(isize, f64)>> = (0..pa.len().div_ceil(block)).map(|k| {
+        let range = k * block..((k + 1) * block).min(pa.len());
+        let mut votes: HashMap<isize, usize> = HashMap::new();
+        for i in range.clone() {
+            // prints rarely survive re-encoding intact, so also try all those
+            // one bit away
+            let variants = std::iter::once(pa[i]).chain((0..32).map(|k| pa[i] ^ (1 << k)));
+            for print in variants {
+                for &j in index.get(&print).map_or(&[][..], |x| &x[..]) {
+                    *votes.entry(j.to_isize().unwrap() - i.to_isize().unwrap()).or_default() += 1;
+                }
+            }
+        }
+        let mut candidates: Vec<_> = votes.into_iter().filter(|x| x.1 >= 2).collect();
+        candidates.sort_by_key(|x| std::cmp::Reverse(x.1));
+        candidates.iter().take(5)
+            .filter_map(|&(offset, _)| Some((offset, bit_error_rate(pa, pb, range.clone(), offset)?)))
+            .filter(|x| x.1 < MAX_BIT_ERROR_RATE)
+            .min_by(|x, y| x.1.total_cmp(&y.1))
+    }).collect();
+
+    // merge runs of blocks with the same offset: (first index, end index, offset)
+    let mut runs: Vec<(usize, usize, isize)> = Vec::new();
+    for (k, matched) in blocks.iter().enumerate() {
+        let Some((offset, _)) = matched else { continue };
+        let range = (k * block, ((k + 1) * block).min(pa.len()));
+        match runs.last_mut() {
+            Some(last) if last.1 == range.0 && last.2.abs_diff(*offset) <= 1 => last.1 = range.1,
+            _ => runs.push((range.0, range.1, *offset)),
+        }
+    }
+
+    // move each boundary to where the switch from one offset to the other,
+    // or to no match at all, fits best
+    let cost = |i: usize, offset: Option<isize>| match offset {
+        Some(offset) => bit_errors(pa, pb, i, offset).unwrap_or(32),
+        None => UNMATCHED_BIT_ERRORS,
+    };
+    let mut boundaries = Vec::new();
+    for r in 0..=runs.len() {
+        let left = r.checked_sub(1).map(|r| runs[r]);
+        let right = runs.get(r).copied();
+        let (lo, hi) = match (left, right) {
+            (Some(l), Some(r)) if l.1 == r.0 =>
+                (l.0.max(l.1.saturating_sub(block)), r.1.min(r.0 + block)),
+            _ => {
+                if let Some(l) = left {
+                    boundaries.push((r - 1, false, l.0.max(l.1.saturating_sub(block)), l.1));
+                }
+                if let Some(r2) = right {
+                    boundaries.push((r, true, r2.0, r2.1.min(r2.0 + block)));
+                }
+                continue;
+            }
+        };
+        let (l, r2) = (left.unwrap(), right.unwrap());
+        let split = best_split(lo, hi, |i| cost(i, Some(l.2)), |i| cost(i, Some(r2.2)));
+        runs[r - 1].1 = split;
+        runs[r].0 = split;
+    }
+    // ends that border on nothing
+    for (r, is_start, lo, hi) in boundaries {
+        let offset = runs[r].2;
+        if is_start {
+            runs[r].0 = best_split(lo, hi, |i| cost(i, None), |i| cost(i, Some(offset)));
+        } else {
+            runs[r].1 = best_split(lo, hi, |i| cost(i, Some(offset)), |i| cost(i, None));
+        }
+    }
+
+    runs.into_iter()
+        .filter(|r| r.1 > r.0)
+        .map(|(first, end, offset)| {
+            let time_a = |i: usize| a.start_time + i.to_f64().unwrap() * a.step;
+            let similarity = 1.0 - bit_error_rate(pa, pb, first..end, offset).unwrap_or(0.5);
+            AlignedSegment {
+                start: units::Seconds(time_a(first)),
+                end: units::Seconds(time_a(end)),
+                offset: units::Seconds(b.start_time
+                    + offset.to_f64().unwrap() * b.step - a.start_time),
+                similarity,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::testing::Random;
+
+    fn prints(prints: Vec<u32>) -> Fingerprint {
+        Fingerprint { start_time: 0.0, step: 0.1, prints }
+    }
+
+    #[test]
+    fn test_align_with_cut_and_insertion() {
+        let mut random = Random(1);
+        let b: Vec<u32> = (0..3000).map(|_| random.next()).collect();
+        // drop b[1000..1300], put 500 unrelated prints in its place, and
+        // flip a few bits everywhere
+        let mut a: Vec<u32> = b[..1000].to_vec();
+        a.extend((0..500).map(|_| random.next()));
+        a.extend_from_slice(&b[1300..]);
+        for (i, x) in a.iter_mut().enumerate() {
+            *x ^= 1 << (random.next() % 32);
+            if i % 3 == 0 {
+                *x ^= (1 << (random.next() % 32)) | (1 << (random.next() % 32));
+            }
+        }
+
+        let segments = align(&prints(a), &prints(b), &AlignmentOptions::default());
+        assert_eq!(segments.len(), 2, "{segments:?}");
+        let (first, second) = (&segments[0], &segments[1]);
+        assert!(first.start.0.abs() < 1e-9 && (first.end.0 - 100.0).abs() < 0.5, "{first:?}");
+        assert!(first.offset.0.abs() < 1e-9, "{first:?}");
+        assert!((second.start.0 - 150.0).abs() < 0.5 && (second.end.0 - 320.0).abs() < 1e-6, "{second:?}");
+        assert!((second.offset.0 + 20.0).abs() < 1e-6, "{second:?}");
+        assert!(second.similarity > 0.9, "{second:?}");
+    }
+
+    #[test]
+    fn test_fingerprint_offset() {
+        const RATE: f64 = 48000.0;
+        // noise with a wandering spectral tilt and level
+        let noise = Random(3).noise(48000 * 30, 1.0);
+        let mut state = 0.0;
+        let signal: Vec<f32> = noise.iter().enumerate().map(|(i, &x)| {
+            let mix = 0.5 + 0.5 * (i.to_f32().unwrap() / 7000.0).sin();
+            state = mix * state + (1.0 - mix) * x;
+            state * (0.2 + 0.1 * (i.to_f32().unwrap() / 20000.0).cos())
+        }).collect();
+
+        let lead = 59_232; // 1.234 s
+        let fingerprint = |samples: &[f32]| {
+            let mut f = Fingerprinter::new(RATE);
+            for (k, chunk) in samples.chunks(1000).enumerate() {
+                f.feed(units::Seconds((k * 1000).to_f64().unwrap() / RATE), chunk);
+            }
+            f.finish()
+        };
+        let a = fingerprint(&signal[lead..]);
+        let b = fingerprint(&signal);
+
+        let segments = align(&a, &b, &AlignmentOptions::default());
+        assert_eq!(segments.len(), 1, "{segments:?}");
+        assert!((segments[0].offset.0 - 1.234).abs() < a.step, "{segments:?}");
+        assert!(segments[0].similarity > 0.8, "{segments:?}");
+    }
+}
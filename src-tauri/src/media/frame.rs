@@ -7,6 +7,7 @@ pub enum Frame {
     Video(Video)
 }
 
+#[derive(Clone, Copy)]
 pub struct FrameMetadata {
     pub byte_pos: isize,
     pub pkt_pos: i64,
@@ -18,6 +19,7 @@ pub struct Audio {
     pub decoded: AudioData,
 }
 
+#[derive(Clone)]
 pub struct Video {
     pub meta: FrameMetadata,
     pub decoded: VideoData,
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+use crate::media::units::Timestamp;
+
+struct Entry<T> {
+    item: T,
+    bytes: usize,
+    /// when the frame after this one starts, if it has been seen
+    next: Option<Timestamp>,
+    last_used: u64,
+}
+
+/// Least-recently-used cache of frames keyed by presentation time, bounded by
+/// the total size of what it holds. Frames inserted one after another are
+/// linked, so that the cache knows how long each of them is shown and can
+/// tell which one is on screen at any given time.
+pub struct FrameCache<T> {
+    entries: BTreeMap<Timestamp, Entry<T>>,
+    /// the entries by `last_used`, so that the oldest is found without a scan
+    by_use: BTreeMap<u64, Timestamp>,
+    budget: usize,
+    bytes: usize,
+    clock: u64,
+    /// the frame inserted last, unless the sequence has been broken since
+    last: Option<Timestamp>,
+}
+
+impl<T> FrameCache<T> {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            by_use: BTreeMap::new(),
+            budget,
+            bytes: 0,
+            clock: 0,
+            last: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.by_use.clear();
+        self.bytes = 0;
+        self.last = None;
+    }
+
+    /// Makes the next frame inserted not follow the previous one, e.g.
+    /// because of a seek.
+    pub fn break_sequence(&mut self) {
+        self.last = None;
+    }
+
+    /// Adds a frame that directly follows the one inserted before it, unless
+    /// `break_sequence` has been called in between. Evicts the least recently
+    /// used frames when over budget.
+    pub fn insert(&mut self, time: Timestamp, item: T, bytes: usize) {
+        if let Some(last) = self.last
+            && last < time
+            && let Some(entry) = self.entries.get_mut(&last)
+        {
+            entry.next = Some(time);
+        }
+        self.last = Some(time);
+
+        self.clock += 1;
+        let next = self.entries.get(&time).and_then(|x| x.next);
+        let entry = Entry { item, bytes, next, last_used: self.clock };
+        if let Some(old) = self.entries.insert(time, entry) {
+            self.bytes -= old.bytes;
+            self.by_use.remove(&old.last_used);
+        }
+        self.by_use.insert(self.clock, time);
+        self.bytes += bytes;
+
+        while self.bytes > self.budget {
+            let Some((_, oldest)) = self.by_use.pop_first() else { break };
+            let entry = self.entries.remove(&oldest).unwrap();
+            self.bytes -= entry.bytes;
+        }
+    }
+
+    /// The frame on screen at `time`, i.e. the last one at or before it,
+    /// provided that it's known to last past `time`. Returns it with its own
+    /// time and when the next frame starts.
+    pub fn get(&mut self, time: Timestamp) -> Option<(&T, Timestamp, Timestamp)> {
+        let (&start, entry) = self.entries.range_mut(..=time).next_back()?;
+        let next = entry.next.filter(|&x| x > time)?;
+        self.clock += 1;
+        self.by_use.remove(&entry.last_used);
+        self.by_use.insert(self.clock, start);
+        entry.last_used = self.clock;
+        Some((&entry.item, start, next))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(times: &[i64], budget: usize) -> FrameCache<i64> {
+        let mut cache = FrameCache::new(budget);
+        for &t in times {
+            cache.insert(Timestamp(t), t, 1);
+        }
+        cache
+    }
+
+    #[test]
+    fn test_frame_on_screen() {
+        let mut cache = filled(&[0, 40, 80, 120], 100);
+        assert_eq!(cache.get(Timestamp(0)).map(|x| *x.0), Some(0));
+        assert_eq!(cache.get(Timestamp(79)).map(|x| *x.0), Some(40));
+        assert_eq!(cache.get(Timestamp(80)), Some((&80, Timestamp(80), Timestamp(120))));
+        // the last frame may last forever, or not; we don't know
+        assert!(cache.get(Timestamp(120)).is_none());
+        assert!(cache.get(Timestamp(-1)).is_none());
+    }
+
+    #[test]
+    fn test_broken_sequence() {
+        let mut cache = filled(&[0, 40], 100);
+        cache.break_sequence();
+        cache.insert(Timestamp(200), 200, 1);
+        cache.insert(Timestamp(240), 240, 1);
+        assert!(cache.get(Timestamp(40)).is_none());
+        assert!(cache.get(Timestamp(100)).is_none());
+        assert_eq!(cache.get(Timestamp(220)).map(|x| *x.0), Some(200));
+
+        // filling the gap later links it up
+        cache.break_sequence();
+        cache.insert(Timestamp(40), 40, 1);
+        cache.insert(Timestamp(200), 200, 1);
+        assert_eq!(cache.get(Timestamp(100)).map(|x| *x.0), Some(40));
+        assert_eq!(cache.get(Timestamp(220)).map(|x| x.2), Some(Timestamp(240)));
+    }
+
+    #[test]
+    fn test_least_recently_used_go_first() {
+        let mut cache = filled(&[0, 40, 80, 120], 4);
+        assert!(cache.get(Timestamp(10)).is_some());
+        cache.insert(Timestamp(160), 160, 2);
+        // 40 and 80 were the oldest
+        assert!(cache.get(Timestamp(10)).is_some());
+        assert!(cache.get(Timestamp(50)).is_none());
+        assert!(cache.get(Timestamp(90)).is_none());
+        assert_eq!(cache.get(Timestamp(130)).map(|x| *x.0), Some(120));
+    }
+
+    #[test]
+    fn test_replaced_frame_is_used_again() {
+        let mut cache = filled(&[0, 40], 2);
+        cache.break_sequence();
+        cache.insert(Timestamp(0), 0, 1);
+        cache.insert(Timestamp(80), 80, 1);
+        // 40 was the oldest and has been evicted, rather than the new 0
+        assert_eq!(cache.get(Timestamp(40)), Some((&0, Timestamp(0), Timestamp(80))));
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt::Write;
+
+/// Keyframe file formats understood by Aegisub and friends. All of them list
+/// frames by number.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, ts_rs::TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub enum KeyframeFormat {
+    /// `# keyframe format v1`, as saved by Aegisub
+    Aegisub,
+    /// XviD first pass log: one line per frame, starting with its type
+    Xvid,
+    /// x264 first pass stats: one line per frame with `in:` and `type:`
+    X264,
+    /// one frame number per line
+    FrameList,
+}
+
+fn frame_list<'a>(lines: impl Iterator<Item = &'a str>) -> Option<Vec<usize>> {
+    lines
+        .map(str::trim)
+        .filter(|x| !x.is_empty() && !x.starts_with('#'))
+        .map(|x| x.parse().ok())
+        .collect()
+}
+
+/// Counts frames of type `i`, `p` or `b` in order, like Aegisub does.
+fn frame_types(types: impl Iterator<Item = (Option<usize>, char)>) -> Vec<usize> {
+    let mut count = 0;
+    let mut result = Vec::new();
+    for (number, kind) in types {
+        let number = number.unwrap_or(count);
+        match kind.to_ascii_lowercase() {
+            'i' => result.push(number),
+            'p' | 'b' => (),
+            _ => continue,
+        }
+        count += 1;
+    }
+    result.sort_unstable();
+    result
+}
+
+/// Reads the frame numbers of the keyframes listed in `text`, recognizing the
+/// format from the first line. Returns `None` if the format is unknown or the
+/// content is malformed.
+pub fn parse(text: &str) -> Option<Vec<usize>> {
+    let mut lines = text.lines();
+    let header = lines.next()?.trim();
+    if header == "# keyframe format v1" {
+        // the next line gives the frame rate, which we don't need
+        lines.next()?.trim().strip_prefix("fps")?;
+        frame_list(lines)
+    } else if header.starts_with("# XviD 2pass stat file")
+        || header.starts_with("# ffmpeg 2-pass log file, using xvid codec")
+        || header.starts_with("# avidemux 2-pass log file, using xvid codec")
+    {
+        Some(frame_types(lines.filter_map(|x| Some((None, x.chars().next()?)))))
+    } else if header.starts_with("#options:") {
+        // stats are in coding order, but `in:` tells the display order
+        Some(frame_types(lines.filter_map(|line| {
+            let kind = line.split_once("type:")?.1.chars().next()?;
+            let number = line.split_once("in:")
+                .and_then(|x| x.1.split_whitespace().next()?.parse().ok());
+            Some((number, kind))
+        })))
+    } else {
+        frame_list(std::iter::once(header).chain(lines))
+    }
+}
+
+/// Writes `keyframes`, sorted frame numbers, in `format`. The formats that
+/// list every frame run up to `frame_count` or one past the last keyframe,
+/// whichever is more.
+pub fn write(format: KeyframeFormat, keyframes: &[usize], frame_count: usize) -> String {
+    let frame_count = frame_count.max(keyframes.last().map_or(0, |x| x + 1));
+    let is_key = |i: usize| keyframes.binary_search(&i).is_ok();
+    let mut result = String::new();
+    match format {
+        KeyframeFormat::Aegisub => {
+            result.push_str("# keyframe format v1\nfps 0\n");
+            for i in keyframes {
+                writeln!(result, "{i}").unwrap();
+            }
+        },
+        KeyframeFormat::Xvid => {
+            result.push_str("# XviD 2pass stat file\n");
+            for i in 0..frame_count {
+                result.push_str(if is_key(i) { "i\n" } else { "p\n" });
+            }
+        },
+        KeyframeFormat::X264 => {
+            result.push_str("#options: \n");
+            for i in 0..frame_count {
+                let kind = if is_key(i) { 'I' } else { 'P' };
+                writeln!(result, "in:{i} out:{i} type:{kind};").unwrap();
+            }
+        },
+        KeyframeFormat::FrameList => {
+            for i in keyframes {
+                writeln!(result, "{i}").unwrap();
+            }
+        },
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let keyframes = [0, 24, 100, 101];
+        for format in [
+            KeyframeFormat::Aegisub, KeyframeFormat::Xvid,
+            KeyframeFormat::X264, KeyframeFormat::FrameList,
+        ] {
+            let text = write(format, &keyframes, 150);
+            assert_eq!(parse(&text).as_deref(), Some(&keyframes[..]), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn test_x264_coding_order() {
+        let text = "#options: 1920x1080 fps=24000/1001\n\
+            in:0 out:0 type:I dur:2 cpbdur:2 q:20.00 tex:0 mv:0 misc:0 imb:0 pmb:0 smb:0 d:- ref:;\n\
+            in:3 out:1 type:P dur:2 cpbdur:2 q:20.00;\n\
+            in:1 out:2 type:B dur:2 cpbdur:2 q:20.00;\n\
+            in:2 out:3 type:b dur:2 cpbdur:2 q:20.00;\n\
+            in:5 out:4 type:i dur:2 cpbdur:2 q:20.00;\n\
+            in:4 out:5 type:B dur:2 cpbdur:2 q:20.00;\n";
+        assert_eq!(parse(text), Some(vec![0, 5]));
+    }
+
+    #[test]
+    fn test_xvid_log() {
+        let text = "# XviD 2pass stat file (core version 1.3.7)\n\
+            # Please do not modify this file\n\
+            \n\
+            i 1 1092 0 0 12000 0 0\n\
+            p 2 78 0 0 0 0 0\n\
+            b 4 10 0 0 0 0 0\n\
+            i 1 990 0 0 11000 0 0\n";
+        assert_eq!(parse(text), Some(vec![0, 3]));
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("12\nabc\n"), None);
+        assert_eq!(parse("# keyframe format v1\n0\n"), None);
+    }
+}
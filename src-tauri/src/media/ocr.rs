@@ -0,0 +1,261 @@
+use std::{io::Write, process::{Command, Stdio}};
+
+use log::{debug, warn};
+use num_traits::ToPrimitive;
+
+use crate::media::{internal::MediaError, subtitles::{SubtitleEntry, SubtitleRect}};
+
+/// Blank space around the text; OCR engines do worse on text that touches
+/// the edges.
+const MARGIN: usize = 10;
+
+/// An 8-bit grayscale picture of dark text on a light background, which is
+/// what OCR engines read best.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// Flattens subtitles in RGBA, usually light text with a dark outline over
+    /// nothing, onto white, so that the fill ends up dark and the rest light.
+    pub fn from_subtitle(rgba: &[u8], width: usize, height: usize) -> Self {
+        let (w, h) = (width + 2 * MARGIN, height + 2 * MARGIN);
+        let mut data = vec![255; w * h];
+        for (y, row) in rgba.chunks_exact(width * 4).take(height).enumerate() {
+            for (x, px) in row.chunks_exact(4).enumerate() {
+                let luma = (u32::from(px[0]) * 54 + u32::from(px[1]) * 183
+                    + u32::from(px[2]) * 19) >> 8;
+                // over black, then inverted
+                let value = 255 - luma * u32::from(px[3]) / 255;
+                data[(y + MARGIN) * w + x + MARGIN] = value.to_u8().unwrap();
+            }
+        }
+        Self { width: w, height: h, data }
+    }
+
+    /// Encodes it as a binary PGM, which about any image library reads.
+    pub fn to_pgm(&self) -> Vec<u8> {
+        let mut out = format!("P5\n{} {}\n255\n", self.width, self.height).into_bytes();
+        out.extend_from_slice(&self.data);
+        out
+    }
+}
+
+pub struct Recognized {
+    pub text: String,
+    /// from 0 to 1, if the engine tells
+    pub confidence: Option<f32>,
+}
+
+pub trait OcrEngine {
+    fn recognize(&mut self, image: &Image) -> Result<Recognized, MediaError>;
+}
+
+#[derive(Clone, Debug, serde::Deserialize, ts_rs::TS)]
+#[serde(rename_all = "camelCase", tag = "type")]
+#[ts(export)]
+pub enum OcrOptions {
+    /// the Tesseract command line tool
+    #[serde(rename_all = "camelCase")]
+    Tesseract {
+        /// `tesseract` in the `PATH` by default
+        program: Option<String>,
+        /// e.g. `eng`, or `chi_sim+eng`
+        language: String,
+    },
+    /// a program that reads a PGM image from its standard input and writes
+    /// the text to its standard output
+    #[serde(rename_all = "camelCase")]
+    Command {
+        program: String,
+        args: Vec<String>,
+    },
+}
+
+impl OcrOptions {
+    pub fn engine(self) -> Box<dyn OcrEngine> {
+        match self {
+            OcrOptions::Tesseract { program, language } => Box::new(CommandEngine {
+                program: program.unwrap_or_else(|| "tesseract".into()),
+                args: vec![
+                    "stdin".into(), "stdout".into(),
+                    "-l".into(), language,
+                    // a single block of text
+                    "--psm".into(), "6".into(),
+                    "tsv".into(),
+                ],
+                tsv: true,
+            }),
+            OcrOptions::Command { program, args } =>
+                Box::new(CommandEngine { program, args, tsv: false }),
+        }
+    }
+}
+
+/// Runs a program for each image.
+struct CommandEngine {
+    program: String,
+    args: Vec<String>,
+    /// whether the output is Tesseract's TSV, which has confidences, or
+    /// just text
+    tsv: bool,
+}
+
+impl OcrEngine for CommandEngine {
+    fn recognize(&mut self, image: &Image) -> Result<Recognized, MediaError> {
+        let error = |e: std::io::Error|
+            MediaError::InternalError(format!("failed to run {}: {e}", self.program));
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(error)?;
+        // dropped right after, so that the program sees the end of it
+        child.stdin.take().unwrap().write_all(&image.to_pgm()).map_err(error)?;
+        let output = child.wait_with_output().map_err(error)?;
+        if !output.status.success() {
+            return Err(MediaError::InternalError(format!(
+                "{} exited with {}: {}", self.program, output.status,
+                String::from_utf8_lossy(&output.stderr).trim())));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(if self.tsv {
+            parse_tsv(&stdout)
+        } else {
+            Recognized { text: stdout.trim().to_owned(), confidence: None }
+        })
+    }
+}
+
+/// Reads Tesseract's TSV output: words with their confidence and where they
+/// are in blocks, paragraphs and lines. The confidence of the whole is that
+/// of the words, weighted by their length.
+pub fn parse_tsv(tsv: &str) -> Recognized {
+    let mut lines: Vec<Vec<&str>> = Vec::new();
+    let mut last_line = None;
+    let (mut weighted, mut total) = (0.0, 0.0);
+    // the first line is the header
+    for row in tsv.lines().skip(1) {
+        let fields: Vec<&str> = row.split('\t').collect();
+        let [level, _, block, par, line, _, _, _, _, _, conf, text] = fields[..] else {
+            continue;
+        };
+        let number = |x: &str| x.parse::<u32>().unwrap_or(0);
+        let text = text.trim();
+        // level 5 is words; the others have no text
+        if level != "5" || text.is_empty() {
+            continue;
+        }
+        let key = Some((number(block), number(par), number(line)));
+        match lines.last_mut() {
+            Some(words) if key == last_line => words.push(text),
+            _ => lines.push(vec![text]),
+        }
+        last_line = key;
+        if let Ok(conf) = conf.parse::<f32>()
+            && conf >= 0.0
+        {
+            let length = text.chars().count().to_f32().unwrap();
+            weighted += conf / 100.0 * length;
+            total += length;
+        }
+    }
+    let text = lines.iter()
+        .map(|words| words.join(" "))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Recognized {
+        text,
+        confidence: (total > 0.0).then(|| weighted / total),
+    }
+}
+
+/// Reads the text in the bitmaps of `entries`, turning them into text
+/// entries; bitmaps in one entry are read top to bottom. The confidence of
+/// an entry is that of its least certain bitmap. Entries without bitmaps are
+/// kept as they are, and those where no text is found are dropped.
+pub fn recognize(
+    engine: &mut dyn OcrEngine, entries: Vec<SubtitleEntry>,
+    mut progress: impl FnMut(f64)
+) -> Result<Vec<SubtitleEntry>, MediaError> {
+    let count = entries.len().to_f64().unwrap();
+    let mut result = Vec::new();
+    for (i, mut entry) in entries.into_iter().enumerate() {
+        progress(i.to_f64().unwrap() / count);
+
+        let mut bitmaps: Vec<_> = entry.rects.iter().filter_map(|rect| match rect {
+            SubtitleRect::Bitmap { y, width, height, data, .. } => Some((y, width, height, data)),
+            _ => None,
+        }).collect();
+        if bitmaps.is_empty() {
+            result.push(entry);
+            continue;
+        }
+        bitmaps.sort_by_key(|(y, ..)| **y);
+
+        let mut texts = Vec::new();
+        let mut confidence: Option<f32> = None;
+        for (_, width, height, data) in bitmaps {
+            let (width, height) = (width.to_usize().unwrap(), height.to_usize().unwrap());
+            if width == 0 || height == 0 {
+                continue;
+            }
+            let recognized = engine.recognize(&Image::from_subtitle(data, width, height))?;
+            if recognized.text.is_empty() {
+                continue;
+            }
+            texts.push(recognized.text);
+            if let Some(x) = recognized.confidence {
+                confidence = Some(confidence.map_or(x, |c| c.min(x)));
+            }
+        }
+        if texts.is_empty() {
+            warn!("ocr: nothing found in entry at {:?}", entry.start);
+            continue;
+        }
+        debug!("ocr: {:?} {texts:?}, {confidence:?}", entry.start);
+        entry.rects = vec![SubtitleRect::Text { content: texts.join("\n") }];
+        entry.confidence = confidence;
+        result.push(entry);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tsv() {
+        let tsv = "\
+level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext
+1\t1\t0\t0\t0\t0\t0\t0\t400\t80\t-1\t
+4\t1\t1\t1\t1\t0\t10\t10\t300\t30\t-1\t
+5\t1\t1\t1\t1\t1\t10\t10\t100\t30\t90\tHello,
+5\t1\t1\t1\t1\t2\t120\t10\t100\t30\t80\tworld
+5\t1\t1\t1\t2\t1\t10\t50\t100\t30\t50.5\tagain
+5\t1\t1\t1\t2\t2\t120\t50\t100\t30\t-1\t \n";
+        let recognized = parse_tsv(tsv);
+        assert_eq!(recognized.text, "Hello, world\nagain");
+        let expected = (0.9 * 6.0 + 0.8 * 5.0 + 0.505 * 5.0) / 16.0;
+        assert!((recognized.confidence.unwrap() - expected).abs() < 1e-6);
+
+        assert!(parse_tsv("").confidence.is_none());
+    }
+
+    #[test]
+    fn test_image_from_subtitle() {
+        // opaque white, opaque black, transparent
+        let rgba = [255, 255, 255, 255, 0, 0, 0, 255, 255, 255, 255, 0];
+        let image = Image::from_subtitle(&rgba, 3, 1);
+        assert_eq!((image.width, image.height), (3 + 2 * MARGIN, 1 + 2 * MARGIN));
+        let row = &image.data[MARGIN * image.width + MARGIN..][..3];
+        assert_eq!(row, [0, 255, 255]);
+        assert!(image.data[..image.width].iter().all(|&x| x == 255));
+        assert!(image.to_pgm().starts_with(b"P5\n23 21\n255\n"));
+    }
+}
@@ -1,10 +1,32 @@
-use crate::media::{audio::{self, AudioSink}, demux, frame, internal::MediaError, units, video::{self, VideoSink}, subtitles};
+use std::path::{Path, PathBuf};
+
+use ffmpeg::ChannelLayout;
+use log::{debug, warn};
+use num_traits::ToPrimitive;
+
+use crate::media::{audio::{self, AudioSink}, cache, demux, frame, internal::MediaError, units, video::{self, VideoSink}, subtitles};
+
+/// How many frames are taken from the player's cache at once.
+const REPLAY_FRAMES: usize = 8;
 
 pub struct Session {
     demuxer: demux::Demuxer,
     audio: Option<(audio::Decoder, audio::AudioSinkKind)>,
     video: Option<(video::Decoder, video::VideoSinkKind)>,
     subtitles: Option<subtitles::Decoder>,
+
+    /// where sampler state is persisted; `None` disables the cache
+    cache_dir: Option<PathBuf>,
+    audio_cache_key: Option<cache::CacheKey>,
+    video_cache_key: Option<cache::CacheKey>,
+
+    /// where to continue reading after frames were served from the player's
+    /// cache without moving the demuxer: audio from the first time, video
+    /// from the second
+    resume_at: Option<(units::Seconds, units::Seconds)>,
+    /// the closed caption services found so far, with their subtitle stream
+    /// indices; see `add_caption_streams`
+    caption_streams: Vec<(usize, subtitles::CaptionService)>,
 }
 
 impl Session {
@@ -33,17 +55,94 @@ impl Session {
 
 unsafe impl Send for Session {}
 
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.save_audio_sampler();
+        self.save_video_sampler();
+    }
+}
+
 impl Session {
-    pub fn create(path: &std::path::Path) -> Result<Self, MediaError> {
+    pub fn create(path: &Path, cache_dir: Option<PathBuf>) -> Result<Self, MediaError> {
         Ok(Self {
             demuxer: demux::Demuxer::open(path)?,
             audio: None,
             video: None,
             subtitles: None,
+            cache_dir,
+            audio_cache_key: None,
+            video_cache_key: None,
+            resume_at: None,
+            caption_streams: Vec::new(),
         })
     }
 
+    /// Lists closed caption services, each with the video stream carrying
+    /// it, as subtitle streams from now on, and returns their descriptions.
+    /// Finding them is up to `subtitles::probe_captions`, which takes a
+    /// while, so it's done apart from the session.
+    pub fn add_caption_streams(&mut self, found: &[(usize, subtitles::CaptionService)])
+        -> Vec<demux::StreamDescription>
+    {
+        let mut added = Vec::new();
+        for &(video, service) in found {
+            let index = subtitles::caption_stream_index(&self.demuxer, video, service);
+            if !self.caption_streams.contains(&(index, service)) {
+                self.caption_streams.push((index, service));
+            }
+            added.push(service.describe(index));
+        }
+        added
+    }
+
+    /// The streams of the file, and after them the closed captions found so
+    /// far as subtitle streams.
+    pub fn describe_streams(&self) -> Vec<demux::StreamDescription> {
+        let mut streams = self.demuxer.describe_streams();
+        streams.extend(self.caption_streams.iter()
+            .map(|&(i, service)| service.describe(i)));
+        streams
+    }
+
+    fn load_cache(&self, key: Option<&cache::CacheKey>) -> Option<Vec<u8>> {
+        cache::load(self.cache_dir.as_deref()?, key?)
+    }
+
+    fn store_cache(&self, key: Option<&cache::CacheKey>, save: impl FnOnce() -> Vec<u8>) {
+        let (Some(dir), Some(key)) = (self.cache_dir.as_deref(), key) else { return };
+        if let Err(e) = cache::store(dir, key, &save()) {
+            warn!("Session: failed to store sampler cache: {e}");
+        }
+    }
+
+    /// Persists the state of the audio sampler, if one is open. Called before
+    /// the audio sink is replaced.
+    fn save_audio_sampler(&mut self) {
+        if let Some((_, audio::AudioSinkKind::Sampler(s))) = &self.audio {
+            self.store_cache(self.audio_cache_key.as_ref(), || s.save());
+        }
+        self.audio_cache_key = None;
+    }
+
+    /// Persists the state of the video sampler, if one is open. Called before
+    /// the video sink is replaced, by `set_video`.
+    fn save_video_sampler(&mut self) {
+        if let Some((_, video::VideoSinkKind::Sampler(s))) = &self.video {
+            self.store_cache(self.video_cache_key.as_ref(), || s.save());
+        }
+        self.video_cache_key = None;
+    }
+
+    /// Replaces the video sink, persisting the sampler it replaces. A new
+    /// sink has nothing to `resume` from.
+    fn set_video(&mut self, decoder: video::Decoder, sink: video::VideoSinkKind) {
+        self.save_video_sampler();
+        self.resume_at = None;
+        self.video = Some((decoder, sink));
+    }
+
     fn flush(&mut self) {
+        self.resume_at = None;
         if let Some((d, s)) = self.audio.as_mut() {
             d.flush();
             s.clear();
@@ -83,9 +182,196 @@ impl Session {
         Ok(())
     }
 
-    pub fn open_audio_player(&mut self, index: Option<usize>) -> Result<(), MediaError> {
+    /// Seeks the video so that its player's first frame is the one shown at
+    /// `time`, i.e. the last frame at or before it. If the player has that
+    /// frame in its cache, a few cached frames are queued instead and the
+    /// demuxer isn't touched until `resume`. Returns the last audio frame
+    /// before `time`, as `skip_until` does.
+    pub fn seek_exact(&mut self, time: units::Seconds)
+        -> Result<Option<frame::Audio>, MediaError>
+    {
+        if self.replay_from(time) {
+            return Ok(None);
+        }
+        self.decode_exact(time, time)
+    }
+
+    /// Continues after frames were served from the player's cache: queues
+    /// more cached frames if there are any, or else seeks so that audio
+    /// starts from the time sought and video from the first frame not yet
+    /// given. Returns whether frames came from the cache, in which case
+    /// nothing else should be read for now.
+    pub fn resume(&mut self) -> Result<bool, MediaError> {
+        let Some((audio_from, video_from)) = self.resume_at.take() else {
+            return Ok(false);
+        };
+        if let Some((_, video::VideoSinkKind::Player(p))) = self.video.as_mut()
+            && let Some(next) = p.replay_cached(video_from, REPLAY_FRAMES)
+        {
+            self.resume_at = Some((audio_from, next));
+            return Ok(true);
+        }
+        self.decode_exact(audio_from, video_from)?;
+        Ok(false)
+    }
+
+    /// Queues cached frames in the player starting with the one shown at
+    /// `time`, if it has them, and sets up `resume`.
+    fn replay_from(&mut self, time: units::Seconds) -> bool {
+        self.resume_at = None;
+        let Some((_, video::VideoSinkKind::Player(p))) = self.video.as_mut() else {
+            return false;
+        };
+        p.clear();
+        let Some(next) = p.replay_cached(time, REPLAY_FRAMES) else {
+            return false;
+        };
+        if let Some((_, s)) = self.audio.as_mut() {
+            s.clear();
+        }
+        self.resume_at = Some((time, next));
+        true
+    }
+
+    /// Seeks and decodes forward so that the audio sink starts from
+    /// `audio_from` and the video player from the frame shown at `video_at`.
+    /// The demuxer sometimes lands after the keyframe we need, for example
+    /// with long GOPs or B-frames; then this seeks again further back.
+    fn decode_exact(&mut self, audio_from: units::Seconds, video_at: units::Seconds)
+        -> Result<Option<frame::Audio>, MediaError>
+    {
+        let start = self.video.as_ref().unwrap().0.stream_info().start_time_seconds();
+        let mut back = 0.0;
+        loop {
+            let target = units::Seconds((audio_from.0 - back).max(start.0));
+            self.seek_video(target)?;
+            let (last_audio, landed) = self.decode_until_shown(audio_from, video_at)?;
+
+            let first = self.player_mut()?.first_time();
+            if !landed && first.is_some_and(|t| t > video_at) && target > start {
+                back = (back * 2.0).max(1.0);
+                debug!("Session::decode_exact: landed at {} for {video_at}, seeking {back}s earlier",
+                    first.unwrap());
+                continue;
+            }
+            return Ok(last_audio);
+        }
+    }
+
+    fn player_mut(&mut self) -> Result<&mut video::Player, MediaError> {
+        match self.video.as_mut() {
+            Some((_, video::VideoSinkKind::Player(p))) => Ok(p),
+            _ => Err(MediaError::InternalError("no video player".into())),
+        }
+    }
+
+    /// Decodes until each sink has something after the point asked for, or
+    /// until the end of the file. Audio before `audio_from` is dropped, save
+    /// for the last frame, which is returned. Video frames before the one
+    /// shown at `video_at` only go to the player's cache. Also returns
+    /// whether that frame was found, i.e. whether any frame came at or before
+    /// `video_at`.
+    fn decode_until_shown(&mut self, audio_from: units::Seconds, video_at: units::Seconds)
+        -> Result<(Option<frame::Audio>, bool), MediaError>
+    {
+        let mut last_audio: Option<frame::Audio> = None;
+        let mut shown: Option<frame::Video> = None;
+        let mut landed = false;
+        loop {
+            let mut received = false;
+            if let Some((d, s)) = self.audio.as_mut()
+                && let Some(f) = d.try_receive()?
+            {
+                received = true;
+                if f.meta.time >= audio_from {
+                    s.process(f)?;
+                } else {
+                    last_audio = Some(f);
+                }
+            }
+            if let Some((d, video::VideoSinkKind::Player(p))) = self.video.as_mut()
+                && let Some(f) = d.try_receive()?
+            {
+                received = true;
+                if f.meta.time > video_at {
+                    if let Some(x) = shown.take() {
+                        p.process(x)?;
+                    }
+                    p.process(f)?;
+                } else {
+                    landed = true;
+                    if let Some(previous) = shown.replace(f) {
+                        p.skip(previous)?;
+                    }
+                }
+            }
+            if received {
+                continue;
+            }
+
+            if self.audio().is_none_or(|(_, s)| !s.is_empty())
+                && self.video().is_none_or(|(_, s)| !s.is_empty())
+            {
+                break;
+            }
+            if !self.try_feed()? {
+                break;
+            }
+        }
+        // at the end of the file, the last frame is still shown
+        if let Some(x) = shown {
+            let player = self.player_mut()?;
+            player.process(x)?;
+            player.finish()?;
+        }
+        Ok((last_audio, landed))
+    }
+
+    /// Decodes until each sink has something at or after `time`, or until
+    /// the end of the file, dropping what comes before. Returns the last
+    /// frames before `time`. Video the player has cached is served from there
+    /// first, like in `seek_exact`.
+    pub fn skip_until(&mut self, time: units::Seconds)
+        -> Result<(Option<frame::Audio>, Option<frame::Video>), MediaError>
+    {
+        if self.replay_from(time) {
+            self.player_mut()?.drop_first_before(time);
+            return Ok((None, None));
+        }
+
+        if let Some((_, s)) = self.audio_mut() {
+            s.clear();
+        }
+        if let Some((_, s)) = self.video_mut() {
+            s.clear();
+        }
+
+        let mut last_audio: Option<frame::Audio> = None;
+        let mut last_video: Option<frame::Video> = None;
+        loop {
+            let (_, a, v) = self.try_process_skipping_before(time)?;
+            if let Some(f) = a { last_audio = Some(f); }
+            if let Some(f) = v { last_video = Some(f); }
+
+            if self.audio().is_none_or(|(_, s)| !s.is_empty())
+                && self.video().is_none_or(|(_, s)| !s.is_empty())
+            {
+                break;
+            }
+            if !self.try_feed()? {
+                break;
+            }
+        }
+        Ok((last_audio, last_video))
+    }
+
+    pub fn open_audio_player(
+        &mut self, index: Option<usize>,
+        layout: Option<ChannelLayout>, mix: Option<&audio::ChannelMix>
+    ) -> Result<(), MediaError> {
         let decoder = audio::Decoder::create(&self.demuxer, index)?;
-        let sink = audio::Player::create(&decoder)?;
+        let sink = audio::Player::create(&decoder, layout, mix)?;
+        self.save_audio_sampler();
         self.audio = Some((decoder, sink.into()));
         Ok(())
     }
@@ -95,26 +381,197 @@ impl Session {
     ) -> Result<(), MediaError> {
         let decoder = video::Decoder::create(&self.demuxer, index, accel)?;
         let sink = video::Player::create(&decoder)?;
-        self.video = Some((decoder, sink.into()));
+        self.set_video(decoder, sink.into());
         Ok(())
     }
 
     pub fn open_audio_sampler(
-        &mut self, index: Option<usize>, sample_per_second: usize
+        &mut self, index: Option<usize>, sample_per_second: usize,
+        mix: Option<&audio::ChannelMix>
+    ) -> Result<(), MediaError> {
+        let decoder = audio::Decoder::create(&self.demuxer, index)?;
+        let mut sink = audio::Sampler::create(&decoder, sample_per_second, mix)?;
+        let key = cache::CacheKey::new(&self.demuxer, "audio",
+            decoder.stream_info().index(), &format!("{sample_per_second}|{mix:?}"));
+        if let Some(data) = self.load_cache(key.as_ref()) {
+            debug!("open_audio_sampler: restored from cache: {}", sink.restore(&data));
+        }
+        self.save_audio_sampler();
+        self.audio = Some((decoder, sink.into()));
+        self.audio_cache_key = key;
+        Ok(())
+    }
+
+    pub fn open_audio_spectrogram(
+        &mut self, index: Option<usize>, slot_per_second: usize, fft_size: usize,
+        mix: Option<&audio::ChannelMix>
     ) -> Result<(), MediaError> {
         let decoder = audio::Decoder::create(&self.demuxer, index)?;
-        let sink = audio::Sampler::create(&decoder, sample_per_second)?;
+        let sink = audio::Spectrogram::create(&decoder, slot_per_second, fft_size, mix)?;
+        self.save_audio_sampler();
+        self.audio = Some((decoder, sink.into()));
+        Ok(())
+    }
+
+    pub fn open_speech_detector(
+        &mut self, index: Option<usize>, options: audio::SpeechDetectionOptions,
+        mix: Option<&audio::ChannelMix>
+    ) -> Result<(), MediaError> {
+        let decoder = audio::Decoder::create(&self.demuxer, index)?;
+        let sink = audio::SpeechDetector::create(&decoder, options, mix)?;
+        self.save_audio_sampler();
+        self.audio = Some((decoder, sink.into()));
+        Ok(())
+    }
+
+    pub fn open_fingerprinter(
+        &mut self, index: Option<usize>, mix: Option<&audio::ChannelMix>
+    ) -> Result<(), MediaError> {
+        let decoder = audio::Decoder::create(&self.demuxer, index)?;
+        let sink = audio::Fingerprinter::create(&decoder, mix)?;
+        self.save_audio_sampler();
         self.audio = Some((decoder, sink.into()));
         Ok(())
     }
 
     pub fn open_video_sampler(&mut self, index: Option<usize>) -> Result<(), MediaError> {
         let decoder = video::Decoder::create(&self.demuxer, index, false)?;
-        let sink = video::Sampler::create(&decoder)?;
-        self.video = Some((decoder, sink.into()));
+        let mut sink = video::Sampler::create(&decoder)?;
+        let key = cache::CacheKey::new(&self.demuxer, "video",
+            decoder.stream_info().index(), "");
+        if let Some(data) = self.load_cache(key.as_ref()) {
+            debug!("open_video_sampler: restored from cache: {}", sink.restore(&data));
+        }
+        self.set_video(decoder, sink.into());
+        self.video_cache_key = key;
+        Ok(())
+    }
+
+    pub fn open_thumbnailer(
+        &mut self, index: Option<usize>, height: u32, interval: Option<units::Seconds>
+    ) -> Result<(), MediaError> {
+        let decoder = video::Decoder::create(&self.demuxer, index, false)?;
+        let sink = video::Thumbnailer::create(&decoder, height, interval)?;
+        self.set_video(decoder, sink.into());
         Ok(())
     }
 
+    /// The stream index and timebase of the video sampler's stream, for
+    /// `scan_video_packets`.
+    pub fn video_sampler_stream(&self) -> Result<(usize, units::Rational), MediaError> {
+        let Some((d, video::VideoSinkKind::Sampler(_))) = &self.video else {
+            return Err(MediaError::InternalError(
+                "video_sampler_stream: no video sampler".to_owned()));
+        };
+        Ok((d.stream_info().index(), d.stream_info().timebase()))
+    }
+
+    /// Reads the flags of every packet of stream `index` in the file at
+    /// `path`, on a demuxer of its own. Needs no session, so that a long
+    /// scan doesn't hold one. `progress` gets the fraction of the file read
+    /// so far.
+    pub fn scan_video_packets(
+        path: &Path, index: usize, timebase: units::Rational,
+        mut progress: impl FnMut(f64)
+    ) -> Result<Vec<video::PacketEntry>, MediaError> {
+        let mut demuxer = demux::Demuxer::open(path)?;
+        let size = demuxer.byte_size().to_f64().unwrap().max(1.0);
+        let mut packets = Vec::new();
+        while let Some((i, packet)) = demuxer.next_packet() {
+            if i != index {
+                continue;
+            }
+            // -1 if unknown
+            if let Some(pos) = packet.position().to_f64()
+                && pos >= 0.0
+            {
+                progress(pos / size);
+            }
+            packets.extend(video::PacketEntry::of(&packet, timebase));
+        }
+        Ok(packets)
+    }
+
+    /// Indexes the keyframes and frames of the video sampler's stream from
+    /// what `scan_video_packets` read of stream `index`; fails if the
+    /// sampler has moved to another stream in the meantime.
+    pub fn index_video_packets(
+        &mut self, index: usize, packets: &[video::PacketEntry]
+    ) -> Result<(), MediaError> {
+        if let Some((d, video::VideoSinkKind::Sampler(s))) = &mut self.video
+            && d.stream_info().index() == index
+        {
+            s.index_packets(packets);
+            return Ok(());
+        }
+        Err(MediaError::InternalError(
+            "index_video_packets: no video sampler on this stream".to_owned()))
+    }
+
+    /// Decodes the last frame at or before `time` of a video stream in the
+    /// file at `path`, on a demuxer and decoder of its own. Needs no session,
+    /// so that nothing open is disturbed or held. Like `decode_exact`, seeks
+    /// again further back when the demuxer lands after `time`; fails if there
+    /// is no frame that early.
+    pub fn grab_frame(
+        path: &Path, index: Option<usize>, time: units::Seconds
+    ) -> Result<(video::Decoder, frame::Video), MediaError> {
+        let mut demuxer = demux::Demuxer::open(path)?;
+        let mut decoder = video::Decoder::create(&demuxer, index, false)?;
+        let index = decoder.stream_info().index();
+        let start = decoder.stream_info().start_time_seconds();
+        let mut back = 0.0;
+        loop {
+            let target = units::Seconds((time.0 - back).max(start.0));
+            demuxer.seek_stream(target, decoder.stream_info())?;
+            decoder.flush();
+            match Self::decode_last_before(&mut demuxer, &mut decoder, index, time)? {
+                Ok(f) => return Ok((decoder, f)),
+                Err(Some(first)) if target > start => {
+                    back = (back * 2.0).max(1.0);
+                    debug!("Session::grab_frame: landed at {first} for {time}, seeking {back}s earlier");
+                }
+                Err(_) => return Err(MediaError::InternalError(
+                    format!("grab_frame: no frame at or before {time}"))),
+            }
+        }
+    }
+
+    /// Decodes from where `demuxer` is to the last frame at or before `time`.
+    /// If there is none, gives the time of the first frame, if any.
+    fn decode_last_before(
+        demuxer: &mut demux::Demuxer, decoder: &mut video::Decoder,
+        index: usize, time: units::Seconds
+    ) -> Result<Result<frame::Video, Option<units::Seconds>>, MediaError> {
+        let mut last: Option<frame::Video> = None;
+        let mut at_eof = false;
+        loop {
+            // errors after EOF only mean that there is nothing left
+            let received = match decoder.try_receive() {
+                Err(_) if at_eof => None,
+                x => x?,
+            };
+            if let Some(f) = received {
+                if f.meta.time > time {
+                    return Ok(last.ok_or(Some(f.meta.time)));
+                }
+                last = Some(f);
+                continue;
+            }
+            if at_eof {
+                return Ok(last.ok_or(None));
+            }
+            match demuxer.next_packet() {
+                Some((i, packet)) if i == index => decoder.feed(&packet)?,
+                Some(_) => (),
+                None => {
+                    decoder.send_eof()?;
+                    at_eof = true;
+                },
+            }
+        }
+    }
+
     pub fn open_subtitles_decoder(&mut self, index: Option<usize>) -> Result<(), MediaError> {
         let decoder = subtitles::Decoder::create(&self.demuxer, index)?;
         self.subtitles = Some(decoder);
@@ -124,6 +581,12 @@ impl Session {
     /// returns `Ok(false)` on EOF
     pub fn try_feed(&mut self) -> Result<bool, MediaError> {
         let Some((i, packet)) = self.demuxer.next_packet() else {
+            if let Some((_, video::VideoSinkKind::Player(p))) = self.video.as_mut() {
+                p.finish()?;
+            }
+            if let Some(d) = self.subtitles.as_mut() {
+                finish_subtitles(&self.demuxer, d);
+            }
             return Ok(false);
         };
         if let Some((d, _)) = self.audio_mut()
@@ -144,6 +607,43 @@ impl Session {
         Ok(true)
     }
 
+    /// Decodes whole subtitle streams, `indices` or all of them, in a single
+    /// pass over the file; `progress` is told how far it is, from 0 to 1.
+    /// Returns the decoders in the order of `indices`, with all their entries.
+    /// Like `seek`, this leaves the other decoders flushed.
+    pub fn decode_subtitle_streams(
+        &mut self, indices: Option<&[usize]>, mut progress: impl FnMut(f64)
+    ) -> Result<Vec<(usize, subtitles::Decoder)>, MediaError> {
+        let indices = match indices {
+            Some(x) => x.to_vec(),
+            None => {
+                let mut all = self.demuxer.stream_indices(demux::StreamKind::Subtitle);
+                all.extend(self.caption_streams.iter().map(|&(i, _)| i));
+                all
+            }
+        };
+        let mut decoders = indices.into_iter()
+            .map(|i| subtitles::Decoder::create(&self.demuxer, Some(i)).map(|d| (i, d)))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.seek(units::Seconds(0.0))?;
+
+        let size = self.demuxer.byte_size().to_f64().unwrap();
+        while let Some((i, packet)) = self.demuxer.next_packet() {
+            for (_, d) in decoders.iter_mut().filter(|(_, d)| d.stream_info().index() == i) {
+                d.feed(&packet)?;
+            }
+            if let Some(pos) = packet.position().to_f64()
+                && pos >= 0.0
+            {
+                progress(pos / size);
+            }
+        }
+        for (_, d) in &mut decoders {
+            finish_subtitles(&self.demuxer, d);
+        }
+        Ok(decoders)
+    }
+
     pub fn try_process(&mut self)
         -> Result<(i32, Option<frame::Audio>, Option<frame::Video>), MediaError>
     {
@@ -183,3 +683,9 @@ impl Session {
         Ok((count, last_audio, last_video))
     }
 }
+
+/// Ends a subtitle decoder that got to the end of the file.
+fn finish_subtitles(demuxer: &demux::Demuxer, decoder: &mut subtitles::Decoder) {
+    let start = decoder.stream_info().start_time_seconds();
+    decoder.finish(units::Seconds(start.0 + demuxer.duration().0));
+}
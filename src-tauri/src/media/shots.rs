@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+
+use num_traits::ToPrimitive;
+
+/// Frames are compared as luma scaled down to this size.
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 36;
+
+const BINS: usize = 32;
+/// A cut needs at least this score...
+const MIN_SCORE: f32 = 0.2;
+/// ...and this many times the mean score of the frames just before it, so
+/// that fast motion and camera shake don't count.
+const CONTRAST: f32 = 3.0;
+const HISTORY: usize = 8;
+
+/// Finds cuts from the change between consecutive frames, measured as the
+/// mean of the sum of absolute pixel differences and the luma histogram
+/// difference, both from 0 to 1.
+#[derive(Default)]
+pub struct Detector {
+    previous: Option<(Vec<u8>, [u32; BINS])>,
+    history: VecDeque<f32>,
+}
+
+fn histogram(luma: &[u8]) -> [u32; BINS] {
+    let mut result = [0; BINS];
+    for &x in luma {
+        result[usize::from(x) * BINS / 256] += 1;
+    }
+    result
+}
+
+impl Detector {
+    /// Forgets the previous frame, e.g. because the next one doesn't follow
+    /// it directly.
+    pub fn reset(&mut self) {
+        self.previous = None;
+        self.history.clear();
+    }
+
+    /// Takes a `WIDTH` × `HEIGHT` luma image, row by row. Returns whether a
+    /// new shot starts with it.
+    pub fn push(&mut self, luma: Vec<u8>) -> bool {
+        debug_assert_eq!(luma.len(), WIDTH * HEIGHT);
+        let hist = histogram(&luma);
+        let Some((prev_luma, prev_hist)) = self.previous.replace((luma, hist)) else {
+            return false;
+        };
+        let luma = &self.previous.as_ref().unwrap().0;
+
+        let n = (WIDTH * HEIGHT).to_f32().unwrap();
+        let sad: u32 = luma.iter().zip(&prev_luma)
+            .map(|(&a, &b)| u32::from(a.abs_diff(b)))
+            .sum();
+        let hist_diff: u32 = hist.iter().zip(&prev_hist)
+            .map(|(&a, &b)| a.abs_diff(b))
+            .sum();
+        let score = 0.5 * (sad.to_f32().unwrap() / (255.0 * n)
+            + hist_diff.to_f32().unwrap() / (2.0 * n));
+
+        let typical = if self.history.is_empty() {
+            0.0
+        } else {
+            self.history.iter().sum::<f32>() / self.history.len().to_f32().unwrap()
+        };
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(score);
+        score > MIN_SCORE && score > CONTRAST * typical
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a diagonal gradient shifted by `shift` pixels, with some texture
+    fn scene(kind: u8, shift: usize) -> Vec<u8> {
+        (0..WIDTH * HEIGHT).map(|i| {
+            let (x, y) = (i % WIDTH + shift, i / WIDTH);
+            let base = match kind {
+                0 => (x + y) * 2,
+                1 => 255 - (x * 3 + y * 2).min(255),
+                _ => 128 + (x / 8 % 2) * 100 - (y / 6 % 2) * 100,
+            };
+            u8::try_from((base + (x * 7 + y * 13) % 11).min(255)).unwrap()
+        }).collect()
+    }
+
+    #[test]
+    fn test_cuts_between_scenes() {
+        let mut detector = Detector::default();
+        let mut cuts = Vec::new();
+        for frame in 0..60 {
+            let kind = match frame { 0..20 => 0, 20..40 => 1, _ => 2 };
+            if detector.push(scene(kind, frame % 20)) {
+                cuts.push(frame);
+            }
+        }
+        assert_eq!(cuts, vec![20, 40]);
+    }
+
+    #[test]
+    fn test_fast_motion_is_not_a_cut() {
+        let mut detector = Detector::default();
+        for frame in 0..40 {
+            assert!(!detector.push(scene(2, frame * 3)), "{frame}");
+        }
+    }
+
+    #[test]
+    fn test_reset_forgets_previous_frame() {
+        let mut detector = Detector::default();
+        assert!(!detector.push(scene(0, 0)));
+        detector.reset();
+        assert!(!detector.push(scene(1, 0)));
+        assert!(detector.push(scene(0, 0)));
+    }
+}
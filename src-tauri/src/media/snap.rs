@@ -0,0 +1,120 @@
+use num_traits::ToPrimitive;
+
+use crate::media::units;
+
+/// Below this spread between loud and quiet buckets there is nothing to snap to.
+const MIN_CONTRAST_DB: f32 = 6.0;
+
+#[derive(Clone, Debug, serde::Deserialize, ts_rs::TS)]
+#[serde(rename_all = "camelCase", default)]
+#[ts(export)]
+pub struct SnapOptions {
+    /// how far from each boundary to look for speech onsets and offsets
+    pub window: units::Seconds,
+    /// how much earlier than the onset an entry starts
+    pub lead_in: units::Seconds,
+    /// how much later than the offset an entry ends
+    pub lead_out: units::Seconds,
+    /// where the speech threshold sits between the quiet and loud levels
+    /// around the boundary, from 0 to 1
+    pub sensitivity: f32,
+    /// louder stretches shorter than this are not taken as speech
+    pub min_speech: units::Seconds,
+}
+
+impl Default for SnapOptions {
+    fn default() -> Self {
+        Self {
+            window: units::Seconds(0.5),
+            lead_in: units::Seconds(0.1),
+            lead_out: units::Seconds(0.2),
+            sensitivity: 0.3,
+            min_speech: units::Seconds(0.1),
+        }
+    }
+}
+
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+    let i = (p * (sorted.len() - 1).to_f32().unwrap()).round().to_usize().unwrap();
+    sorted[i]
+}
+
+/// Finds the speech onset (or offset if `!onset`) nearest to `center` in a
+/// stretch of bucket levels in dB, with NaN for buckets not sampled. Returns
+/// the index of the first bucket of speech (or silence) after the edge.
+pub fn find_edge(
+    db: &[f32], center: usize, onset: bool, min_run: usize, sensitivity: f32
+) -> Option<usize> {
+    let mut sorted: Vec<f32> = db.iter().copied().filter(|x| x.is_finite()).collect();
+    if sorted.len() < 2 {
+        return None;
+    }
+    sorted.sort_by(f32::total_cmp);
+    let floor = percentile(&sorted, 0.1);
+    let peak = percentile(&sorted, 0.9);
+    if peak - floor < MIN_CONTRAST_DB {
+        return None;
+    }
+    let threshold = floor + sensitivity * (peak - floor);
+
+    let mut speech: Vec<bool> = db.iter().map(|&x| x > threshold).collect();
+    // drop clicks and other short bursts
+    let mut i = 0;
+    while i < speech.len() {
+        if !speech[i] {
+            i += 1;
+            continue;
+        }
+        let run = speech[i..].iter().take_while(|&&x| x).count();
+        if run < min_run {
+            speech[i..i + run].fill(false);
+        }
+        i += run;
+    }
+
+    (1..db.len())
+        .filter(|&i| db[i - 1].is_finite() && db[i].is_finite())
+        .filter(|&i| speech[i] == onset && speech[i - 1] != onset)
+        .min_by_key(|&i| i.abs_diff(center))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(pattern: &str) -> Vec<f32> {
+        pattern.chars().map(|c| match c {
+            '#' => -10.0,
+            '.' => -50.0,
+            _ => f32::NAN,
+        }).collect()
+    }
+
+    #[test]
+    fn test_nearest_edges() {
+        let db = levels("....######......######....");
+        assert_eq!(find_edge(&db, 0, true, 1, 0.3), Some(4));
+        assert_eq!(find_edge(&db, 14, true, 1, 0.3), Some(16));
+        assert_eq!(find_edge(&db, 12, false, 1, 0.3), Some(10));
+        assert_eq!(find_edge(&db, 25, false, 1, 0.3), Some(22));
+    }
+
+    #[test]
+    fn test_short_bursts_ignored() {
+        let db = levels("....#.........######....");
+        assert_eq!(find_edge(&db, 4, true, 3, 0.3), Some(14));
+    }
+
+    #[test]
+    fn test_no_contrast_or_data() {
+        assert_eq!(find_edge(&levels("............"), 5, true, 1, 0.3), None);
+        assert_eq!(find_edge(&levels("????????????"), 5, true, 1, 0.3), None);
+    }
+
+    #[test]
+    fn test_unknown_buckets_are_not_edges() {
+        let db = levels("....????######....");
+        assert_eq!(find_edge(&db, 6, true, 1, 0.3), None);
+        assert_eq!(find_edge(&db, 6, false, 1, 0.3), Some(14));
+    }
+}
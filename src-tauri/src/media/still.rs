@@ -0,0 +1,101 @@
+use ffmpeg::{codec, encoder, format, software::scaling, Packet};
+use num_traits::ToPrimitive;
+
+use crate::media::{color::{Colorimetry, RgbaConverter}, frame, internal::{check, MediaError}, units::Rational};
+
+/// `FF_QP2LAMBDA`: converts a quantizer to the lambda of `AVFrame::quality`.
+const QP2LAMBDA: i32 = 118;
+/// JPEG quantizer; 2 is close to the best quality and still compact.
+const JPEG_QSCALE: i32 = 2;
+
+#[derive(Clone, Copy, Debug, serde::Deserialize, ts_rs::TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+}
+
+/// The size to encode a frame of `size` pixels at: `width` and `height` if
+/// both are given, the other one following the display aspect ratio if only
+/// one is, or the display size at the original height if neither is.
+pub fn output_size(
+    size: (u32, u32), sample_aspect_ratio: Rational,
+    width: Option<u32>, height: Option<u32>
+) -> (u32, u32) {
+    let sar: f64 = sample_aspect_ratio.into();
+    let aspect = f64::from(size.0) * sar / f64::from(size.1.max(1));
+    let round = |x: f64| x.round().max(1.0).to_u32().unwrap();
+    match (width, height) {
+        (Some(w), Some(h)) => (w.max(1), h.max(1)),
+        (Some(w), None) => (w.max(1), round(f64::from(w) / aspect)),
+        (None, Some(h)) => (round(f64::from(h) * aspect), h.max(1)),
+        (None, None) => (round(f64::from(size.1) * aspect), size.1.max(1)),
+    }
+}
+
+/// Scales `frame` to `size` and encodes it as a single image.
+pub fn encode(
+    frame: &frame::VideoData, size: (u32, u32), format: ImageFormat
+) -> Result<Vec<u8>, MediaError> {
+    let (codec_id, pixel) = match format {
+        ImageFormat::Png => (codec::Id::PNG, format::Pixel::RGBA),
+        ImageFormat::Jpeg => (codec::Id::MJPEG, format::Pixel::YUVJ420P),
+    };
+
+    // go through RGB first so that the colors are those the player shows
+    let mut converter = RgbaConverter::new(
+        frame.format(), (frame.width(), frame.height()), Colorimetry::of(frame),
+        size, scaling::Flags::BICUBIC)?;
+    let mut scaled = frame::VideoData::empty();
+    converter.run(frame, &mut scaled)?;
+    if pixel != format::Pixel::RGBA {
+        let mut scaler = check!(scaling::Context::get(
+            format::Pixel::RGBA, size.0, size.1,
+            pixel, size.0, size.1,
+            scaling::Flags::POINT,
+        ))?;
+        let mut converted = frame::VideoData::empty();
+        check!(scaler.run(&scaled, &mut converted))?;
+        scaled = converted;
+    }
+    scaled.set_pts(Some(0));
+
+    let codec = encoder::find(codec_id).ok_or(
+        MediaError::InternalError(format!("encoder not found: {codec_id:?}")))?;
+    let mut context = check!(codec::Context::new_with_codec(codec).encoder().video())?;
+    context.set_width(size.0);
+    context.set_height(size.1);
+    context.set_format(pixel);
+    context.set_time_base(Rational(1, 1));
+    if let ImageFormat::Jpeg = format {
+        context.set_flags(codec::Flags::QSCALE);
+        context.set_quality((JPEG_QSCALE * QP2LAMBDA).to_usize().unwrap());
+        // the encoder takes the quantizer from the frame
+        unsafe { (*scaled.as_mut_ptr()).quality = JPEG_QSCALE * QP2LAMBDA };
+    }
+    let mut encoder = check!(context.open_as(codec))?;
+
+    check!(encoder.send_frame(&scaled))?;
+    check!(encoder.send_eof())?;
+    let mut packet = Packet::empty();
+    check!(encoder.receive_packet(&mut packet))?;
+    Ok(packet.data().map(<[u8]>::to_vec).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_size() {
+        let square = Rational(1, 1);
+        assert_eq!(output_size((1920, 1080), square, None, None), (1920, 1080));
+        assert_eq!(output_size((1920, 1080), square, Some(640), None), (640, 360));
+        assert_eq!(output_size((1920, 1080), square, None, Some(540)), (960, 540));
+        assert_eq!(output_size((1920, 1080), square, Some(10), Some(20)), (10, 20));
+        // anamorphic DVD
+        assert_eq!(output_size((720, 480), Rational(32, 27), None, None), (853, 480));
+        assert_eq!(output_size((720, 480), Rational(32, 27), Some(320), None), (320, 180));
+    }
+}
@@ -1,20 +1,38 @@
 use std::{collections::VecDeque, slice};
 
+use base64::prelude::*;
 use ffmpeg::codec;
 use ffmpeg::codec::subtitle;
 use ffmpeg::error::EAGAIN;
+use ffmpeg::frame::side_data;
+use ffmpeg_sys_next::AVDiscard;
 use getset::{CopyGetters, Getters};
 use log::warn;
+use num_traits::ToPrimitive;
 
-use crate::media::{demux, internal::{MediaError, check}, units};
+use crate::media::{cea608, cea708, demux, frame, internal::{MediaError, check}, units};
+
+pub use crate::media::ass::{AssEvent, AssHeader};
+pub use crate::media::ocr::{recognize, OcrOptions};
 
 #[derive(Clone, Debug, serde::Serialize, ts_rs::TS)]
 #[serde(rename = "BackendSubtitleRect", rename_all = "camelCase", tag = "type")]
 #[ts(export)]
 pub enum SubtitleRect {
     // Note: SRT decodes into ASS in ffmpeg
-    Ass { content: String },
+    Ass(AssEvent),
     Text { content: String },
+    /// As in PGS, VobSub and DVB; placed on a frame of the video's size
+    Bitmap {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        /// RGBA with the palette applied, rows without padding; base64 in JSON
+        #[serde(serialize_with = "serialize_base64")]
+        #[ts(type = "string")]
+        data: Vec<u8>,
+    },
     Unsupported,
 }
 
@@ -25,23 +43,110 @@ pub struct SubtitleEntry {
     pub start: units::Seconds,
     pub end: units::Seconds,
     pub rects: Vec<SubtitleRect>,
+    /// how sure OCR is of the text, from 0 to 1, if it came from there
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub confidence: Option<f32>,
+}
+
+/// A service of the A/53 closed captions in a video stream. Each one a video
+/// stream carries is listed as a subtitle stream of its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptionService {
+    /// CEA-608 CC1 to CC4
+    Cea608(u8),
+    /// CEA-708 service 1, the primary caption service; the secondary and
+    /// private ones aren't looked for
+    Cea708,
+}
+
+impl CaptionService {
+    const ALL: [CaptionService; 5] = [
+        Self::Cea608(1), Self::Cea608(2), Self::Cea608(3), Self::Cea608(4), Self::Cea708
+    ];
+
+    fn decoder(self) -> CaptionDecoder {
+        match self {
+            Self::Cea608(cc) => CaptionDecoder::Cea608(cea608::Decoder::new(cc)),
+            Self::Cea708 => CaptionDecoder::Cea708(cea708::Decoder::new(1)),
+        }
+    }
+
+    /// The description of the service as the subtitle stream `index`.
+    pub fn describe(self, index: usize) -> demux::StreamDescription {
+        let name = match self {
+            Self::Cea608(cc) => format!("eia_608 CC{cc}"),
+            Self::Cea708 => "eia_708 service 1".to_owned(),
+        };
+        demux::StreamDescription::closed_captions(index, name)
+    }
+}
+
+enum CaptionDecoder {
+    Cea608(cea608::Decoder),
+    Cea708(cea708::Decoder),
+}
+
+impl CaptionDecoder {
+    fn feed_a53(&mut self, time: units::Seconds, data: &[u8]) {
+        match self {
+            Self::Cea608(x) => x.feed_a53(time, data),
+            Self::Cea708(x) => x.feed_a53(time, data),
+        }
+    }
+
+    fn take(&mut self) -> Vec<cea608::Caption> {
+        match self {
+            Self::Cea608(x) => x.take(),
+            Self::Cea708(x) => x.take(),
+        }
+    }
+
+    fn finish(&mut self, time: units::Seconds) -> Vec<cea608::Caption> {
+        match self {
+            Self::Cea608(x) => x.finish(time),
+            Self::Cea708(x) => x.finish(time),
+        }
+    }
+}
+
+enum Source {
+    Stream(codec::decoder::Subtitle),
+    /// A/53 closed captions in the frames of a video stream
+    Captions {
+        video: codec::decoder::Video,
+        service: CaptionService,
+        captions: CaptionDecoder,
+    },
 }
 
 #[derive(Getters, CopyGetters)]
 pub struct Decoder {
-    inner: codec::decoder::Subtitle,
+    source: Source,
 
     header: Option<String>,
     entries: VecDeque<SubtitleEntry>,
+    /// an entry that lasts until the next one starts, as in PGS, where an
+    /// empty subtitle clears the screen
+    open: Option<SubtitleEntry>,
 
     #[getset(get = "pub")]
     stream_info: demux::StreamInfo,
 }
 
 impl Decoder {
+    /// An `index` past the last stream means a closed caption service of a
+    /// video stream; see `caption_stream_index`.
     pub fn create(
         demuxer: &demux::Demuxer, index: Option<usize>
     ) -> Result<Decoder, MediaError> {
+        if let Some(i) = index
+            && let Some(offset) = i.checked_sub(demuxer.stream_count())
+        {
+            let n = CaptionService::ALL.len();
+            return Self::create_captions(demuxer, offset / n, CaptionService::ALL[offset % n]);
+        }
+
         let (stream_info, stream) = match index {
             Some(i) => demuxer.get_stream_from_index(i),
             None => demuxer.get_stream_from_kind(demux::StreamKind::Subtitle)
@@ -64,14 +169,58 @@ impl Decoder {
             }
         };
 
-        Ok(Decoder { inner: decoder, stream_info, entries: VecDeque::new(), header })
+        Ok(Decoder {
+            source: Source::Stream(decoder), stream_info,
+            entries: VecDeque::new(), open: None,
+            header
+        })
+    }
+
+    fn create_captions(
+        demuxer: &demux::Demuxer, video_index: usize, service: CaptionService
+    ) -> Result<Decoder, MediaError> {
+        let (stream_info, stream) = demuxer.get_stream_from_index(video_index)?;
+        Ok(Decoder {
+            source: Source::Captions {
+                video: open_caption_decoder(&stream)?,
+                service,
+                captions: service.decoder(),
+            },
+            stream_info,
+            entries: VecDeque::new(), open: None,
+            header: None
+        })
     }
 
     pub fn flush(&mut self) {
-        self.inner.flush();
+        match &mut self.source {
+            Source::Stream(x) => x.flush(),
+            Source::Captions { video, service, captions } => {
+                video.flush();
+                *captions = service.decoder();
+            }
+        }
         self.stream_info.byte_pos_can_update = true;
         self.stream_info.byte_pos = -1;
         self.entries.clear();
+        self.open = None;
+    }
+
+    /// Ends the entry left open at the end of the stream, `end`.
+    pub fn finish(&mut self, end: units::Seconds) {
+        let timebase = self.stream_info.timebase();
+        if let Source::Captions { video, captions, .. } = &mut self.source {
+            // an error here only means that there is nothing left
+            if video.send_eof().is_ok() {
+                receive_captions(video, captions, timebase);
+            }
+            let captions = captions.finish(end);
+            self.push_captions(captions);
+        }
+        if let Some(mut entry) = self.open.take() {
+            entry.end = units::Seconds(end.0.max(entry.start.0));
+            self.entries.push_back(entry);
+        }
     }
 
     pub fn feed(&mut self, packet: &demux::Packet) -> Result<(), MediaError> {
@@ -79,8 +228,23 @@ impl Decoder {
             self.stream_info.byte_pos = packet.position();
         }
 
+        let timebase = self.stream_info.timebase();
+        let inner = match &mut self.source {
+            Source::Stream(x) => x,
+            Source::Captions { video, captions, .. } => {
+                // a broken packet only loses the captions of a frame or so
+                if let Err(e) = video.send_packet(packet) {
+                    warn!("subtitles::Decoder::feed: video: {e}");
+                }
+                receive_captions(video, captions, timebase);
+                let captions = captions.take();
+                self.push_captions(captions);
+                return Ok(());
+            }
+        };
+
         let mut decoded = subtitle::Subtitle::new();
-        let got = check!(self.inner.decode(packet, &mut decoded)
+        let got = check!(inner.decode(packet, &mut decoded)
             .or_else(|e| match e {
                 ffmpeg::Error::Other { errno: EAGAIN } => {
                     warn!("subtitles::Decoder::feed: EAGAIN (unexpected)");
@@ -117,20 +281,39 @@ impl Decoder {
             units::Seconds(pts_seconds + duration_seconds)
         ) };
 
-        let rects = decoded.rects().map(|rect| match rect {
-            subtitle::Rect::Ass(a) =>
-                SubtitleRect::Ass { content: a.get().to_owned() },
+        let rects: Vec<_> = decoded.rects().map(|rect| match rect {
+            subtitle::Rect::Ass(a) => match AssEvent::parse(a.get()) {
+                Some(event) => SubtitleRect::Ass(event),
+                None => {
+                    // keep the text rather than lose the whole line
+                    warn!("subtitles::Decoder::feed: invalid ASS line: {}", a.get());
+                    SubtitleRect::Text { content: a.get().to_owned() }
+                }
+            },
             subtitle::Rect::Text(t) =>
                 SubtitleRect::Text { content: t.get().to_owned() },
+            subtitle::Rect::Bitmap(b) => bitmap_rect(&b),
             _ => SubtitleRect::Unsupported,
         }).collect();
 
-        self.entries.push_back(SubtitleEntry { start, end, rects });
+        if let Some(mut previous) = self.open.take() {
+            previous.end = units::Seconds(previous.end.0.min(start.0));
+            self.entries.push_back(previous);
+        }
+        let entry = SubtitleEntry { start, end, rects, confidence: None };
+        // no end time given; an empty one only ends the previous
+        if decoded.end() == u32::MAX {
+            if !entry.rects.is_empty() {
+                self.open = Some(entry);
+            }
+        } else {
+            self.entries.push_back(entry);
+        }
         Ok(())
     }
 
-    pub fn header(&self) -> Option<String> {
-        self.header.clone()
+    pub fn header(&self) -> Option<AssHeader> {
+        self.header.as_deref().map(AssHeader::parse)
     }
 
     #[allow(dead_code)]
@@ -141,4 +324,137 @@ impl Decoder {
     pub fn get_delta(&mut self) -> VecDeque<SubtitleEntry> {
         std::mem::take(&mut self.entries)
     }
+
+    fn push_captions(&mut self, captions: Vec<cea608::Caption>) {
+        self.entries.extend(captions.into_iter().map(|c| SubtitleEntry {
+            start: c.start,
+            end: c.end,
+            rects: vec![SubtitleRect::Text { content: c.text }],
+            confidence: None,
+        }));
+    }
+}
+
+/// Feeds the captions in the frames `video` has ready to `captions`.
+fn receive_captions(
+    video: &mut codec::decoder::Video, captions: &mut CaptionDecoder, timebase: units::Rational
+) {
+    let mut frame = frame::VideoData::empty();
+    while video.receive_frame(&mut frame).is_ok() {
+        let Some(pts) = frame.pts().or(frame.timestamp()) else { continue };
+        if let Some(data) = frame.side_data(side_data::Type::A53CC) {
+            captions.feed_a53(units::Timestamp(pts).to_seconds(timebase), data.data());
+        }
+    }
+}
+
+/// The index by which `Decoder::create` opens a closed caption service of a
+/// video stream.
+pub fn caption_stream_index(
+    demuxer: &demux::Demuxer, video_index: usize, service: CaptionService
+) -> usize {
+    let offset = CaptionService::ALL.iter().position(|&x| x == service).unwrap();
+    demuxer.stream_count() + video_index * CaptionService::ALL.len() + offset
+}
+
+/// Opens a decoder for a video stream that is only after side data, so it
+/// skips what it can of making pictures.
+fn open_caption_decoder(stream: &ffmpeg::Stream) -> Result<codec::decoder::Video, MediaError> {
+    let mut context = check!(codec::Context::from_parameters(stream.parameters()))?;
+    context.set_threading(codec::threading::Config {
+        kind: codec::threading::Type::Frame,
+        count: num_cpus::get().min(16),
+    });
+    unsafe {
+        let ctx = context.as_mut_ptr();
+        (*ctx).skip_loop_filter = AVDiscard::AVDISCARD_ALL;
+        (*ctx).skip_idct = AVDiscard::AVDISCARD_ALL;
+    }
+    check!(context.decoder().video())
+}
+
+/// The closed caption services a video stream carries in its first `limit`
+/// of time, or anywhere if `None`. Opens the file on its own, so that nothing
+/// open is disturbed; `progress` is told how far it is, from 0 to 1.
+pub fn probe_captions(
+    path: &std::path::Path, index: usize, limit: Option<units::Seconds>,
+    mut progress: impl FnMut(f64)
+) -> Result<Vec<CaptionService>, MediaError> {
+    let mut demuxer = demux::Demuxer::open(path)?;
+    let (info, stream) = demuxer.get_stream_from_index(index)?;
+    let mut video = open_caption_decoder(&stream)?;
+    let end = limit.map(|x| x.0 + info.start_time_seconds().0);
+    let size = demuxer.byte_size().to_f64().unwrap().max(1.0);
+    let mut found = Vec::new();
+    let mut packets = cea708::Packets::default();
+    let mut frame = frame::VideoData::empty();
+    'packets: while let Some((i, packet)) = demuxer.next_packet() {
+        if i != index || video.send_packet(&packet).is_err() {
+            continue;
+        }
+        if let Some(pos) = packet.position().to_f64()
+            && pos >= 0.0
+        {
+            progress(pos / size);
+        }
+        while video.receive_frame(&mut frame).is_ok() {
+            if let Some(data) = frame.side_data(side_data::Type::A53CC) {
+                let data = data.data();
+                found.extend(cea608::caption_channels(data).map(CaptionService::Cea608));
+                packets.feed_a53(data, |service, block| {
+                    if service == 1 && !block.is_empty() {
+                        found.push(CaptionService::Cea708);
+                    }
+                });
+                found.sort_by_key(|&x| CaptionService::ALL.iter().position(|&y| y == x));
+                found.dedup();
+                if found.len() == CaptionService::ALL.len() {
+                    break 'packets;
+                }
+            }
+            let time = frame.pts().or(frame.timestamp())
+                .map(|x| units::Timestamp(x).to_seconds(info.timebase()).0);
+            if let (Some(time), Some(end)) = (time, end)
+                && time >= end
+            {
+                break 'packets;
+            }
+        }
+    }
+    Ok(found)
+}
+
+fn bitmap_rect(rect: &subtitle::Bitmap) -> SubtitleRect {
+    // ffmpeg_next doesn't export the pixels or the palette
+    let (x, y, width, height, rgba) = unsafe {
+        let raw = &*rect.as_ptr();
+        let (width, height) = (raw.w.to_usize().unwrap(), raw.h.to_usize().unwrap());
+        let stride = raw.linesize[0].to_usize().unwrap();
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        if width > 0 && height > 0 && !raw.data[0].is_null() && !raw.data[1].is_null() {
+            let indices = slice::from_raw_parts(raw.data[0], stride * height);
+            // AV_PIX_FMT_RGB32, i.e. ARGB in native endianness
+            let palette = slice::from_raw_parts(
+                raw.data[1].cast::<u32>(), raw.nb_colors.to_usize().unwrap());
+            for row in indices.chunks(stride) {
+                for &i in &row[..width] {
+                    let [a, r, g, b] = palette.get(usize::from(i))
+                        .copied().unwrap_or(0).to_be_bytes();
+                    rgba.extend_from_slice(&[r, g, b, a]);
+                }
+            }
+        }
+        (raw.x, raw.y, raw.w, raw.h, rgba)
+    };
+    SubtitleRect::Bitmap {
+        x: x.to_u32().unwrap_or(0),
+        y: y.to_u32().unwrap_or(0),
+        width: width.to_u32().unwrap_or(0),
+        height: height.to_u32().unwrap_or(0),
+        data: rgba,
+    }
+}
+
+fn serialize_base64<S: serde::Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&BASE64_STANDARD.encode(data))
 }
@@ -0,0 +1,217 @@
+use num_traits::ToPrimitive;
+
+use crate::media::{fft::Fft, internal::MediaError, units};
+
+/// Resolution of the on/off patterns being correlated.
+const STEP: f64 = 0.01;
+
+/// Frame rate conversions a subtitle file commonly went through.
+const COMMON_SCALES: [f64; 7] = [
+    1.0,
+    25.0 / 23.976, 23.976 / 25.0,
+    24.0 / 23.976, 23.976 / 24.0,
+    25.0 / 24.0, 24.0 / 25.0,
+];
+
+#[derive(Clone, Debug, serde::Deserialize, ts_rs::TS)]
+#[serde(rename_all = "camelCase", default)]
+#[ts(export)]
+pub struct SyncOptions {
+    /// largest offset to consider in either direction
+    pub max_offset: units::Seconds,
+    /// scale factors to try; common frame rate conversions if `None`
+    pub scales: Option<Vec<f64>>,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            max_offset: units::Seconds(600.0),
+            scales: None,
+        }
+    }
+}
+
+impl SyncOptions {
+    pub fn validate(&self) -> Result<(), MediaError> {
+        if !(self.max_offset.0.is_finite() && self.max_offset.0 >= 0.0) {
+            return Err(MediaError::InternalError(
+                format!("sync: invalid max_offset {}", self.max_offset.0)));
+        }
+        if let Some(x) = self.scales.iter().flatten().find(|x| !(x.is_finite() && **x > 0.0)) {
+            return Err(MediaError::InternalError(format!("sync: invalid scale {x}")));
+        }
+        Ok(())
+    }
+}
+
+/// Subtitles fit the audio best when every time `t` becomes
+/// `t * scale + offset`.
+#[derive(Clone, serde::Serialize, Debug, ts_rs::TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct SyncEstimate {
+    pub offset: units::Seconds,
+    pub scale: f64,
+    /// fraction of the subtitle timeline whose on/off state agrees with the
+    /// speech after the correction, from 0 to 1
+    pub agreement: f64,
+    /// how far the best alignment stands out from a typical one, from 0 to 1
+    pub confidence: f64,
+}
+
+/// `+1` where any interval covers the slot, `-1` elsewhere.
+fn rasterize(intervals: &[(f64, f64)], len: usize) -> Vec<f32> {
+    let mut raster = vec![-1.0; len];
+    for &(start, end) in intervals {
+        let first = (start / STEP).round().max(0.0).to_usize().unwrap().min(len);
+        let last = (end / STEP).round().max(0.0).to_usize().unwrap().min(len);
+        if first < last {
+            raster[first..last].fill(1.0);
+        }
+    }
+    raster
+}
+
+/// `result[lag]` is the sum over `k` of `a[k + lag] * b[k]`, for lags from 0
+/// up to `a.len()` and, wrapped around at the end, down to `-b.len()`.
+fn cross_correlate(fft: &Fft, a: &[f32], b: &[f32]) -> Vec<f32> {
+    let n = fft.size();
+    let mut fa: Vec<(f32, f32)> = (0..n).map(|i| (a.get(i).copied().unwrap_or(0.0), 0.0)).collect();
+    let mut fb: Vec<(f32, f32)> = (0..n).map(|i| (b.get(i).copied().unwrap_or(0.0), 0.0)).collect();
+    fft.transform(&mut fa);
+    fft.transform(&mut fb);
+
+    // A · conj(B), then the inverse transform as conj(fft(conj(x))) / n
+    let mut product: Vec<(f32, f32)> = fa.iter().zip(&fb)
+        .map(|(&(ar, ai), &(br, bi))| (ar * br + ai * bi, -(ai * br - ar * bi)))
+        .collect();
+    fft.transform(&mut product);
+    let scale = n.to_f32().unwrap();
+    product.iter().map(|&(re, _)| re / scale).collect()
+}
+
+/// Finds the scale and offset that best line up the on/off pattern of
+/// `entries` with `speech`, both as `(start, end)` in seconds. `duration` is
+/// how long the audio is. Returns `None` if there is nothing to correlate.
+pub fn estimate(
+    speech: &[(f64, f64)], entries: &[(f64, f64)], duration: f64, options: &SyncOptions
+) -> Result<Option<SyncEstimate>, MediaError> {
+    options.validate()?;
+    let subtitle_end = entries.iter().map(|x| x.1).fold(0.0, f64::max);
+    if speech.is_empty() || entries.is_empty() || duration <= 0.0 || subtitle_end <= 0.0 {
+        return Ok(None);
+    }
+    let scales = options.scales.as_deref().unwrap_or(&COMMON_SCALES);
+    let max_scale = scales.iter().copied().fold(1.0, f64::max);
+
+    let speech_len = (duration / STEP).ceil().to_usize().unwrap();
+    let subtitle_len = (subtitle_end * max_scale / STEP).ceil().to_usize().unwrap();
+    let fft = Fft::new((speech_len + subtitle_len).next_power_of_two());
+    let n = fft.size();
+    let max_lag = (options.max_offset.0 / STEP).round().to_usize().unwrap();
+
+    let speech_raster = rasterize(speech, speech_len);
+    let mut best: Option<(SyncEstimate, f32, usize)> = None;
+    // median correlation and raster length for each scale
+    let mut typical = Vec::new();
+
+    for (i, &scale) in scales.iter().enumerate() {
+        let scaled: Vec<(f64, f64)> =
+            entries.iter().map(|&(a, b)| (a * scale, b * scale)).collect();
+        let len = (subtitle_end * scale / STEP).ceil().to_usize().unwrap();
+        let corr = cross_correlate(&fft, &speech_raster, &rasterize(&scaled, len));
+
+        // lag L means subtitle slot k lines up with speech slot k + L
+        let lags = (0..=max_lag.min(speech_len))
+            .map(|l| (l.to_f64().unwrap(), corr[l]))
+            .chain((1..=max_lag.min(len)).map(|l| (-l.to_f64().unwrap(), corr[n - l])));
+        let mut values = Vec::new();
+        for (lag, value) in lags {
+            values.push(value);
+            if best.as_ref().is_none_or(|(_, v, _)| value > *v) {
+                let agreement = (f64::from(value) / len.to_f64().unwrap() + 1.0) / 2.0;
+                best = Some((SyncEstimate {
+                    offset: units::Seconds(lag * STEP),
+                    scale,
+                    agreement: agreement.clamp(0.0, 1.0),
+                    confidence: 0.0,
+                }, value, i));
+            }
+        }
+        values.sort_by(f32::total_cmp);
+        typical.push((values[values.len() / 2], len));
+    }
+
+    let Some((mut estimate, value, i)) = best else { return Ok(None) };
+    let (median, len) = typical[i];
+    let len = len.to_f32().unwrap();
+    // the best possible value is `len`, when everything agrees
+    estimate.confidence = f64::from(((value - median) / (len - median).max(1.0)).clamp(0.0, 1.0));
+    Ok(Some(estimate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::testing::Random;
+
+    /// irregular speech-like on/off pattern
+    fn speech(duration: f64) -> Vec<(f64, f64)> {
+        let mut random = Random(12345);
+        let mut t = 1.0;
+        let mut result = Vec::new();
+        while t < duration - 5.0 {
+            let len = 0.5 + 3.0 * random.unit();
+            result.push((t, t + len));
+            t += len + 0.3 + 2.0 * random.unit();
+        }
+        result
+    }
+
+    fn retime(x: &[(f64, f64)], scale: f64, offset: f64) -> Vec<(f64, f64)> {
+        x.iter().map(|&(a, b)| ((a - offset) / scale, (b - offset) / scale)).collect()
+    }
+
+    #[test]
+    fn test_pure_offset() {
+        let speech = speech(300.0);
+        let entries = retime(&speech, 1.0, 4.2);
+        let e = estimate(&speech, &entries, 300.0, &SyncOptions::default()).unwrap().unwrap();
+        assert!((e.offset.0 - 4.2).abs() < 0.02, "{e:?}");
+        assert!((e.scale - 1.0).abs() < 1e-9, "{e:?}");
+        assert!(e.agreement > 0.95 && e.confidence > 0.8, "{e:?}");
+    }
+
+    #[test]
+    fn test_frame_rate_drift() {
+        let speech = speech(600.0);
+        let scale = 25.0 / 23.976;
+        let entries = retime(&speech, scale, 1.5);
+        let e = estimate(&speech, &entries, 600.0, &SyncOptions::default()).unwrap().unwrap();
+        assert!((e.scale - scale).abs() < 1e-9, "{e:?}");
+        assert!((e.offset.0 - 1.5).abs() < 0.03, "{e:?}");
+    }
+
+    #[test]
+    fn test_unrelated_is_not_confident() {
+        let speech = speech(300.0);
+        let entries: Vec<_> = (0..60)
+            .map(|i| (f64::from(i) * 5.0, f64::from(i) * 5.0 + 2.5))
+            .collect();
+        let e = estimate(&speech, &entries, 300.0, &SyncOptions::default()).unwrap().unwrap();
+        assert!(e.confidence < 0.5, "{e:?}");
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let x = [(1.0, 2.0)];
+        let invalid = [
+            SyncOptions { max_offset: units::Seconds(f64::NAN), scales: None },
+            SyncOptions { scales: Some(vec![0.0]), ..SyncOptions::default() },
+        ];
+        for options in &invalid {
+            assert!(estimate(&x, &x, 10.0, options).is_err(), "{options:?}");
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! Helpers for the tests of the signal processing modules.
+
+use num_traits::ToPrimitive;
+
+/// A linear congruential generator, so that a test sees the same noise
+/// every time it runs.
+pub struct Random(pub u32);
+
+impl Random {
+    pub fn next(&mut self) -> u32 {
+        self.0 = self.0.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        self.0
+    }
+
+    /// uniform in [0, 1)
+    pub fn unit(&mut self) -> f64 {
+        f64::from(self.next() >> 8) / f64::from(1 << 24)
+    }
+
+    /// white noise, uniform in [-amplitude, amplitude)
+    pub fn noise(&mut self, len: usize, amplitude: f32) -> Vec<f32> {
+        (0..len).map(|_| amplitude * (self.unit() * 2.0 - 1.0).to_f32().unwrap()).collect()
+    }
+}
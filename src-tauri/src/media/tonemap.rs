@@ -0,0 +1,187 @@
+use num_traits::ToPrimitive;
+
+/// Luminance of SDR white in HDR content, per ITU-R BT.2408.
+const SDR_WHITE_NITS: f32 = 203.0;
+/// Assumed peak of HDR content; most is mastered for 1000 nits, and it's the
+/// nominal peak of HLG.
+const PEAK_NITS: f32 = 1000.0;
+/// Output below this, relative to SDR white, is left as it is; above it the
+/// curve starts rolling off towards the peak.
+const KNEE: f32 = 0.75;
+const ENCODE_STEPS: usize = 4096;
+
+/// BT.2020 to BT.709 primaries, in linear light.
+const BT2020_TO_BT709: [[f32; 3]; 3] = [
+    [ 1.6605, -0.5876, -0.0728],
+    [-0.1246,  1.1329, -0.0083],
+    [-0.0182, -0.1006,  1.1187],
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transfer {
+    /// SMPTE ST 2084, as in HDR10
+    Pq,
+    /// ARIB STD-B67
+    Hlg,
+}
+
+/// The PQ EOTF: a signal from 0 to 1 to luminance in nits.
+pub fn pq_eotf(e: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+    let p = e.clamp(0.0, 1.0).powf(1.0 / M2);
+    10000.0 * ((p - C1).max(0.0) / (C2 - C3 * p)).powf(1.0 / M1)
+}
+
+/// The inverse of the HLG OETF: a signal from 0 to 1 to relative scene light.
+pub fn hlg_inverse_oetf(e: f32) -> f32 {
+    const A: f32 = 0.178_832_77;
+    const B: f32 = 1.0 - 4.0 * A;
+    const C: f32 = 0.559_910_7;
+    let e = e.clamp(0.0, 1.0);
+    if e <= 0.5 {
+        e * e / 3.0
+    } else {
+        (((e - C) / A).exp() + B) / 12.0
+    }
+}
+
+/// Compresses `x`, linear light relative to SDR white, so that `peak` comes
+/// out as 1. Identity below `KNEE`, then an extended Reinhard curve that
+/// joins it smoothly.
+pub fn roll_off(x: f32, peak: f32) -> f32 {
+    if x <= KNEE || peak <= 1.0 {
+        return x.min(1.0);
+    }
+    let y = (x - KNEE) / (1.0 - KNEE);
+    let white = (peak - KNEE) / (1.0 - KNEE);
+    let y = y.min(white);
+    KNEE + (1.0 - KNEE) * y * (1.0 + y / (white * white)) / (1.0 + y)
+}
+
+/// Turns 16-bit HDR RGB, as decoded from PQ or HLG video, into 8-bit SDR
+/// BT.709 RGB.
+pub struct Tonemapper {
+    transfer: Transfer,
+    /// linear light by 16-bit code: display light relative to SDR white for
+    /// PQ, scene light from 0 to 1 for HLG
+    linear: Vec<f32>,
+    /// converts from BT.2020 primaries, if they are those of the source
+    gamut: Option<[[f32; 3]; 3]>,
+    peak: f32,
+    /// BT.1886 encoding of linear light from 0 to 1
+    encode: Vec<u8>,
+}
+
+impl Tonemapper {
+    pub fn new(transfer: Transfer, bt2020: bool) -> Self {
+        let linear = (0..=u16::MAX)
+            .map(|c| {
+                let e = f32::from(c) / f32::from(u16::MAX);
+                match transfer {
+                    Transfer::Pq => pq_eotf(e) / SDR_WHITE_NITS,
+                    Transfer::Hlg => hlg_inverse_oetf(e),
+                }
+            })
+            .collect();
+        let steps = (ENCODE_STEPS - 1).to_f32().unwrap();
+        let encode = (0..ENCODE_STEPS)
+            .map(|i| {
+                let x = i.to_f32().unwrap() / steps;
+                (x.powf(1.0 / 2.4) * 255.0).round().to_u8().unwrap()
+            })
+            .collect();
+        Self {
+            transfer,
+            linear,
+            gamut: bt2020.then_some(BT2020_TO_BT709),
+            peak: PEAK_NITS / SDR_WHITE_NITS,
+            encode,
+        }
+    }
+
+    pub fn map(&self, rgb: [u16; 3]) -> [u8; 3] {
+        let mut x = rgb.map(|c| self.linear[usize::from(c)]);
+        if self.transfer == Transfer::Hlg {
+            // the HLG OOTF with a system gamma of 1.2, for a 1000-nit display
+            let ys = 0.2627 * x[0] + 0.6780 * x[1] + 0.0593 * x[2];
+            let gain = PEAK_NITS / SDR_WHITE_NITS * ys.max(0.0).powf(0.2);
+            x = x.map(|c| c * gain);
+        }
+        if let Some(m) = &self.gamut {
+            x = m.map(|row| (row[0] * x[0] + row[1] * x[1] + row[2] * x[2]).max(0.0));
+        }
+        // scaling all channels alike keeps the hue
+        let max = x[0].max(x[1]).max(x[2]);
+        if max > 0.0 {
+            let scale = roll_off(max, self.peak) / max;
+            x = x.map(|c| c * scale);
+        }
+        let steps = (ENCODE_STEPS - 1).to_f32().unwrap();
+        x.map(|c| self.encode[(c.clamp(0.0, 1.0) * steps).round().to_usize().unwrap()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: f32, b: f32, epsilon: f32) {
+        assert!((a - b).abs() <= epsilon, "{a} is not {b}");
+    }
+
+    #[test]
+    fn test_transfer_functions() {
+        assert_near(pq_eotf(0.0), 0.0, 1e-6);
+        assert_near(pq_eotf(1.0), 10000.0, 1.0);
+        assert_near(pq_eotf(0.508), 100.0, 1.0);
+        assert_near(pq_eotf(0.7518), 1000.0, 5.0);
+        assert_near(hlg_inverse_oetf(0.5), 1.0 / 12.0, 1e-6);
+        assert_near(hlg_inverse_oetf(1.0), 1.0, 1e-4);
+    }
+
+    #[test]
+    fn test_roll_off() {
+        let peak = PEAK_NITS / SDR_WHITE_NITS;
+        assert_near(roll_off(0.0, peak), 0.0, 1e-6);
+        assert_near(roll_off(0.5, peak), 0.5, 1e-6);
+        assert_near(roll_off(peak, peak), 1.0, 1e-5);
+        assert_near(roll_off(2.0 * peak, peak), 1.0, 1e-5);
+        let mut last = 0.0;
+        for i in 1..=100 {
+            let y = roll_off(i.to_f32().unwrap() * peak / 100.0, peak);
+            assert!(y > last);
+            last = y;
+        }
+    }
+
+    #[test]
+    fn test_map() {
+        let pq = Tonemapper::new(Transfer::Pq, true);
+        let code = |nits: f32| {
+            let e = (0..=u16::MAX).find(|&c| pq_eotf(f32::from(c) / 65535.0) >= nits).unwrap();
+            [e; 3]
+        };
+        assert_eq!(pq.map([0; 3]), [0; 3]);
+        // neutral stays neutral, and brighter stays brighter
+        let white = pq.map(code(SDR_WHITE_NITS));
+        assert!(white[0] == white[1] && white[1] == white[2]);
+        assert!(white[0] > 200);
+        assert!(pq.map(code(100.0))[0] < white[0]);
+        assert!(pq.map(code(1000.0))[0] > white[0]);
+        assert_eq!(pq.map(code(1000.0)), [255; 3]);
+        // saturated BT.2020 green is out of the BT.709 gamut
+        let green = pq.map([0, code(SDR_WHITE_NITS)[0], 0]);
+        assert_eq!(green[0], 0);
+        assert!(green[1] > 200);
+
+        let hlg = Tonemapper::new(Transfer::Hlg, true);
+        assert_eq!(hlg.map([0; 3]), [0; 3]);
+        // 75% HLG is the reference white
+        let white = hlg.map([(0.75 * 65535.0f32).round().to_u16().unwrap(); 3]);
+        assert!(white[0] > 200 && white[0] < 255);
+    }
+}
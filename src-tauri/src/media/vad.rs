@@ -0,0 +1,253 @@
+use num_traits::ToPrimitive;
+
+use crate::media::{fft::Fft, units};
+
+/// Analysis frames are about this long, rounded up to a power of two samples.
+const FRAME_SECONDS: f64 = 0.032;
+/// Below this level a frame is never speech, however quiet the background.
+const ABSOLUTE_FLOOR_DB: f32 = -60.0;
+/// How fast the noise floor estimate may rise, in dB per frame.
+const NOISE_RISE_DB: f32 = 0.02;
+/// Band holding most of the energy of voiced speech.
+const SPEECH_BAND: (f64, f64) = (300.0, 3400.0);
+
+#[derive(Clone, Debug, serde::Deserialize, ts_rs::TS)]
+#[serde(rename_all = "camelCase", default)]
+#[ts(export)]
+pub struct SpeechDetectionOptions {
+    /// frames whose speech probability exceeds this start or continue a segment
+    pub threshold: f32,
+    /// segments shorter than this are dropped
+    pub min_speech: units::Seconds,
+    /// gaps shorter than this do not split a segment
+    pub min_silence: units::Seconds,
+}
+
+impl Default for SpeechDetectionOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 0.5,
+            min_speech: units::Seconds(0.25),
+            min_silence: units::Seconds(0.3),
+        }
+    }
+}
+
+#[derive(Clone, serde::Serialize, Debug, ts_rs::TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct SpeechSegment {
+    pub start: units::Seconds,
+    pub end: units::Seconds,
+    /// mean speech probability of the frames in the segment, from 0 to 1
+    pub confidence: f32,
+}
+
+/// Finds speech in a mono signal from the frame energy relative to a tracked
+/// noise floor, how much of the spectrum lies in the speech band, and how
+/// far the spectrum is from flat noise.
+pub struct Detector {
+    options: SpeechDetectionOptions,
+    fft: Fft,
+    sample_rate: f64,
+    band: (usize, usize),
+
+    /// samples not yet analyzed; `pending_time` is the time of the first one
+    pending: Vec<f32>,
+    pending_time: f64,
+    noise_db: Option<f32>,
+
+    /// the segment being built: start time and probabilities so far
+    current: Option<(f64, Vec<f32>)>,
+    /// time of the last frame that was above the threshold
+    last_speech: f64,
+    segments: Vec<SpeechSegment>,
+}
+
+impl Detector {
+    pub fn new(sample_rate: f64, options: SpeechDetectionOptions) -> Self {
+        let size = (sample_rate * FRAME_SECONDS).to_usize().unwrap()
+            .next_power_of_two()
+            .max(2);
+        let bin_of = |freq: f64| {
+            (freq * size.to_f64().unwrap() / sample_rate).round()
+                .to_usize().unwrap()
+                .clamp(1, size / 2)
+        };
+        Self {
+            options,
+            fft: Fft::new(size),
+            sample_rate,
+            band: (bin_of(SPEECH_BAND.0), bin_of(SPEECH_BAND.1)),
+            pending: Vec::new(),
+            pending_time: 0.0,
+            noise_db: None,
+            current: None,
+            last_speech: 0.0,
+            segments: Vec::new(),
+        }
+    }
+
+    fn hop(&self) -> usize {
+        self.fft.size() / 2
+    }
+
+    /// Feeds samples starting at `time`. A jump in time drops the samples
+    /// still pending, but keeps the noise estimate and the open segment.
+    pub fn feed(&mut self, time: units::Seconds, samples: &[f32]) {
+        let expected = self.pending_time
+            + self.pending.len().to_f64().unwrap() / self.sample_rate;
+        let hop_seconds = self.hop().to_f64().unwrap() / self.sample_rate;
+        if self.pending.is_empty() || (time.0 - expected).abs() > hop_seconds {
+            self.pending.clear();
+            self.pending_time = time.0;
+        }
+        self.pending.extend_from_slice(samples);
+
+        let size = self.fft.size();
+        let hop = self.hop();
+        let mut pending = std::mem::take(&mut self.pending);
+        let mut consumed = 0;
+        while pending.len() - consumed >= size {
+            let time = self.pending_time
+                + consumed.to_f64().unwrap() / self.sample_rate;
+            let p = self.probability(&pending[consumed..consumed + size]);
+            self.push_frame(time, p);
+            consumed += hop;
+        }
+        pending.drain(..consumed);
+        self.pending = pending;
+        self.pending_time += consumed.to_f64().unwrap() / self.sample_rate;
+    }
+
+    /// Speech probability of one frame; also updates the noise floor.
+    fn probability(&mut self, frame: &[f32]) -> f32 {
+        let mean_square = frame.iter().map(|x| x * x).sum::<f32>()
+            / frame.len().to_f32().unwrap();
+        let energy_db = 10.0 * (mean_square + 1e-12).log10();
+
+        let noise_db = match self.noise_db {
+            Some(n) if energy_db > n => n + NOISE_RISE_DB,
+            _ => energy_db,
+        };
+        self.noise_db = Some(noise_db);
+        if energy_db < ABSOLUTE_FLOOR_DB {
+            return 0.0;
+        }
+
+        let power: Vec<f32> = self.fft.magnitudes(frame)
+            .iter()
+            .map(|x| x * x + 1e-12)
+            .collect();
+        let (lo, hi) = self.band;
+        let total: f32 = power[1..].iter().sum();
+        let band = &power[lo..hi];
+        let band_ratio = band.iter().sum::<f32>() / total;
+
+        let n = band.len().to_f32().unwrap();
+        let log_mean = band.iter().map(|x| x.ln()).sum::<f32>() / n;
+        let flatness = log_mean.exp() / (band.iter().sum::<f32>() / n);
+
+        let snr = energy_db - noise_db;
+        let score = (snr - 6.0) / 3.0
+            + (band_ratio - 0.5) / 0.15
+            + (0.3 - flatness) / 0.1;
+        1.0 / (1.0 + (-score).exp())
+    }
+
+    fn push_frame(&mut self, time: f64, p: f32) {
+        let frame_seconds = self.hop().to_f64().unwrap() / self.sample_rate;
+        let is_speech = p > self.options.threshold;
+
+        if let Some((_, probabilities)) = &mut self.current {
+            if is_speech || time - self.last_speech < self.options.min_silence.0 {
+                probabilities.push(p);
+            } else {
+                self.close_segment(self.last_speech + frame_seconds);
+            }
+        } else if is_speech {
+            self.current = Some((time, vec![p]));
+        }
+        if is_speech {
+            self.last_speech = time;
+        }
+    }
+
+    fn close_segment(&mut self, end: f64) {
+        let Some((start, probabilities)) = self.current.take() else { return };
+        if end - start < self.options.min_speech.0 {
+            return;
+        }
+        // the hangover frames after the last speech frame don't count
+        let frame_seconds = self.hop().to_f64().unwrap() / self.sample_rate;
+        let n = ((end - start) / frame_seconds).round().to_usize().unwrap()
+            .clamp(1, probabilities.len());
+        let confidence = probabilities[..n].iter().sum::<f32>() / n.to_f32().unwrap();
+        self.segments.push(SpeechSegment {
+            start: units::Seconds(start),
+            end: units::Seconds(end),
+            confidence,
+        });
+    }
+
+    /// Closes the open segment and returns everything found.
+    pub fn finish(&mut self) -> Vec<SpeechSegment> {
+        let frame_seconds = self.hop().to_f64().unwrap() / self.sample_rate;
+        self.close_segment(self.last_speech + frame_seconds);
+        std::mem::take(&mut self.segments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+    use crate::media::testing::Random;
+
+    const RATE: f64 = 16000.0;
+
+    /// a crude vowel: harmonics of a 150 Hz voice, shaped towards the speech band
+    fn vowel(len: usize, amplitude: f32) -> Vec<f32> {
+        (0..len).map(|i| {
+            let t = i.to_f32().unwrap() / RATE.to_f32().unwrap();
+            (1..20).map(|k| {
+                let f = 150.0 * k.to_f32().unwrap();
+                let gain = if (300.0..3400.0).contains(&f) { 1.0 } else { 0.2 };
+                gain * (2.0 * PI * f * t).sin()
+            }).sum::<f32>() * amplitude / 10.0
+        }).collect()
+    }
+
+    fn detect(signal: &[f32]) -> Vec<SpeechSegment> {
+        let mut detector = Detector::new(RATE, SpeechDetectionOptions::default());
+        for (i, chunk) in signal.chunks(1024).enumerate() {
+            let time = (i * 1024).to_f64().unwrap() / RATE;
+            detector.feed(units::Seconds(time), chunk);
+        }
+        detector.finish()
+    }
+
+    #[test]
+    fn test_vowel_in_noise() {
+        let second = RATE.to_usize().unwrap();
+        let mut signal = Random(1).noise(3 * second, 0.01);
+        let speech = vowel(second, 0.3);
+        for (x, y) in signal[second..2 * second].iter_mut().zip(speech) {
+            *x += y;
+        }
+
+        let segments = detect(&signal);
+        assert_eq!(segments.len(), 1, "{segments:?}");
+        let s = &segments[0];
+        assert!((s.start.0 - 1.0).abs() < 0.1, "{s:?}");
+        assert!((s.end.0 - 2.0).abs() < 0.1, "{s:?}");
+        assert!(s.confidence > 0.8, "{s:?}");
+    }
+
+    #[test]
+    fn test_silence_and_noise() {
+        assert!(detect(&[0.0; 32000]).is_empty());
+        assert!(detect(&Random(7).noise(32000, 0.2)).is_empty());
+    }
+}
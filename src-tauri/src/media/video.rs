@@ -1,11 +1,16 @@
-use std::collections::{BTreeMap, VecDeque};
+use std::{collections::{BTreeMap, BTreeSet, VecDeque}, sync::Arc};
 
 use enum_dispatch::enum_dispatch;
 use ffmpeg::{codec, decoder, error::EAGAIN, format, software::scaling, Rescale};
 use getset::{CopyGetters, Getters};
 use log::{debug, warn};
+use num_traits::ToPrimitive;
 
-use crate::media::{accel, demux, disjoint_interval_set::DisjointIntervalSet, frame, internal::{MediaError, check}, units::{Seconds, Timestamp, Rational, DEFAULT_TIMEBASE}};
+use crate::media::{accel, cache, color::{Colorimetry, RgbaConverter}, deinterlace::Deinterlacer, demux, disjoint_interval_set::DisjointIntervalSet, frame, frame_cache::FrameCache, shots, internal::{MediaError, check}, units::{Seconds, Timestamp, Rational, DEFAULT_TIMEBASE}};
+
+pub use crate::media::deinterlace::DeinterlaceMode;
+pub use crate::media::keyframes::{parse as parse_keyframes, write as write_keyframes, KeyframeFormat};
+pub use crate::media::still::{encode as encode_still, output_size as still_size, ImageFormat};
 
 #[derive(Getters, CopyGetters)]
 pub struct Decoder {
@@ -143,6 +148,11 @@ impl Decoder {
         }
     }
 
+    /// Makes the decoder give out the frames it still holds.
+    pub fn send_eof(&mut self) -> Result<(), MediaError> {
+        check!(self.inner.send_eof())
+    }
+
     pub fn try_receive(&mut self) -> Result<Option<frame::Video>, MediaError> {
         let mut decoded = frame::VideoData::empty();
         let mut byte_pos: isize = -1;
@@ -184,7 +194,8 @@ impl Decoder {
 #[enum_dispatch]
 pub enum VideoSinkKind {
     Player,
-    Sampler
+    Sampler,
+    Thumbnailer
 }
 
 #[enum_dispatch(VideoSinkKind)]
@@ -194,38 +205,38 @@ pub trait VideoSink {
     fn process(&mut self, frame: frame::Video) -> Result<(), MediaError>;
 }
 
+/// How many bytes of scaled frames `Player` keeps for scrubbing and stepping
+/// backwards.
+const FRAME_CACHE_BYTES: usize = 256 * 1024 * 1024;
+
 pub struct Player {
-    original_format: format::Pixel,
-    original_size: (u32, u32),
-    output_size: (u32, u32),
-    scaling_method: scaling::Flags,
-    scaler: scaling::Context,
+    converter: RgbaConverter,
+    deinterlacer: Deinterlacer,
+
+    /// scaled frames, shared with `cache`
+    frames: VecDeque<Arc<frame::Video>>,
 
-    frames: VecDeque<frame::Video>
+    /// Every frame scaled, including those skipped over while seeking. It
+    /// survives `clear`, so that going back to a place seen before doesn't
+    /// have to decode it again.
+    cache: FrameCache<Arc<frame::Video>>,
 }
 
 impl VideoSink for Player {
     fn clear(&mut self) {
         self.frames.clear();
+        self.deinterlacer.reset();
+        self.cache.break_sequence();
     }
 
     fn is_empty(&self) -> bool {
         self.frames.is_empty()
     }
 
-    fn process(&mut self, mut frame: frame::Video) -> Result<(), MediaError> {
-        if frame.decoded.format() != self.original_format {
-            warn!("decoded format is actually {:?}", frame.decoded.format());
-            self.original_format = frame.decoded.format();
-            self.create_scaler()?;
+    fn process(&mut self, frame: frame::Video) -> Result<(), MediaError> {
+        for (frame, shown) in self.deinterlacer.push(frame, true)? {
+            self.output(frame, shown)?;
         }
-
-        // av_frame_alloc
-        let mut processed = frame::VideoData::empty();
-        // sws_scale
-        check!(self.scaler.run(&frame.decoded, &mut processed))?;
-        frame.decoded = processed;
-        self.frames.push_back(frame);
         Ok(())
     }
 }
@@ -239,61 +250,160 @@ impl Player {
         };
 
         let (w, h) = (decoder.inner.width(), decoder.inner.height());
-        let scaling_method = scaling::Flags::FAST_BILINEAR;
+        let display_width = w
+            .rescale(Rational(1, 1), decoder.sample_aspect_ratio())
+            .try_into()
+            .unwrap();
 
         Ok(Self {
-            original_format: format,
-            original_size: (w, h),
-            output_size: (w, h),
-            scaling_method,
-            scaler: check!(scaling::Context::get(
-                format, w, h,
-                format::Pixel::RGBA,
-                decoder.inner.width()
-                    .rescale(Rational(1, 1), decoder.sample_aspect_ratio())
-                    .try_into()
-                    .unwrap(),
-                h,
-                scaling_method,
-            ))?,
-            frames: VecDeque::new()
+            converter: RgbaConverter::new(
+                format, (w, h), Colorimetry::of_decoder(&decoder.inner),
+                (display_width, h), scaling::Flags::FAST_BILINEAR,
+            )?,
+            deinterlacer: Deinterlacer::new(DeinterlaceMode::Auto),
+            frames: VecDeque::new(),
+            cache: FrameCache::new(FRAME_CACHE_BYTES),
         })
     }
 
-    pub fn get_delta(&mut self) -> VecDeque<frame::Video> {
+    pub fn get_delta(&mut self) -> VecDeque<Arc<frame::Video>> {
         std::mem::take(&mut self.frames)
     }
 
+    /// Time of the earliest frame not yet taken.
+    pub fn first_time(&self) -> Option<Seconds> {
+        self.frames.front().map(|f| f.meta.time)
+    }
+
+    /// Scales a frame that is skipped over and keeps it in the cache only.
+    pub fn skip(&mut self, frame: frame::Video) -> Result<(), MediaError> {
+        for (frame, shown) in self.deinterlacer.push(frame, false)? {
+            self.output(frame, shown)?;
+        }
+        Ok(())
+    }
+
+    /// Takes out the frame the deinterlacer holds back, at the end of the
+    /// file.
+    pub fn finish(&mut self) -> Result<(), MediaError> {
+        for (frame, shown) in self.deinterlacer.finish()? {
+            self.output(frame, shown)?;
+        }
+        Ok(())
+    }
+
+    fn output(&mut self, frame: frame::Video, shown: bool) -> Result<(), MediaError> {
+        let frame = Arc::new(self.scale(frame)?);
+        if shown {
+            self.frames.push_back(Arc::clone(&frame));
+        }
+        self.insert_cache(frame);
+        Ok(())
+    }
+
+    /// Queues up to `count` consecutive cached frames, starting
+    /// with the one shown at `time`. Returns a time within the frame after
+    /// them, or `None` if the cache doesn't have the frame at `time`.
+    pub fn replay_cached(&mut self, time: Seconds, count: usize) -> Option<Seconds> {
+        let mut at = Timestamp::from_seconds(time, DEFAULT_TIMEBASE);
+        let mut replayed = false;
+        for _ in 0..count {
+            let Some((frame, _, next)) = self.cache.get(at) else { break };
+            self.frames.push_back(Arc::clone(frame));
+            at = next;
+            replayed = true;
+        }
+        // keys are truncated to microseconds, so one more falls within the
+        // next frame whatever its exact time
+        replayed.then(|| Timestamp(at.0 + 1).to_seconds(DEFAULT_TIMEBASE))
+    }
+
+    /// Drops the first queued frame if it starts before `time`, unless it's
+    /// the only one, which is then the last frame before `time` to show.
+    pub fn drop_first_before(&mut self, time: Seconds) {
+        if self.frames.len() > 1 && self.frames.front().is_some_and(|f| f.meta.time < time) {
+            self.frames.pop_front();
+        }
+    }
+
+    fn insert_cache(&mut self, frame: Arc<frame::Video>) {
+        let time = Timestamp::from_seconds(frame.meta.time, DEFAULT_TIMEBASE);
+        let bytes = frame.decoded.data(0).len();
+        self.cache.insert(time, frame, bytes);
+    }
+
+    fn scale(&mut self, mut frame: frame::Video) -> Result<frame::Video, MediaError> {
+        if frame.decoded.format() != self.converter.input_format() {
+            warn!("decoded format is actually {:?}", frame.decoded.format());
+        }
+
+        // av_frame_alloc
+        let mut processed = frame::VideoData::empty();
+        // sws_scale, and tonemapping if needed
+        self.converter.run(&frame.decoded, &mut processed)?;
+        frame.decoded = processed;
+        Ok(frame)
+    }
+
     pub fn set_output_size(&mut self, size: (u32, u32)) -> Result<(), MediaError> {
-        if self.output_size == size {
+        if self.converter.output_size() == size {
             return Ok(());
         }
 
-        self.output_size = size;
-        self.create_scaler()?;
-        debug!("set_output_size: {:?}, format {:?}", size, self.original_format);
+        self.converter.set_output_size(size)?;
+        self.cache.clear();
+        debug!("set_output_size: {:?}, format {:?}", size, self.converter.input_format());
         Ok(())
     }
 
-    fn create_scaler(&mut self) -> Result<(), MediaError> {
-        self.scaler = check!(scaling::Context::get(
-            self.original_format,
-            self.original_size.0,
-            self.original_size.1,
-            format::Pixel::RGBA,
-            self.output_size.0,
-            self.output_size.1,
-            self.scaling_method,
-        ))?;
-        Ok(())
+    pub fn set_deinterlace(&mut self, mode: DeinterlaceMode) {
+        if self.deinterlacer.mode() == mode {
+            return;
+        }
+
+        self.deinterlacer.set_mode(mode);
+        self.cache.clear();
+        debug!("set_deinterlace: {mode:?}");
+    }
+}
+
+/// What `Sampler::index_packets` needs of a packet, which is kept while the
+/// packet itself is dropped.
+#[derive(Clone, Copy)]
+pub struct PacketEntry {
+    timestamp: Timestamp,
+    position: isize,
+    is_key: bool,
+}
+
+impl PacketEntry {
+    /// `None` if the packet has no timestamp at all.
+    pub fn of(packet: &demux::Packet, timebase: Rational) -> Option<Self> {
+        // fall back to DTS if no pts available, like the decoder does
+        let pts = packet.pts().or(packet.dts())?;
+        Some(Self {
+            timestamp: Timestamp::from_seconds(
+                Timestamp(pts).to_seconds(timebase), DEFAULT_TIMEBASE),
+            position: packet.position(),
+            is_key: packet.is_key(),
+        })
     }
 }
 
 pub struct Sampler {
     frames: BTreeMap<Timestamp, isize>,
     keyframes: BTreeMap<Timestamp, isize>,
+    shot_changes: BTreeSet<Timestamp>,
     known_range: DisjointIntervalSet<Timestamp>,
-    data: Option<SamplerDeltaData>
+    data: Option<SamplerDeltaData>,
+
+    /// scales frames down to the grayscale thumbnails the shot detector uses
+    original_format: format::Pixel,
+    original_size: (u32, u32),
+    scaler: scaling::Context,
+    shot_detector: shots::Detector,
+    /// the last frame given to `shot_detector`
+    last_frame: Option<Timestamp>,
 }
 
 #[derive(Clone, serde::Serialize, Debug, ts_rs::TS)]
@@ -302,6 +412,8 @@ pub struct Sampler {
 #[ts(rename = "VideoSamplerDeltaData")]
 pub struct SamplerDeltaData {
     pub keyframes: Vec<(Seconds, isize)>,
+    /// first frames of new shots
+    pub shot_changes: Vec<Seconds>,
     pub start_time: Seconds,
     pub end_time: Seconds
 }
@@ -319,6 +431,7 @@ impl VideoSink for Sampler {
         if self.data.is_none() {
             self.data = Some(SamplerDeltaData {
                 keyframes: Vec::new(),
+                shot_changes: Vec::new(),
                 start_time: frame.meta.time,
                 end_time: frame.meta.time
             });
@@ -326,13 +439,25 @@ impl VideoSink for Sampler {
 
         let timestamp = Timestamp::from_seconds(frame.meta.time, DEFAULT_TIMEBASE);
 
+        // the detector compares with the frame before, so it must not have
+        // skipped any since
+        let previous = self.frames.range(..timestamp).next_back().map(|x| *x.0);
+        if previous.is_none() || previous != self.last_frame {
+            self.shot_detector.reset();
+        }
         self.frames.insert(timestamp, frame.meta.byte_pos);
+        self.last_frame = Some(timestamp);
+        let is_shot_change = self.detect_shot_change(&frame)?;
 
         let sd = self.data.as_mut().unwrap();
         if frame.decoded.is_key() {
             sd.keyframes.push((frame.meta.time, frame.meta.byte_pos));
             self.keyframes.insert(timestamp, frame.meta.byte_pos);
         }
+        if is_shot_change {
+            sd.shot_changes.push(frame.meta.time);
+            self.shot_changes.insert(timestamp);
+        }
         if frame.meta.time > sd.end_time {
             sd.end_time = frame.meta.time;
             self.known_range.add(
@@ -345,20 +470,152 @@ impl VideoSink for Sampler {
 }
 
 impl Sampler {
-    #[allow(clippy::unnecessary_wraps)]
-    pub fn create(_decoder: &Decoder) -> Result<Self, MediaError> {
+    pub fn create(decoder: &Decoder) -> Result<Self, MediaError> {
+        let format = decoder.inner.format();
+        let size = (decoder.inner.width(), decoder.inner.height());
         Ok(Self {
             frames: BTreeMap::new(),
             keyframes: BTreeMap::new(),
+            shot_changes: BTreeSet::new(),
             known_range: DisjointIntervalSet::new(),
-            data: None
+            data: None,
+            original_format: format,
+            original_size: size,
+            scaler: Self::create_scaler(format, size)?,
+            shot_detector: shots::Detector::default(),
+            last_frame: None,
         })
     }
 
+    fn create_scaler(
+        format: format::Pixel, size: (u32, u32)
+    ) -> Result<scaling::Context, MediaError> {
+        check!(scaling::Context::get(
+            format, size.0, size.1,
+            format::Pixel::GRAY8,
+            shots::WIDTH.try_into().unwrap(),
+            shots::HEIGHT.try_into().unwrap(),
+            scaling::Flags::AREA,
+        ))
+    }
+
+    fn detect_shot_change(&mut self, frame: &frame::Video) -> Result<bool, MediaError> {
+        let size = (frame.decoded.width(), frame.decoded.height());
+        if frame.decoded.format() != self.original_format || size != self.original_size {
+            self.original_format = frame.decoded.format();
+            self.original_size = size;
+            self.scaler = Self::create_scaler(self.original_format, size)?;
+        }
+
+        let mut gray = frame::VideoData::empty();
+        check!(self.scaler.run(&frame.decoded, &mut gray))?;
+        let stride = gray.stride(0);
+        let luma = gray.data(0).chunks(stride)
+            .take(shots::HEIGHT)
+            .flat_map(|row| &row[..shots::WIDTH])
+            .copied()
+            .collect();
+        Ok(self.shot_detector.push(luma))
+    }
+
     pub fn get_delta(&mut self) -> Option<SamplerDeltaData> {
         std::mem::take(&mut self.data)
     }
 
+    /// Indexes frames and keyframes from the packets of the sampled stream
+    /// without decoding them. Shot changes can't be found this way.
+    pub fn index_packets(&mut self, packets: &[PacketEntry]) {
+        // packets come in decoding order, so only the whole span is known
+        let mut span: Option<(Timestamp, Timestamp)> = None;
+        for &PacketEntry { timestamp, position, is_key } in packets {
+            self.frames.insert(timestamp, position);
+            if is_key {
+                self.keyframes.insert(timestamp, position);
+            }
+            span = Some(span.map_or((timestamp, timestamp),
+                |(first, last)| (first.min(timestamp), last.max(timestamp))));
+        }
+        if let Some((first, last)) = span {
+            self.known_range.add(first, last);
+        }
+    }
+
+    /// Everything sampled so far, one delta for each contiguous range.
+    pub fn known_deltas(&self) -> Vec<SamplerDeltaData> {
+        self.known_range.iter().map(|(first, last)| SamplerDeltaData {
+            keyframes: self.keyframes.range(first..=last)
+                .map(|(t, &pos)| (t.to_seconds(DEFAULT_TIMEBASE), pos))
+                .collect(),
+            shot_changes: self.shot_changes.range(first..=last)
+                .map(|t| t.to_seconds(DEFAULT_TIMEBASE))
+                .collect(),
+            start_time: first.to_seconds(DEFAULT_TIMEBASE),
+            end_time: last.to_seconds(DEFAULT_TIMEBASE),
+        }).collect()
+    }
+
+    /// Serializes the frame index for `cache::store`.
+    pub fn save(&self) -> Vec<u8> {
+        let mut w = cache::Writer::default();
+        for map in [&self.frames, &self.keyframes] {
+            w.u64(map.len() as u64);
+            for (t, &pos) in map {
+                w.i64(t.0);
+                w.i64(pos as i64);
+            }
+        }
+        let ranges: Vec<_> = self.known_range.iter().collect();
+        w.u64(ranges.len() as u64);
+        for (first, last) in ranges {
+            w.i64(first.0);
+            w.i64(last.0);
+        }
+        w.u64(self.shot_changes.len() as u64);
+        for t in &self.shot_changes {
+            w.i64(t.0);
+        }
+        w.finish()
+    }
+
+    /// Merges in what `save` produced. Returns `false` and changes nothing if
+    /// the data is malformed.
+    pub fn restore(&mut self, data: &[u8]) -> bool {
+        fn read_map(r: &mut cache::Reader) -> Option<Vec<(Timestamp, isize)>> {
+            (0..r.count()?)
+                .map(|_| Some((Timestamp(r.i64()?), isize::try_from(r.i64()?).ok()?)))
+                .collect()
+        }
+
+        let mut r = cache::Reader::new(data);
+        let mut read = || {
+            let frames = read_map(&mut r)?;
+            let keyframes = read_map(&mut r)?;
+            let ranges = (0..r.count()?)
+                .map(|_| Some((Timestamp(r.i64()?), Timestamp(r.i64()?))))
+                .collect::<Option<Vec<_>>>()?;
+            let shot_changes = (0..r.count()?)
+                .map(|_| Some(Timestamp(r.i64()?)))
+                .collect::<Option<Vec<_>>>()?;
+            ranges.iter().all(|(a, b)| a <= b)
+                .then_some((frames, keyframes, ranges, shot_changes))
+        };
+        let Some((frames, keyframes, ranges, shot_changes)) =
+            read() else { return false };
+
+        self.frames.extend(frames);
+        self.keyframes.extend(keyframes);
+        self.shot_changes.extend(shot_changes);
+        for (first, last) in ranges {
+            self.known_range.add(first, last);
+        }
+        true
+    }
+
+    /// Times of all keyframes found so far.
+    pub fn keyframe_times(&self) -> Vec<Seconds> {
+        self.keyframes.keys().map(|t| t.to_seconds(DEFAULT_TIMEBASE)).collect()
+    }
+
     pub fn get_keyframe_before(&self, time: Seconds) -> Option<(Seconds, isize)> {
         let timestamp = Timestamp::from_seconds(time, DEFAULT_TIMEBASE);
         if let Some((&t, &pos)) =
@@ -383,3 +640,103 @@ impl Sampler {
         }
     }
 }
+
+pub struct Thumbnail {
+    pub time: Seconds,
+    pub size: (u32, u32),
+    /// packed RGBA, without padding between rows
+    pub data: Vec<u8>,
+}
+
+/// Shortest thumbnail interval, for streams that don't tell their frame rate.
+const MIN_THUMBNAIL_INTERVAL: f64 = 0.001;
+
+/// Makes small thumbnails of a fixed height, either of the first frame in
+/// each `interval` or of every keyframe.
+pub struct Thumbnailer {
+    converter: RgbaConverter,
+
+    /// keyframes only if `None`
+    interval: Option<Seconds>,
+    /// index of the interval of the last thumbnail
+    last_slot: Option<i64>,
+    thumbnails: VecDeque<Thumbnail>,
+}
+
+impl VideoSink for Thumbnailer {
+    fn clear(&mut self) {
+        self.thumbnails.clear();
+        self.last_slot = None;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.thumbnails.is_empty()
+    }
+
+    fn process(&mut self, frame: frame::Video) -> Result<(), MediaError> {
+        match self.interval {
+            Some(interval) => {
+                // `None` only for times out of range, which get a thumbnail each
+                let slot = (frame.meta.time.0 / interval.0).floor().to_i64();
+                if slot.is_some() && self.last_slot == slot {
+                    return Ok(());
+                }
+                self.last_slot = slot;
+            },
+            None => if !frame.decoded.is_key() {
+                return Ok(());
+            },
+        }
+
+        let mut scaled = frame::VideoData::empty();
+        self.converter.run(&frame.decoded, &mut scaled)?;
+        let output_size = self.converter.output_size();
+        let row = output_size.0.to_usize().unwrap() * 4;
+        let data = scaled.data(0).chunks(scaled.stride(0))
+            .take(output_size.1.to_usize().unwrap())
+            .flat_map(|x| &x[..row])
+            .copied()
+            .collect();
+        self.thumbnails.push_back(Thumbnail {
+            time: frame.meta.time,
+            size: output_size,
+            data,
+        });
+        Ok(())
+    }
+}
+
+impl Thumbnailer {
+    /// Thumbnails are `height` pixels high and as wide as the display aspect
+    /// ratio demands. An `interval` shorter than a frame counts as a frame.
+    pub fn create(
+        decoder: &Decoder, height: u32, interval: Option<Seconds>
+    ) -> Result<Self, MediaError> {
+        let format = decoder.inner.format();
+        let (w, h) = (decoder.inner.width(), decoder.inner.height());
+        let sar: f64 = decoder.sample_aspect_ratio().into();
+        let width = (f64::from(w) * sar * f64::from(height) / f64::from(h.max(1)))
+            .round().max(1.0);
+        let output_size = (width.to_u32().unwrap(), height.max(1));
+        let framerate: f64 = decoder.framerate().into();
+        let min_interval = if framerate.is_finite() && framerate > 0.0 {
+            1.0 / framerate
+        } else {
+            MIN_THUMBNAIL_INTERVAL
+        };
+
+        Ok(Self {
+            converter: RgbaConverter::new(
+                format, (w, h), Colorimetry::of_decoder(&decoder.inner),
+                output_size, scaling::Flags::AREA,
+            )?,
+            interval: interval.filter(|x| x.0 > 0.0).map(|x| Seconds(x.0.max(min_interval))),
+            last_slot: None,
+            thumbnails: VecDeque::new(),
+        })
+    }
+
+    pub fn get_delta(&mut self) -> VecDeque<Thumbnail> {
+        std::mem::take(&mut self.thumbnails)
+    }
+}
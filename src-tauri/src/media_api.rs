@@ -7,11 +7,13 @@ use crate::media::internal::MediaError;
 use crate::media::video::{VideoSink, VideoSinkKind};
 use crate::media::{accel, audio, demux, frame, session, subtitles, units, video};
 
+use ffmpeg::ChannelLayout;
 use log::warn;
 use num_traits::ToPrimitive;
 use serde::Serialize;
 use std::collections::VecDeque;
 use std::{collections::HashMap};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tauri::ipc::{self, Channel};
@@ -20,15 +22,24 @@ use tauri::{async_runtime, State};
 pub struct PlaybackRegistry {
     next_id: i32,
     table: HashMap<i32, session::Session>,
+    /// where sessions persist their sampler state
+    cache_dir: Option<PathBuf>,
 }
 
 impl PlaybackRegistry {
-    pub fn new() -> PlaybackRegistry {
+    pub fn new(cache_dir: Option<PathBuf>) -> PlaybackRegistry {
         PlaybackRegistry {
             next_id: 0,
             table: HashMap::new(),
+            cache_dir,
         }
     }
+
+    /// The file of session `id`. Long work opens a session of its own on it,
+    /// so as not to hold the registry, and with it every other command.
+    fn path_of(&self, id: i32) -> Option<PathBuf> {
+        self.table.get(&id).map(|s| s.demuxer().path().to_owned())
+    }
 }
 
 #[derive(Clone, Serialize, Debug, ts_rs::TS)]
@@ -49,7 +60,8 @@ pub enum MediaEvent<'a> {
         index: usize,
         length: usize,
         start_time: units::Seconds,
-        sample_rate: u32
+        sample_rate: u32,
+        channels: u32,
     },
     #[serde(rename_all = "camelCase")]
     VideoStatus {
@@ -82,16 +94,52 @@ pub enum MediaEvent<'a> {
     #[serde(rename_all = "camelCase")]
     SampleDone {
         audio: Option<audio::SamplerDeltaData>,
+        spectrogram: Option<audio::SpectrogramDeltaData>,
         video: Option<video::SamplerDeltaData>,
         is_eof: bool
     },
     #[serde(rename_all = "camelCase")]
+    SpeechSegments {
+        segments: Vec<audio::SpeechSegment>,
+    },
+    #[serde(rename_all = "camelCase")]
+    SyncEstimate {
+        estimate: audio::SyncEstimate,
+    },
+    #[serde(rename_all = "camelCase")]
+    AudioAlignment {
+        segments: Vec<audio::AlignedSegment>,
+    },
+    #[serde(rename_all = "camelCase")]
+    SnappedEntries {
+        entries: Vec<(units::Seconds, units::Seconds)>,
+    },
+    #[serde(rename_all = "camelCase")]
+    SampledData {
+        audio: Vec<audio::SamplerDeltaData>,
+        video: Vec<video::SamplerDeltaData>,
+    },
+    #[serde(rename_all = "camelCase")]
+    AudioWaveform {
+        waveform: audio::WaveformData,
+    },
+    #[serde(rename_all = "camelCase")]
+    Keyframes {
+        keyframes: Vec<units::Seconds>,
+    },
+    #[serde(rename_all = "camelCase")]
     Progress {
         value: f64
     },
     #[serde(rename_all = "camelCase")]
+    ClosedCaptions {
+        streams: Vec<demux::StreamDescription>,
+    },
+    #[serde(rename_all = "camelCase")]
     SubtitleData {
-        header: Option<String>,
+        /// the stream it's from
+        index: usize,
+        header: Option<subtitles::AssHeader>,
         entries: Vec<subtitles::SubtitleEntry>,
     },
 }
@@ -156,7 +204,7 @@ pub fn media_status(id: i32, state: State<Arc<Mutex<PlaybackRegistry>>>, channel
             audio_index,
             video_index,
             duration: session.demuxer().duration(),
-            streams: session.demuxer().describe_streams(),
+            streams: session.describe_streams(),
         },
     );
 }
@@ -179,6 +227,22 @@ pub fn video_set_size(
     }
 }
 
+#[tauri::command]
+pub fn video_set_deinterlace(
+    id: i32, mode: video::DeinterlaceMode,
+    state: State<Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent>,
+) {
+    let mut ap = state.lock().unwrap();
+    let Some(session) =
+        ap.table.get_mut(&id) else { return send_invalid_id(&channel) };
+    let Some((_, VideoSinkKind::Player(c))) =
+        session.video_mut() else { return send(&channel, MediaEvent::NoStream {}) };
+
+    c.set_deinterlace(mode);
+    send_done(&channel);
+}
+
 #[tauri::command]
 pub fn close_media(id: i32, state: State<Arc<Mutex<PlaybackRegistry>>>, channel: Channel<MediaEvent>) {
     let mut ap = state.lock().unwrap();
@@ -193,7 +257,9 @@ pub fn open_media(state: State<Arc<Mutex<PlaybackRegistry>>>, path: &str, channe
     let mut ap = state.lock().unwrap();
     send(&channel, MediaEvent::Debug { message: path });
 
-    let session = match session::Session::create(std::path::Path::new(path)) {
+    let session = match session::Session::create(
+        std::path::Path::new(path), ap.cache_dir.clone()
+    ) {
         Ok(x) => x,
         Err(e) => return send_error!(&channel, e.to_string()),
     };
@@ -234,6 +300,39 @@ pub fn open_video(
     send_done(&channel);
 }
 
+/// Opens a video sink making thumbnails `height` pixels high, one for each
+/// `interval` or, if that is `None`, one for each keyframe. They are collected
+/// while `sample_automatic` runs and fetched with `get_thumbnails`.
+#[tauri::command]
+#[allow(clippy::cast_sign_loss)]
+pub fn open_thumbnailer(
+    id: i32, video_id: i32, height: u32, interval: Option<units::Seconds>,
+    state: State<Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent>,
+) {
+    let mut ap = state.lock().unwrap();
+    let Some(session) =
+        ap.table.get_mut(&id) else { return send_invalid_id(&channel) };
+
+    let index = (video_id > 0).then_some(video_id as usize);
+    let (d, _) = match session.open_thumbnailer(index, height, interval) {
+        Ok(()) => session.video().unwrap(),
+        Err(e) => return send_error!(&channel, e.to_string()),
+    };
+
+    log::debug!("open_thumbnailer: {id} {video_id} {height} {interval:?}");
+
+    send(&channel, MediaEvent::VideoStatus {
+        index: d.stream_info().index(),
+        framerate: d.framerate().into(),
+        is_vfr: d.is_vfr(),
+        start_time: d.stream_info().start_time_seconds(),
+        sample_aspect_ratio: d.sample_aspect_ratio().into(),
+        size: d.original_size()
+    });
+    send_done(&channel);
+}
+
 #[tauri::command]
 #[allow(clippy::cast_sign_loss)]
 pub fn open_video_sampler(
@@ -266,8 +365,6 @@ pub fn open_video_sampler(
 
 #[tauri::command]
 #[allow(clippy::cast_sign_loss)]
-#[allow(clippy::cast_precision_loss)]
-#[allow(clippy::cast_possible_truncation)]
 pub async fn extract_subtitles(
     id: i32, sub_id: i32,
     state: State<'_, Arc<Mutex<PlaybackRegistry>>>,
@@ -282,38 +379,402 @@ pub async fn extract_subtitles(
             ap.table.get_mut(&id) else { return send_invalid_id(&channel) };
 
         let index = (sub_id > 0).then_some(sub_id as usize);
-        if let Err(e) = session.open_subtitles_decoder(index) {
-            return send_error!(&channel, e.to_string());
+        match decode_subtitles(session, index, (0.0, 1.0), &channel) {
+            Ok((index, entries, header)) =>
+                send(&channel, MediaEvent::SubtitleData { index, entries, header }),
+            Err(e) => send_error!(&channel, e.to_string()),
         }
+    })
+    .await
+    .map_err(|_| ())
+}
 
-        if let Err(e) = session.seek(units::Seconds(0.0)) {
-            return send_error!(&channel, e.to_string());
+/// Looks for closed captions in the first `duration` of each video stream,
+/// or in all of it, and lists the services found as subtitle streams from
+/// then on: CEA-608 CC1 to CC4, and CEA-708 service 1.
+/// The file is read on its own, without holding up other commands. Sends the
+/// descriptions of the streams found.
+#[tauri::command]
+#[allow(clippy::cast_precision_loss)]
+#[allow(clippy::cast_possible_truncation)]
+pub async fn find_closed_captions(
+    id: i32, duration: Option<units::Seconds>,
+    state: State<'_, Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent<'static>>,
+) -> Result<(), ()> {
+    let state = Arc::clone(&state);
+    let channel = channel.clone();
+
+    async_runtime::spawn_blocking(move || {
+        let (path, videos) = {
+            let ap = state.lock().unwrap();
+            let Some(session) =
+                ap.table.get(&id) else { return send_invalid_id(&channel) };
+            let demuxer = session.demuxer();
+            (demuxer.path().to_owned(), demuxer.stream_indices(demux::StreamKind::Video))
+        };
+
+        let mut found = Vec::new();
+        let mut percentage = 0;
+        for (n, &index) in videos.iter().enumerate() {
+            let probed = subtitles::probe_captions(&path, index, duration, |x| {
+                let value = (n as f64 + x) / videos.len() as f64;
+                if (value * 100.0) as i32 > percentage {
+                    percentage = (value * 100.0) as i32;
+                    send(&channel, MediaEvent::Progress { value });
+                }
+            });
+            match probed {
+                Ok(services) => found.extend(services.into_iter().map(|s| (index, s))),
+                Err(e) => return send_error!(&channel, e.to_string()),
+            }
         }
 
-        let size = session.demuxer().byte_size() as f64;
+        let mut ap = state.lock().unwrap();
+        let Some(session) =
+            ap.table.get_mut(&id) else { return send_invalid_id(&channel) };
+        let streams = session.add_caption_streams(&found);
+        send(&channel, MediaEvent::ClosedCaptions { streams });
+    })
+    .await
+    .map_err(|_| ())
+}
+
+/// Extracts several subtitle streams, `sub_ids` or all of them, reading the
+/// file once. Sends the data of each stream, then `Done`.
+#[tauri::command]
+#[allow(clippy::cast_possible_truncation)]
+pub async fn extract_subtitle_streams(
+    id: i32, sub_ids: Option<Vec<usize>>,
+    state: State<'_, Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent<'static>>,
+) -> Result<(), ()> {
+    let state = Arc::clone(&state);
+    let channel = channel.clone();
+
+    async_runtime::spawn_blocking(move || {
+        let mut ap = state.lock().unwrap();
+        let Some(session) =
+            ap.table.get_mut(&id) else { return send_invalid_id(&channel) };
+
         let mut percentage = 0;
+        let decoded = session.decode_subtitle_streams(sub_ids.as_deref(), |value| {
+            if (value * 100.0) as i32 > percentage {
+                percentage = (value * 100.0) as i32;
+                send(&channel, MediaEvent::Progress { value });
+            }
+        });
+        match decoded {
+            Ok(decoders) => {
+                for (index, mut d) in decoders {
+                    send(&channel, MediaEvent::SubtitleData {
+                        index,
+                        entries: d.get_delta().into(),
+                        header: d.header(),
+                    });
+                }
+                send_done(&channel);
+            }
+            Err(e) => send_error!(&channel, e.to_string()),
+        }
+    })
+    .await
+    .map_err(|_| ())
+}
 
-        loop {
-            match session.try_feed() {
-                Ok(false) => break,
-                Ok(true) => {
-                    let pos = session.subtitles().unwrap().stream_info().byte_pos;
-                    let progress = (pos as f64) / size;
-                    if (progress as i32) > percentage {
-                        percentage = progress as i32;
-                        send(&channel, MediaEvent::Progress { value: progress });
-                    }
-                },
+/// Extracts a bitmap subtitle stream, such as PGS or VobSub, and reads the
+/// text in it with OCR. The media isn't locked while reading.
+#[tauri::command]
+#[allow(clippy::cast_sign_loss)]
+#[allow(clippy::cast_possible_truncation)]
+pub async fn recognize_subtitles(
+    id: i32, sub_id: i32, options: subtitles::OcrOptions,
+    state: State<'_, Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent<'static>>,
+) -> Result<(), ()> {
+    let state = Arc::clone(&state);
+    let channel = channel.clone();
+
+    async_runtime::spawn_blocking(move || {
+        let extracted = {
+            let mut ap = state.lock().unwrap();
+            let Some(session) =
+                ap.table.get_mut(&id) else { return send_invalid_id(&channel) };
+            let index = (sub_id > 0).then_some(sub_id as usize);
+            decode_subtitles(session, index, (0.0, 0.2), &channel)
+        };
+        let (index, entries) = match extracted {
+            Ok((index, entries, _)) => (index, entries),
+            Err(e) => return send_error!(&channel, e.to_string()),
+        };
+
+        let mut engine = options.engine();
+        let mut percentage = 20;
+        let recognized = subtitles::recognize(engine.as_mut(), entries, |x| {
+            let value = 0.2 + 0.8 * x;
+            if (value * 100.0) as i32 > percentage {
+                percentage = (value * 100.0) as i32;
+                send(&channel, MediaEvent::Progress { value });
+            }
+        });
+        match recognized {
+            Ok(entries) =>
+                send(&channel, MediaEvent::SubtitleData { index, entries, header: None }),
+            Err(e) => send_error!(&channel, e.to_string()),
+        }
+    })
+    .await
+    .map_err(|_| ())
+}
+
+/// Decodes a whole subtitle stream, reporting progress on `channel` mapped
+/// into `progress`. Returns its index, its entries and its header, if any.
+#[allow(clippy::cast_precision_loss)]
+#[allow(clippy::cast_possible_truncation)]
+fn decode_subtitles(
+    session: &mut session::Session, index: Option<usize>, progress: (f64, f64),
+    channel: &Channel<MediaEvent>,
+) -> Result<(usize, Vec<subtitles::SubtitleEntry>, Option<subtitles::AssHeader>), MediaError> {
+    session.open_subtitles_decoder(index)?;
+    session.seek(units::Seconds(0.0))?;
+
+    let size = session.demuxer().byte_size() as f64;
+    let mut percentage = 0;
+    while session.try_feed()? {
+        let pos = session.subtitles().unwrap().stream_info().byte_pos;
+        let value = progress.0 + (progress.1 - progress.0) * (pos as f64) / size;
+        if (value * 100.0) as i32 > percentage {
+            percentage = (value * 100.0) as i32;
+            send(channel, MediaEvent::Progress { value });
+        }
+    }
+
+    let d = session.subtitles_mut().unwrap();
+    let index = index.unwrap_or(d.stream_info().index());
+    Ok((index, d.get_delta().into(), d.header()))
+}
+
+/// Finds speech in `[from, to)` of an audio stream, defaulting to the whole
+/// stream. Reads the file in a session of its own.
+#[tauri::command]
+#[allow(clippy::cast_sign_loss)]
+#[allow(clippy::too_many_arguments)]
+pub async fn detect_speech(
+    id: i32, audio_id: i32,
+    from: Option<units::Seconds>, to: Option<units::Seconds>,
+    mix: Option<audio::ChannelMix>,
+    options: Option<audio::SpeechDetectionOptions>,
+    state: State<'_, Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent<'static>>,
+) -> Result<(), ()> {
+    let state = Arc::clone(&state);
+    let channel = channel.clone();
+
+    async_runtime::spawn_blocking(move || {
+        let path = state.lock().unwrap().path_of(id);
+        let Some(path) = path else { return send_invalid_id(&channel) };
+        let mut session = match session::Session::create(&path, None) {
+            Ok(x) => x,
+            Err(e) => return send_error!(&channel, e.to_string()),
+        };
+
+        let index = (audio_id > 0).then_some(audio_id as usize);
+        if let Err(e) = session.open_speech_detector(
+            index, options.unwrap_or_default(), mix.as_ref()
+        ) {
+            return send_error!(&channel, e.to_string());
+        }
+
+        match run_speech_detector(&mut session, from, to, &channel) {
+            Ok(segments) => send(&channel, MediaEvent::SpeechSegments { segments }),
+            Err(e) => send_error!(&channel, e.to_string()),
+        }
+    })
+    .await
+    .map_err(|_| ())
+}
+
+/// Feeds the session's audio sink from `from` until `position` of the sink
+/// reaches `to`, reporting progress on `channel` mapped into `progress`.
+#[allow(clippy::cast_possible_truncation)]
+fn run_audio_sink(
+    session: &mut session::Session,
+    from: units::Seconds, to: units::Seconds, progress: (f64, f64),
+    channel: &Channel<MediaEvent>,
+    position: impl Fn(&AudioSinkKind) -> units::Seconds
+) -> Result<(), MediaError> {
+    session.seek_audio(from)?;
+
+    let mut percentage = 0;
+    loop {
+        session.try_process_skipping_before(from)?;
+        let current = position(&session.audio().unwrap().1);
+        if current >= to {
+            break;
+        }
+        let value = progress.0
+            + (progress.1 - progress.0) * (current.0 - from.0) / (to.0 - from.0);
+        if (value * 100.0) as i32 > percentage {
+            percentage = (value * 100.0) as i32;
+            send(channel, MediaEvent::Progress { value });
+        }
+        if !session.try_feed()? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the session's speech detector over `[from, to)`, by default the whole
+/// stream, reporting progress on `channel`.
+fn run_speech_detector(
+    session: &mut session::Session,
+    from: Option<units::Seconds>, to: Option<units::Seconds>,
+    channel: &Channel<MediaEvent>
+) -> Result<Vec<audio::SpeechSegment>, MediaError> {
+    let start_time = session.audio().unwrap().0.stream_info().start_time_seconds();
+    let from = from.unwrap_or(start_time);
+    let to = to.unwrap_or(units::Seconds(start_time.0 + session.demuxer().duration().0));
+    run_audio_sink(session, from, to, (0.0, 1.0), channel, |sink| {
+        let AudioSinkKind::SpeechDetector(d) = sink else { unreachable!() };
+        d.position()
+    })?;
+
+    let Some((_, AudioSinkKind::SpeechDetector(d))) =
+        session.audio_mut() else { unreachable!() };
+    Ok(d.finish().into_iter()
+        .filter(|x| x.end > from && x.start < to)
+        .map(|x| audio::SpeechSegment {
+            start: units::Seconds(x.start.0.max(from.0)),
+            end: units::Seconds(x.end.0.min(to.0)),
+            ..x
+        })
+        .collect())
+}
+
+/// Fingerprints the whole audio stream of a session, reporting progress on
+/// `channel` mapped into `progress`.
+fn run_fingerprinter(
+    session: &mut session::Session, index: Option<usize>,
+    mix: Option<&audio::ChannelMix>, progress: (f64, f64),
+    channel: &Channel<MediaEvent>
+) -> Result<audio::Fingerprint, MediaError> {
+    session.open_fingerprinter(index, mix)?;
+    let from = session.audio().unwrap().0.stream_info().start_time_seconds();
+    let to = units::Seconds(from.0 + session.demuxer().duration().0);
+    run_audio_sink(session, from, to, progress, channel, |sink| {
+        let AudioSinkKind::Fingerprinter(f) = sink else { unreachable!() };
+        f.position()
+    })?;
+
+    let Some((_, AudioSinkKind::Fingerprinter(f))) =
+        session.audio_mut() else { unreachable!() };
+    Ok(f.finish())
+}
+
+/// Matches the audio of session `id` against that of `other_id` and sends the
+/// piecewise mapping from the timeline of the first to that of the second.
+/// Reads both files in sessions of their own.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::cast_sign_loss)]
+pub async fn align_audio(
+    id: i32, audio_id: i32,
+    other_id: i32, other_audio_id: i32,
+    mix: Option<audio::ChannelMix>,
+    options: Option<audio::AlignmentOptions>,
+    state: State<'_, Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent<'static>>,
+) -> Result<(), ()> {
+    let state = Arc::clone(&state);
+    let channel = channel.clone();
+
+    async_runtime::spawn_blocking(move || {
+        let paths = {
+            let ap = state.lock().unwrap();
+            [ap.path_of(id), ap.path_of(other_id)]
+        };
+        let [Some(path), Some(other_path)] = paths else { return send_invalid_id(&channel) };
+
+        let mut fingerprints = Vec::new();
+        let sources = [(path, audio_id, (0.0, 0.5)), (other_path, other_audio_id, (0.5, 1.0))];
+        for (path, audio_id, progress) in sources {
+            let mut session = match session::Session::create(&path, None) {
+                Ok(x) => x,
+                Err(e) => return send_error!(&channel, e.to_string()),
+            };
+            let index = (audio_id > 0).then_some(audio_id as usize);
+            match run_fingerprinter(&mut session, index, mix.as_ref(), progress, &channel) {
+                Ok(x) => fingerprints.push(x),
                 Err(e) => return send_error!(&channel, e.to_string()),
             }
         }
 
-        let (entries, header) =
-            session.subtitles_mut()
-                .map(|d| (d.get_delta().into(), d.header()))
-                .unwrap();
+        let segments = audio::align(
+            &fingerprints[0], &fingerprints[1], &options.unwrap_or_default());
+        send(&channel, MediaEvent::AudioAlignment { segments });
+    })
+    .await
+    .map_err(|_| ())
+}
+
+/// Estimates the offset and scale that sync `entries` to the speech in an
+/// audio stream. Reads the file in a session of its own.
+#[tauri::command]
+#[allow(clippy::cast_sign_loss)]
+pub async fn estimate_sync(
+    id: i32, audio_id: i32,
+    entries: Vec<(units::Seconds, units::Seconds)>,
+    mix: Option<audio::ChannelMix>,
+    options: Option<audio::SyncOptions>,
+    state: State<'_, Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent<'static>>,
+) -> Result<(), ()> {
+    let state = Arc::clone(&state);
+    let channel = channel.clone();
+
+    async_runtime::spawn_blocking(move || {
+        let options = options.unwrap_or_default();
+        if let Err(e) = options.validate() {
+            return send_error!(&channel, e.to_string());
+        }
+        let path = state.lock().unwrap().path_of(id);
+        let Some(path) = path else { return send_invalid_id(&channel) };
+        let mut session = match session::Session::create(&path, None) {
+            Ok(x) => x,
+            Err(e) => return send_error!(&channel, e.to_string()),
+        };
+
+        let index = (audio_id > 0).then_some(audio_id as usize);
+        if let Err(e) = session.open_speech_detector(
+            index, audio::SpeechDetectionOptions::default(), mix.as_ref()
+        ) {
+            return send_error!(&channel, e.to_string());
+        }
+        let speech = match run_speech_detector(&mut session, None, None, &channel) {
+            Ok(x) => x,
+            Err(e) => return send_error!(&channel, e.to_string()),
+        };
 
-        send(&channel, MediaEvent::SubtitleData { entries, header });
+        // both relative to the start of the stream
+        let start_time = session.audio().unwrap().0.stream_info().start_time_seconds().0;
+        let speech: Vec<_> = speech.iter()
+            .map(|x| (x.start.0 - start_time, x.end.0 - start_time))
+            .collect();
+        let entries: Vec<_> = entries.iter()
+            .map(|x| (x.0.0 - start_time, x.1.0 - start_time))
+            .collect();
+        let duration = session.demuxer().duration().0;
+
+        match audio::estimate_sync(&speech, &entries, duration, &options) {
+            Ok(Some(mut estimate)) => {
+                // undo the shift: t' = (t - s) * scale + offset + s
+                estimate.offset.0 += start_time * (1.0 - estimate.scale);
+                send(&channel, MediaEvent::SyncEstimate { estimate });
+            },
+            Ok(None) => send(&channel, MediaEvent::NoResult {}),
+            Err(e) => send_error!(&channel, e.to_string()),
+        }
     })
     .await
     .map_err(|_| ())
@@ -323,6 +784,10 @@ pub async fn extract_subtitles(
 #[allow(clippy::cast_sign_loss)]
 pub fn open_audio(
     id: i32, audio_id: i32,
+    // number of output channels; keeps the source layout if `None`
+    channels: Option<i32>,
+    // folds the channels into mono if present
+    mix: Option<audio::ChannelMix>,
     state: State<Arc<Mutex<PlaybackRegistry>>>,
     channel: Channel<MediaEvent>,
 ) {
@@ -331,18 +796,21 @@ pub fn open_audio(
         ap.table.get_mut(&id) else { return send_invalid_id(&channel) };
 
     let index = (audio_id > 0).then_some(audio_id as usize);
-    let (d, _) = match session.open_audio_player(index) {
+    let layout = channels.map(ChannelLayout::default);
+    let (d, s) = match session.open_audio_player(index, layout, mix.as_ref()) {
         Ok(()) => session.audio().unwrap(),
         Err(e) => return send_error!(&channel, e.to_string()),
     };
+    let AudioSinkKind::Player(p) = s else { unreachable!() };
 
-    log::debug!("open_audio: {id} {audio_id}");
+    log::debug!("open_audio: {id} {audio_id} {channels:?} {mix:?}");
 
     send(&channel, MediaEvent::AudioStatus {
         index: d.stream_info().index(),
         start_time: d.stream_info().start_time_seconds(),
         length: d.estimated_length(),
         sample_rate: d.sample_rate(),
+        channels: p.channel_layout().channels().to_u32().unwrap(),
     });
 }
 
@@ -351,6 +819,37 @@ pub fn open_audio(
 pub fn open_audio_sampler(
     id: i32, audio_id: i32,
     sample_per_second: usize,
+    mix: Option<audio::ChannelMix>,
+    state: State<Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent>,
+) {
+    let mut ap = state.lock().unwrap();
+    let Some(session) =
+        ap.table.get_mut(&id) else { return send_invalid_id(&channel) };
+
+    let index = (audio_id > 0).then_some(audio_id as usize);
+    let (d, _) = match session.open_audio_sampler(index, sample_per_second, mix.as_ref()) {
+        Ok(()) => session.audio().unwrap(),
+        Err(e) => return send_error!(&channel, e.to_string()),
+    };
+
+    log::debug!("open_audio_sampler: {id} [{audio_id}] {sample_per_second} {mix:?}");
+
+    send(&channel, MediaEvent::AudioStatus {
+        index: d.stream_info().index(),
+        start_time: d.stream_info().start_time_seconds(),
+        length: d.estimated_length(),
+        sample_rate: d.sample_rate(),
+        channels: 1,
+    });
+}
+
+#[tauri::command]
+#[allow(clippy::cast_sign_loss)]
+pub fn open_audio_spectrogram(
+    id: i32, audio_id: i32,
+    slot_per_second: usize, fft_size: usize,
+    mix: Option<audio::ChannelMix>,
     state: State<Arc<Mutex<PlaybackRegistry>>>,
     channel: Channel<MediaEvent>,
 ) {
@@ -359,18 +858,21 @@ pub fn open_audio_sampler(
         ap.table.get_mut(&id) else { return send_invalid_id(&channel) };
 
     let index = (audio_id > 0).then_some(audio_id as usize);
-    let (d, _) = match session.open_audio_sampler(index, sample_per_second) {
+    let (d, _) = match session.open_audio_spectrogram(
+        index, slot_per_second, fft_size, mix.as_ref()
+    ) {
         Ok(()) => session.audio().unwrap(),
         Err(e) => return send_error!(&channel, e.to_string()),
     };
 
-    log::debug!("open_audio_sampler: {id} [{audio_id}] {sample_per_second}");
+    log::debug!("open_audio_spectrogram: {id} [{audio_id}] {slot_per_second} {fft_size} {mix:?}");
 
     send(&channel, MediaEvent::AudioStatus {
         index: d.stream_info().index(),
         start_time: d.stream_info().start_time_seconds(),
         length: d.estimated_length(),
         sample_rate: d.sample_rate(),
+        channels: 1,
     });
 }
 
@@ -457,48 +959,66 @@ pub fn skip_until(
         return Err(());
     };
 
-    if let Some((_, s)) = session.audio_mut() {
-        s.clear();
-    }
-    if let Some((_, s)) = session.video_mut() {
-        s.clear();
-    }
-
-    let mut last_audio: Option<frame::Audio> = None;
-    let mut last_video: Option<frame::Video> = None;
-
-    loop {
-        match session.try_process_skipping_before(time) {
-            Ok((_, a, v)) => {
-                if let Some(f) = a { last_audio = Some(f); }
-                if let Some(f) = v {  last_video = Some(f); }
-            },
-            Err(e) => {
-                send_error!(&channel, e.to_string());
-                return Err(());
-            },
-        }
-
-        if session.audio().as_ref().is_none_or(|(_, s)| !s.is_empty())
-            && session.video().as_ref().is_none_or(|(_, s)| !s.is_empty())
-        {
-            break;
-        }
-
-        match session.try_feed() {
-            Ok(false) => break,
-            Ok(true) => {},
-            Err(e) => {
-                send_error!(&channel, e.to_string());
-                return Err(());
-            }
-        }
+    let (last_audio, last_video) = match session.skip_until(time) {
+        Ok(x) => x,
+        Err(e) => {
+            send_error!(&channel, e.to_string());
+            return Err(());
+        },
     };
     send_frames(session, last_audio, last_video).map_err(|e| {
         send_error!(&channel, e.to_string());
     })
 }
 
+/// Seeks the video player to the frame shown at `time`, or to frame number
+/// `frame` counted at the stream's frame rate, and returns the decoded frames
+/// starting with that one, which carries its exact time. Frame numbers mean
+/// nothing at a variable frame rate, so `frame` is refused then.
+#[tauri::command]
+pub fn seek_exact(
+    id: i32,
+    time: Option<units::Seconds>,
+    frame: Option<usize>,
+    state: State<Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent>,
+) -> Result<ipc::Response, ()> {
+    let mut ap = state.lock().unwrap();
+    let Some(session) = ap.table.get_mut(&id) else {
+        send_invalid_id(&channel);
+        return Err(());
+    };
+    let Some((d, VideoSinkKind::Player(_))) = session.video() else {
+        send(&channel, MediaEvent::NoStream {});
+        return Err(());
+    };
+
+    let time = match (time, frame) {
+        (Some(t), _) => t,
+        (None, Some(_)) if d.is_vfr() => {
+            send_error!(&channel, "seek_exact: can't seek to a frame number at a variable frame rate");
+            return Err(());
+        },
+        (None, Some(n)) => {
+            // aim at the middle of the frame so that rounded timestamps still
+            // fall on the right one
+            let framerate: f64 = d.framerate().into();
+            let start = d.stream_info().start_time_seconds();
+            units::Seconds(start.0 + (n.to_f64().unwrap() + 0.5) / framerate)
+        },
+        (None, None) => {
+            send_error!(&channel, "seek_exact: neither time nor frame given");
+            return Err(());
+        },
+    };
+
+    session.seek_exact(time)
+        .and_then(|last_audio| send_frames(session, last_audio, None))
+        .map_err(|e| {
+            send_error!(&channel, e.to_string());
+        })
+}
+
 #[tauri::command]
 pub async fn get_frames_automatic(
     id: i32, target_working_time_ms: u64,
@@ -515,7 +1035,9 @@ pub async fn get_frames_automatic(
             return Err(());
         };
 
-        match work(session, target_working_time_ms) {
+        let result = session.resume().and_then(|replayed|
+            if replayed { Ok(true) } else { work(session, target_working_time_ms) });
+        match result {
             Ok(_) => {
                 send_frames(session, None, None).map_err(
                     |e| { send_error!(&channel, e.to_string()); })
@@ -555,6 +1077,12 @@ pub async fn sample_automatic(
                     } else {
                         None
                     };
+                let spectrogram =
+                    if let Some((_, AudioSinkKind::Spectrogram(s))) = session.audio_mut() {
+                        s.get_delta()
+                    } else {
+                        None
+                    };
                 let video =
                     if let Some((_, VideoSinkKind::Sampler(s))) = session.video_mut() {
                         s.get_delta()
@@ -562,7 +1090,7 @@ pub async fn sample_automatic(
                         None
                     };
                 send(&channel, MediaEvent::SampleDone {
-                    audio, video,
+                    audio, spectrogram, video,
                     is_eof: !has_next
                 });
                 Ok(())
@@ -578,6 +1106,62 @@ pub async fn sample_automatic(
     .flatten()
 }
 
+/// Builds the video sampler's keyframe index from packet flags without
+/// decoding, then sends everything it knows. The file is read without
+/// holding the session.
+#[tauri::command]
+#[allow(clippy::cast_possible_truncation)]
+pub async fn scan_keyframes(
+    id: i32,
+    state: State<'_, Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent<'static>>,
+) -> Result<(), ()> {
+    let state = Arc::clone(&state);
+    let channel = channel.clone();
+
+    async_runtime::spawn_blocking(move || {
+        let stream = {
+            let ap = state.lock().unwrap();
+            let Some(session) =
+                ap.table.get(&id) else { return send_invalid_id(&channel) };
+            session.video_sampler_stream()
+                .map(|(index, timebase)| (session.demuxer().path().to_owned(), index, timebase))
+        };
+        let (path, index, timebase) = match stream {
+            Ok(x) => x,
+            Err(e) => return send_error!(&channel, e.to_string()),
+        };
+
+        let mut percentage = 0;
+        let result = session::Session::scan_video_packets(&path, index, timebase, |value| {
+            if (value * 100.0) as i32 > percentage {
+                percentage = (value * 100.0) as i32;
+                send(&channel, MediaEvent::Progress { value });
+            }
+        });
+        let packets = match result {
+            Ok(x) => x,
+            Err(e) => return send_error!(&channel, e.to_string()),
+        };
+
+        let mut ap = state.lock().unwrap();
+        let Some(session) =
+            ap.table.get_mut(&id) else { return send_invalid_id(&channel) };
+        if let Err(e) = session.index_video_packets(index, &packets) {
+            return send_error!(&channel, e.to_string());
+        }
+
+        let Some((_, VideoSinkKind::Sampler(s))) =
+            session.video() else { unreachable!() };
+        send(&channel, MediaEvent::SampledData {
+            audio: Vec::new(),
+            video: s.known_deltas(),
+        });
+    })
+    .await
+    .map_err(|_| ())
+}
+
 fn work(
     session: &mut session::Session, target_working_time_ms: u64
 ) -> Result<bool, MediaError> {
@@ -650,7 +1234,7 @@ fn send_frames(
  *  frames      : frame[]
  * ]
  * */
-pub fn pack_video_frames(frames: &VecDeque<frame::Video>, buf: &mut Vec<u8>) {
+pub fn pack_video_frames(frames: &VecDeque<Arc<frame::Video>>, buf: &mut Vec<u8>) {
     fn to_byte_slice(data: &[(u8, u8, u8, u8)]) -> &[u8] {
         unsafe { std::slice::from_raw_parts(data.as_ptr().cast(), data.len() * 4) }
     }
@@ -672,8 +1256,10 @@ pub fn pack_video_frames(frames: &VecDeque<frame::Video>, buf: &mut Vec<u8>) {
  * frame: [
  *  time        : [f64]
  *  pktpos      : [i64]
- *  length      : [u32]
- *  sample data : [f32]
+ *  channels    : [u32]
+ *  layout      : [u64]   (channel mask)
+ *  length      : [u32]   (samples per channel)
+ *  sample data : [f32]   (interleaved, length * channels)
  * ]
  * response := [
  *  size        : [u32]
@@ -681,7 +1267,6 @@ pub fn pack_video_frames(frames: &VecDeque<frame::Video>, buf: &mut Vec<u8>) {
  * ]
  * */
 pub fn pack_audio_frames(frames: &VecDeque<frame::Audio>, buf: &mut Vec<u8>) {
-    // FIXME: support multiple channels
     fn to_byte_slice(floats: &[f32]) -> &[u8] {
         unsafe {
             std::slice::from_raw_parts(floats.as_ptr().cast(), floats.len() * 4)
@@ -691,15 +1276,175 @@ pub fn pack_audio_frames(frames: &VecDeque<frame::Audio>, buf: &mut Vec<u8>) {
     buf.extend(u32::try_from(frames.len()).unwrap().to_le_bytes().iter());
     for frame in frames {
         let time = frame.meta.time.0;
-        let data: &[f32] = frame.decoded.plane(0);
+        let data = audio::interleaved(&frame.decoded);
+        let layout = frame.decoded.channel_layout();
 
         buf.extend(time.to_le_bytes().iter());
         buf.extend(frame.meta.pkt_pos.to_le_bytes().iter());
-        buf.extend(i32::try_from(data.len()).unwrap().to_le_bytes().iter());
+        buf.extend(u32::from(frame.decoded.channels()).to_le_bytes().iter());
+        buf.extend(layout.bits().to_le_bytes().iter());
+        buf.extend(u32::try_from(frame.decoded.samples()).unwrap().to_le_bytes().iter());
         buf.extend_from_slice(to_byte_slice(data));
     };
 }
 
+/**
+ * thumbnail: [
+ *  time        : [f64]
+ *  width       : [u32]
+ *  height      : [u32]
+ *  data        : [u8]    (RGBA, width * height * 4)
+ * ]
+ * response := [
+ *  size        : [u32]
+ *  thumbnails  : thumbnail[]
+ * ]
+ * */
+pub fn pack_thumbnails(thumbnails: &VecDeque<video::Thumbnail>, buf: &mut Vec<u8>) {
+    buf.extend(u32::try_from(thumbnails.len()).unwrap().to_le_bytes().iter());
+    for thumbnail in thumbnails {
+        buf.extend(thumbnail.time.0.to_le_bytes().iter());
+        buf.extend(thumbnail.size.0.to_le_bytes().iter());
+        buf.extend(thumbnail.size.1.to_le_bytes().iter());
+        buf.extend_from_slice(&thumbnail.data);
+    }
+}
+
+/// Takes the thumbnails made since the last call.
+#[tauri::command]
+pub fn get_thumbnails(
+    id: i32,
+    state: State<Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent>
+) -> Result<ipc::Response, ()> {
+    let mut ap = state.lock().unwrap();
+    let Some(session) = ap.table.get_mut(&id) else {
+        send_invalid_id(&channel);
+        return Err(());
+    };
+    let Some((_, VideoSinkKind::Thumbnailer(t))) = session.video_mut() else {
+        send(&channel, MediaEvent::NoStream {});
+        return Err(());
+    };
+
+    let mut buf = Vec::new();
+    pack_thumbnails(&t.get_delta(), &mut buf);
+    Ok(ipc::Response::new(buf))
+}
+
+/// Encodes the frame shown at `time` as a PNG or JPEG image, at its display
+/// size or at `width` and/or `height`. Writes it to `path` if given and
+/// returns nothing; otherwise returns the image. The session is held only to
+/// look up its file.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::cast_sign_loss)]
+pub async fn get_still(
+    id: i32, video_id: i32, time: units::Seconds, format: video::ImageFormat,
+    width: Option<u32>, height: Option<u32>, path: Option<String>,
+    state: State<'_, Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent<'static>>,
+) -> Result<ipc::Response, ()> {
+    let state = Arc::clone(&state);
+    let channel = channel.clone();
+
+    async_runtime::spawn_blocking(move || {
+        let file = state.lock().unwrap().path_of(id);
+        let Some(file) = file else {
+            send_invalid_id(&channel);
+            return Err(());
+        };
+
+        let index = (video_id > 0).then_some(video_id as usize);
+        let image = session::Session::grab_frame(&file, index, time).and_then(|(d, f)| {
+            let size = video::still_size(
+                d.original_size(), d.sample_aspect_ratio(), width, height);
+            video::encode_still(&f.decoded, size, format)
+        });
+        let image = image.and_then(|x| match &path {
+            Some(path) => std::fs::write(path, x)
+                .map(|()| Vec::new())
+                .map_err(|e| MediaError::InternalError(e.to_string())),
+            None => Ok(x),
+        });
+        match image {
+            Ok(x) => Ok(ipc::Response::new(x)),
+            Err(e) => {
+                send_error!(&channel, e.to_string());
+                Err(())
+            }
+        }
+    })
+    .await
+    .map_err(|_| ())
+    .flatten()
+}
+
+/// Sends everything the open samplers already know, including what was
+/// restored from the cache when they were opened.
+#[tauri::command]
+pub fn get_sampled_data(
+    id: i32,
+    state: State<Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent>
+) {
+    let ap = state.lock().unwrap();
+    let Some(session) =
+        ap.table.get(&id) else { return send_invalid_id(&channel) };
+
+    let audio =
+        if let Some((_, AudioSinkKind::Sampler(s))) = session.audio() {
+            s.known_deltas()
+        } else {
+            Vec::new()
+        };
+    let video =
+        if let Some((_, VideoSinkKind::Sampler(s))) = session.video() {
+            s.known_deltas()
+        } else {
+            Vec::new()
+        };
+    send(&channel, MediaEvent::SampledData { audio, video });
+}
+
+/// Snaps `(start, end)` pairs to speech onsets and offsets found in the audio
+/// sampler's data. Only what has been sampled is considered.
+#[tauri::command]
+pub fn snap_to_speech(
+    id: i32, entries: Vec<(units::Seconds, units::Seconds)>,
+    options: Option<audio::SnapOptions>,
+    state: State<Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent>
+) {
+    let ap = state.lock().unwrap();
+    let Some(session) =
+        ap.table.get(&id) else { return send_invalid_id(&channel) };
+    let Some((_, AudioSinkKind::Sampler(s))) =
+        session.audio() else { return send(&channel, MediaEvent::NoStream {}) };
+
+    let entries = s.snap(&entries, &options.unwrap_or_default());
+    send(&channel, MediaEvent::SnappedEntries { entries });
+}
+
+#[tauri::command]
+pub fn get_audio_waveform(
+    id: i32, from: units::Seconds, to: units::Seconds, width: usize,
+    state: State<Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent>
+) {
+    let ap = state.lock().unwrap();
+    let Some(session) =
+        ap.table.get(&id) else { return send_invalid_id(&channel) };
+    let Some((_, AudioSinkKind::Sampler(s))) =
+        session.audio() else { return send(&channel, MediaEvent::NoStream {}) };
+
+    if let Some(waveform) = s.waveform(from, to, width) {
+        send(&channel, MediaEvent::AudioWaveform { waveform });
+    } else {
+        send(&channel, MediaEvent::NoResult {  });
+    }
+}
+
 #[tauri::command]
 pub fn get_keyframe_before(
     id: i32, time: units::Seconds,
@@ -720,6 +1465,73 @@ pub fn get_keyframe_before(
 }
 
 
+/// Writes the keyframes the video sampler has found so far to a file, by
+/// frame number at the stream's frame rate. Refuses a variable frame rate,
+/// where those numbers would be wrong.
+#[tauri::command]
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+pub fn export_keyframes(
+    id: i32, path: &str, format: video::KeyframeFormat,
+    state: State<Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent>
+) {
+    let ap = state.lock().unwrap();
+    let Some(session) =
+        ap.table.get(&id) else { return send_invalid_id(&channel) };
+    let Some((d, VideoSinkKind::Sampler(s))) =
+        session.video() else { return send(&channel, MediaEvent::NoStream {}) };
+    if d.is_vfr() {
+        return send_error!(&channel, "can't number keyframes at a variable frame rate");
+    }
+
+    let framerate: f64 = d.framerate().into();
+    let start_time = d.stream_info().start_time_seconds();
+    let frames: Vec<usize> = s.keyframe_times().iter()
+        .map(|t| ((t.0 - start_time.0) * framerate).round().max(0.0) as usize)
+        .collect();
+    let frame_count = (session.demuxer().duration().0 * framerate).ceil() as usize;
+
+    let text = video::write_keyframes(format, &frames, frame_count);
+    match std::fs::write(path, text) {
+        Ok(()) => send_done(&channel),
+        Err(e) => send_error!(&channel, e.to_string()),
+    }
+}
+
+/// Reads a keyframe file in any of the `KeyframeFormat`s and sends the times
+/// of its frames, for when sampling the video itself takes too long. Refuses
+/// a variable frame rate, like `export_keyframes`.
+#[tauri::command]
+pub fn import_keyframes(
+    id: i32, path: &str,
+    state: State<Arc<Mutex<PlaybackRegistry>>>,
+    channel: Channel<MediaEvent>
+) {
+    let ap = state.lock().unwrap();
+    let Some(session) =
+        ap.table.get(&id) else { return send_invalid_id(&channel) };
+    let Some((d, _)) =
+        session.video() else { return send(&channel, MediaEvent::NoStream {}) };
+    if d.is_vfr() {
+        return send_error!(&channel, "can't number keyframes at a variable frame rate");
+    }
+
+    let text = match std::fs::read_to_string(path) {
+        Ok(x) => x,
+        Err(e) => return send_error!(&channel, e.to_string()),
+    };
+    let Some(frames) = video::parse_keyframes(&text) else {
+        return send_error!(&channel, "unrecognized keyframe file");
+    };
+    let framerate: f64 = d.framerate().into();
+    let start_time = d.stream_info().start_time_seconds();
+    let keyframes = frames.into_iter()
+        .map(|i| units::Seconds(start_time.0 + i.to_f64().unwrap() / framerate))
+        .collect();
+    send(&channel, MediaEvent::Keyframes { keyframes });
+}
+
 #[tauri::command]
 pub fn get_frame_before(
     id: i32, time: units::Seconds,
@@ -749,9 +1561,9 @@ pub fn test_performance(
     }
 
     let mut session =
-        session::Session::create(std::path::Path::new(&path)).unwrap();
+        session::Session::create(std::path::Path::new(&path), None).unwrap();
     session.open_video_player(None, hwaccel).unwrap();
-    session.open_audio_player(None).unwrap();
+    session.open_audio_player(None, None, None).unwrap();
     if let Some((_, VideoSinkKind::Player(x))) = session.video_mut() {
         x.set_output_size((768, 432)).unwrap();
     }
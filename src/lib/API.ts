@@ -9,6 +9,20 @@ import type { BufferHandle, ReadonlyBufferHandle, SlabBuffer } from './details/S
 import type { DiffEntry } from './bindings/DiffEntry';
 import type { EntryScorer } from './bindings/EntryScorer';
 import type { MatchResult } from './bindings/MatchResult';
+import type { AudioChannelMix } from './bindings/AudioChannelMix';
+import type { AudioWaveformData } from './bindings/AudioWaveformData';
+import type { SpeechDetectionOptions } from './bindings/SpeechDetectionOptions';
+import type { SpeechSegment } from './bindings/SpeechSegment';
+import type { SnapOptions } from './bindings/SnapOptions';
+import type { SyncEstimate } from './bindings/SyncEstimate';
+import type { SyncOptions } from './bindings/SyncOptions';
+import type { AlignedSegment } from './bindings/AlignedSegment';
+import type { AlignmentOptions } from './bindings/AlignmentOptions';
+import type { KeyframeFormat } from './bindings/KeyframeFormat';
+import type { ImageFormat } from './bindings/ImageFormat';
+import type { DeinterlaceMode } from './bindings/DeinterlaceMode';
+import type { OcrOptions } from './bindings/OcrOptions';
+import type { VideoSamplerDeltaData } from './bindings/VideoSamplerDeltaData';
 
 export class MediaError extends Error {
     constructor(msg: string, public readonly from: string) {
@@ -75,10 +89,21 @@ export type ReadonlyVideoFrameData = {
     readonly content: ReadonlyBufferHandle<ImageDataArray>
 };
 
+export type ThumbnailData = {
+    readonly time: number,
+    readonly size: readonly [width: number, height: number],
+    /** RGBA, rows without padding */
+    readonly content: Uint8ClampedArray
+};
+
 export type AudioFrameData = {
     pktpos: number,
     time: number,
+    /** samples per channel */
     length: number,
+    channels: number,
+    layout: number,
+    /** interleaved */
     content: Float32Array
 };
 
@@ -157,9 +182,11 @@ export class MMedia {
     #readAudioFrame(view: BinaryReader<ArrayBuffer>): AudioFrameData {
         const time = view.readF64();
         const pktpos = view.readI64();
+        const channels = view.readU32();
+        const layout = view.readI64();
         const length = view.readU32();
-        const content = view.readF32Array(length, { copy: true });
-        return { pktpos, time, length, content };
+        const content = view.readF32Array(length * channels, { copy: true });
+        return { pktpos, time, length, channels, layout, content };
     }
 
     #readVideoFrame(
@@ -216,24 +243,47 @@ export class MMedia {
         });
     }
 
-    async openAudio(audioId: number) {
+    /**
+     * @param channels number of output channels; keeps the source layout if `null`
+     * @param mix folds the channels into mono if not `null`
+     */
+    async openAudio(
+        audioId: number, channels: number | null = null, mix: AudioChannelMix | null = null
+    ) {
         Debug.assert(!this.#destroyed);
         this.#audio = await new Promise<AudioStatus>((resolve, reject) => {
             const channel = createChannel('openAudio', {
                 audioStatus: (data) => resolve(data)
             }, reject);
-            void invoke('open_audio', {id: this.id, audioId, channel});
+            void invoke('open_audio', {id: this.id, audioId, channels, mix, channel});
         });
         return this.#audio;
     }
 
-    async openAudioSampler(audioId: number, samplePerSecond: number) {
+    async openAudioSampler(
+        audioId: number, samplePerSecond: number, mix: AudioChannelMix | null = null
+    ) {
         Debug.assert(!this.#destroyed);
         this.#audio = await new Promise<AudioStatus>((resolve, reject) => {
             const channel = createChannel('openAudioSampler', {
                 audioStatus: (data) => resolve(data)
             }, reject);
-            void invoke('open_audio_sampler', {id: this.id, audioId, samplePerSecond, channel});
+            void invoke('open_audio_sampler', {id: this.id, audioId, samplePerSecond, mix, channel});
+        });
+        return this.#audio;
+    }
+
+    async openAudioSpectrogram(
+        audioId: number, slotPerSecond: number, fftSize: number,
+        mix: AudioChannelMix | null = null
+    ) {
+        Debug.assert(!this.#destroyed);
+        this.#audio = await new Promise<AudioStatus>((resolve, reject) => {
+            const channel = createChannel('openAudioSpectrogram', {
+                audioStatus: (data) => resolve(data)
+            }, reject);
+            void invoke('open_audio_spectrogram',
+                {id: this.id, audioId, slotPerSecond, fftSize, mix, channel});
         });
         return this.#audio;
     }
@@ -261,6 +311,64 @@ export class MMedia {
         return this.#video;
     }
 
+    /**
+     * Replaces the video sink with one that makes thumbnails `height` pixels
+     * high while `sampleAutomatic` runs: one for each `interval` seconds, or
+     * one for each keyframe if `interval` is `null`.
+     */
+    async openThumbnailer(videoId: number, height: number, interval: number | null) {
+        Debug.assert(!this.#destroyed);
+        this.#video = await new Promise<VideoStatus>((resolve, reject) => {
+            const channel = createChannel('openThumbnailer', {
+                videoStatus: (data) => resolve(data)
+            }, reject);
+            void invoke('open_thumbnailer', {id: this.id, videoId, height, interval, channel});
+        });
+        return this.#video;
+    }
+
+    /**
+     * Encodes the frame shown at `time` at its display size, or at `width`
+     * and/or `height` with the other following the aspect ratio. The file is
+     * read apart from this media, so playback and sampling neither wait for
+     * it nor are disturbed.
+     * @returns the image, or nothing if it was written to `path`
+     */
+    async getStill(
+        videoId: number, time: number, format: ImageFormat,
+        width: number | null = null, height: number | null = null,
+        path: string | null = null
+    ) {
+        Debug.assert(!this.#destroyed);
+        return await new Promise<ArrayBuffer>((resolve, reject) => {
+            const channel = createChannel('getStill', {}, reject, -1);
+            invoke<ArrayBuffer>('get_still', {
+                id: this.id, videoId, time, format, width, height, path, channel
+            }).then(resolve, reject);
+        });
+    }
+
+    /** takes the thumbnails made since the last call */
+    async getThumbnails() {
+        Debug.assert(!this.#destroyed);
+        const result = await new Promise<ArrayBuffer>((resolve, reject) => {
+            const channel = createChannel('getThumbnails', {}, reject);
+            invoke<ArrayBuffer>('get_thumbnails', { id: this.id, channel })
+                .then(resolve, reject);
+        });
+        const view = new BinaryReader(result);
+        const n = view.readU32();
+        const thumbnails: ThumbnailData[] = [];
+        for (let i = 0; i < n; i++) {
+            const time = view.readF64();
+            const width = view.readU32();
+            const height = view.readU32();
+            const content = view.readU8ClampedArray(width * height * 4, { copy: true });
+            thumbnails.push({ time, size: [width, height], content });
+        }
+        return thumbnails;
+    }
+
     async extractSubtitles(subId: number) {
         Debug.assert(!this.#destroyed);
         return await new Promise<BackendSubtitleData>((resolve, reject) => {
@@ -272,6 +380,129 @@ export class MMedia {
         });
     }
 
+    /**
+     * Extracts several subtitle tracks, by default all of them, reading the
+     * file only once. The results are in the order of `subIds`.
+     */
+    async extractSubtitleStreams(
+        subIds: number[] | null = null, onProgress?: (value: number) => void
+    ) {
+        Debug.assert(!this.#destroyed);
+        return await new Promise<BackendSubtitleData[]>((resolve, reject) => {
+            const results: BackendSubtitleData[] = [];
+            const channel = createChannel('extractSubtitleStreams', {
+                subtitleData: (data) => results.push(data),
+                progress: (data) => onProgress?.(data.value),
+                done: () => resolve(results),
+            }, reject, -1);
+            void invoke('extract_subtitle_streams', {id: this.id, subIds, channel});
+        });
+    }
+
+    /**
+     * Looks for closed captions in the first `duration` seconds of the video,
+     * or in all of it if null. Each service found (CEA-608 CC1 to CC4, and
+     * CEA-708 service 1) is added to `streams` as a subtitle track, and
+     * returned.
+     */
+    async findClosedCaptions(
+        duration: number | null, onProgress?: (value: number) => void
+    ) {
+        Debug.assert(!this.#destroyed);
+        const streams = await new Promise<StreamDescription[]>((resolve, reject) => {
+            const channel = createChannel('findClosedCaptions', {
+                closedCaptions: (data) => resolve(data.streams),
+                progress: (data) => onProgress?.(data.value),
+            }, reject, -1);
+            void invoke('find_closed_captions', {id: this.id, duration, channel});
+        });
+        for (const stream of streams)
+            if (!this._streams.some((x) => x.index == stream.index))
+                this._streams.push(stream);
+        return streams;
+    }
+
+    /**
+     * Extracts a bitmap subtitle track and reads its text with OCR. Entries
+     * come with the confidence of the engine, if it tells.
+     */
+    async recognizeSubtitles(
+        subId: number, options: OcrOptions, onProgress?: (value: number) => void
+    ) {
+        Debug.assert(!this.#destroyed);
+        return await new Promise<BackendSubtitleData>((resolve, reject) => {
+            const channel = createChannel('recognizeSubtitles', {
+                subtitleData: (data) => resolve(data),
+                progress: (data) => onProgress?.(data.value),
+            }, reject, -1);
+            void invoke('recognize_subtitles', {id: this.id, subId, options, channel});
+        });
+    }
+
+    /**
+     * Finds speech in `[from, to)`, by default the whole stream. The file is
+     * read apart from this media, which is free to play meanwhile.
+     */
+    async detectSpeech(
+        audioId: number, from: number | null = null, to: number | null = null,
+        mix: AudioChannelMix | null = null, options: SpeechDetectionOptions | null = null,
+        onProgress?: (value: number) => void
+    ) {
+        Debug.assert(!this.#destroyed);
+        return await new Promise<SpeechSegment[]>((resolve, reject) => {
+            const channel = createChannel('detectSpeech', {
+                speechSegments: (data) => resolve(data.segments),
+                progress: (data) => onProgress?.(data.value),
+            }, reject, -1);
+            void invoke('detect_speech', {id: this.id, audioId, from, to, mix, options, channel});
+        });
+    }
+
+    /**
+     * Estimates how to retime `[start, end]` pairs to fit the speech in an
+     * audio stream: `t` becomes `t * scale + offset`. The file is read apart
+     * from this media, which is free to play meanwhile.
+     * @returns `null` if there is nothing to correlate
+     */
+    async estimateSync(
+        audioId: number, entries: [number, number][],
+        mix: AudioChannelMix | null = null, options: SyncOptions | null = null,
+        onProgress?: (value: number) => void
+    ) {
+        Debug.assert(!this.#destroyed);
+        return await new Promise<SyncEstimate | null>((resolve, reject) => {
+            const channel = createChannel('estimateSync', {
+                syncEstimate: (data) => resolve(data.estimate),
+                noResult: () => resolve(null),
+                progress: (data) => onProgress?.(data.value),
+            }, reject, -1);
+            void invoke('estimate_sync', {id: this.id, audioId, entries, mix, options, channel});
+        });
+    }
+
+    /**
+     * Matches this media's audio against `other`'s. Within each returned
+     * segment, time `t` here corresponds to `t + offset` in `other`; parts
+     * with no counterpart are left out. Both files are read apart from the
+     * media instances, which are free to play meanwhile.
+     */
+    async alignAudio(
+        audioId: number, other: MMedia, otherAudioId: number,
+        mix: AudioChannelMix | null = null, options: AlignmentOptions | null = null,
+        onProgress?: (value: number) => void
+    ) {
+        Debug.assert(!this.#destroyed && !other.#destroyed);
+        return await new Promise<AlignedSegment[]>((resolve, reject) => {
+            const channel = createChannel('alignAudio', {
+                audioAlignment: (data) => resolve(data.segments),
+                progress: (data) => onProgress?.(data.value),
+            }, reject, -1);
+            void invoke('align_audio', {
+                id: this.id, audioId, otherId: other.id, otherAudioId, mix, options, channel
+            });
+        });
+    }
+
     async setVideoSize(width: number, height: number) {
         Debug.assert(!this.#destroyed);
         Debug.assert(this.#video !== undefined);
@@ -286,6 +517,18 @@ export class MMedia {
         this.#outSize = [width, height];
     }
 
+    /** Takes effect from the next frame decoded; clear the buffer and seek to see it. */
+    async setDeinterlace(mode: DeinterlaceMode) {
+        Debug.assert(!this.#destroyed);
+        Debug.assert(this.#video !== undefined);
+        await new Promise<void>((resolve, reject) => {
+            const channel = createChannel('setDeinterlace', {
+                done: () => resolve()
+            }, reject);
+            void invoke('video_set_deinterlace', {id: this.id, channel, mode});
+        });
+    }
+
     async sampleAutomatic(targetWorkingTimeMs: number): Promise<SampleResult> {
         Debug.assert(!this.#destroyed);
         Debug.assert(this.#currentJobs == 0);
@@ -414,6 +657,98 @@ export class MMedia {
         }
     }
 
+    /**
+     * Seeks to the frame shown at a time, or to a frame number at the video's
+     * frame rate, and reads the frames from there on. The first video frame
+     * is the one asked for, with its exact time. Frame numbers fail at a
+     * variable frame rate.
+     */
+    async seekExact(to: { time: number } | { frame: number }, pool: SlabBuffer<ImageDataArray>) {
+        Debug.assert(!this.#destroyed);
+        Debug.assert(this.#currentJobs == 0);
+        this.#currentJobs += 1;
+        try {
+            const channel = new Channel<MediaEvent>();
+            channel.onmessage = (msg) => {
+                if (msg.event === 'debug')
+                    void Debug.info(msg.data.message);
+            };
+            const result = await invoke<ArrayBuffer>('seek_exact', {
+                id: this.id,
+                time: 'time' in to ? to.time : null,
+                frame: 'frame' in to ? to.frame : null,
+                channel
+            });
+            if (result.byteLength > 0)
+                return this.#readFrames(result, pool);
+            return { audio: [], video: [], readTime: 0 };
+        } finally {
+            this.#currentJobs -= 1;
+        }
+    }
+
+    /**
+     * Moves `[start, end]` pairs to the nearest speech onsets and offsets
+     * found in the audio sampler's data. Requires an open audio sampler.
+     */
+    async snapToSpeech(entries: [number, number][], options: SnapOptions | null = null) {
+        Debug.assert(!this.#destroyed);
+        let channel: Channel<MediaEvent> | undefined;
+        return await new Promise<[number, number][]>((resolve, reject) => {
+            channel = createChannel('snapToSpeech', {
+                snappedEntries: (data) => resolve(data.entries)
+            }, reject);
+            void invoke('snap_to_speech', { id: this.id, channel, entries, options });
+        });
+    }
+
+    /**
+     * Everything the open samplers already know, including what was restored
+     * from the cache when they were opened.
+     */
+    async getSampledData() {
+        Debug.assert(!this.#destroyed);
+        let channel: Channel<MediaEvent> | undefined;
+        return await new Promise<MediaEventData['sampledData']>((resolve, reject) => {
+            channel = createChannel('getSampledData', {
+                sampledData: (data) => resolve(data)
+            }, reject);
+            void invoke('get_sampled_data', { id: this.id, channel });
+        });
+    }
+
+    /**
+     * Indexes the keyframes of the video sampler's stream from packet flags,
+     * without decoding. Much faster than sampling, but finds no shot changes.
+     * @returns everything the video sampler knows afterwards
+     */
+    async scanKeyframes(onProgress?: (value: number) => void) {
+        Debug.assert(!this.#destroyed);
+        return await new Promise<VideoSamplerDeltaData[]>((resolve, reject) => {
+            const channel = createChannel('scanKeyframes', {
+                sampledData: (data) => resolve(data.video),
+                progress: (data) => onProgress?.(data.value),
+            }, reject, -1);
+            void invoke('scan_keyframes', { id: this.id, channel });
+        });
+    }
+
+    /**
+     * Aggregated waveform of `[from, to)` with at least `width` points, or as
+     * many as the sampler's resolution allows. Requires an open audio sampler.
+     */
+    async getAudioWaveform(from: number, to: number, width: number) {
+        Debug.assert(!this.#destroyed);
+        let channel: Channel<MediaEvent> | undefined;
+        return await new Promise<AudioWaveformData | null>((resolve, reject) => {
+            channel = createChannel('getAudioWaveform', {
+                audioWaveform: (data) => resolve(data.waveform),
+                noResult: () => resolve(null)
+            }, reject);
+            void invoke('get_audio_waveform', { id: this.id, channel, from, to, width });
+        });
+    }
+
     async getKeyframeBefore(time: number) {
         Debug.assert(!this.#destroyed);
         let channel: Channel<MediaEvent> | undefined;
@@ -437,6 +772,34 @@ export class MMedia {
             void invoke('get_frame_before', { id: this.id, channel, time });
         });
     }
+
+    /**
+     * writes the keyframes found by the video sampler so far to `path`; fails
+     * at a variable frame rate, where frame numbers don't hold
+     */
+    async exportKeyframes(path: string, format: KeyframeFormat) {
+        Debug.assert(!this.#destroyed);
+        await new Promise<void>((resolve, reject) => {
+            const channel = createChannel('exportKeyframes', {
+                done: () => resolve()
+            }, reject);
+            void invoke('export_keyframes', { id: this.id, channel, path, format });
+        });
+    }
+
+    /**
+     * reads a keyframe file in any supported format; returns their times.
+     * Fails at a variable frame rate, like `exportKeyframes`
+     */
+    async importKeyframes(path: string) {
+        Debug.assert(!this.#destroyed);
+        return await new Promise<number[]>((resolve, reject) => {
+            const channel = createChannel('importKeyframes', {
+                keyframes: (data) => resolve(data.keyframes)
+            }, reject);
+            void invoke('import_keyframes', { id: this.id, channel, path });
+        });
+    }
 }
 
 export const MAPI = {
@@ -106,10 +106,13 @@ export async function initWindowMenu() {
             { item: 'Separator' },
             PlaybackCommands.nextFrame.toGlobalMenuItem(),
             PlaybackCommands.previousFrame.toGlobalMenuItem(),
+            PlaybackCommands.playReverse.toGlobalMenuItem(),
             PlaybackCommands.jumpBackward.toGlobalMenuItem(),
             PlaybackCommands.jumpForward.toGlobalMenuItem(),
             { item: 'Separator' },
             PlaybackCommands.selectAudioStream.toGlobalMenuItem(),
+            PlaybackCommands.recognizeSubtitles.toGlobalMenuItem(),
+            PlaybackCommands.findClosedCaptions.toGlobalMenuItem(),
         ]
     });
     const system = await Submenu.new({
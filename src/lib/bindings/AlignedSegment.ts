@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Seconds } from "./Seconds";
+
+/**
+ * Within `[start, end)` of the first file, time `t` corresponds to
+ * `t + offset` in the second.
+ */
+export type AlignedSegment = { start: Seconds, end: Seconds, offset: Seconds, 
+/**
+ * fraction of matching print bits, from 0.5 (chance) to 1
+ */
+similarity: number, };
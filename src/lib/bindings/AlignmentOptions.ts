@@ -0,0 +1,9 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Seconds } from "./Seconds";
+
+export type AlignmentOptions = { 
+/**
+ * length of the pieces matched independently; cuts shorter than this
+ * may go unnoticed
+ */
+block: Seconds, };
@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A dialogue line as ffmpeg's decoders give it, without the timing.
+ */
+export type AssEvent = { layer: number, style: string, 
+/**
+ * the `Name` field
+ */
+actor: string, marginL: number, marginR: number, marginV: number, effect: string, 
+/**
+ * with its override tags
+ */
+text: string, };
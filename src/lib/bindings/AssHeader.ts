@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AssStyle } from "./AssStyle";
+
+/**
+ * What a decoder's `subtitle_header` says: the script info and the styles.
+ */
+export type AssHeader = { 
+/**
+ * the `[Script Info]` entries, such as `PlayResX`, in order
+ */
+scriptInfo: Array<[string, string]>, styles: Array<AssStyle>, };
@@ -0,0 +1,23 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type AssStyle = { name: string, fontName: string, fontSize: number, 
+/**
+ * colours are `0xAABBGGRR`, where alpha 0 is opaque
+ */
+primaryColour: number, secondaryColour: number, outlineColour: number, backColour: number, bold: boolean, italic: boolean, underline: boolean, strikeOut: boolean, 
+/**
+ * percentages
+ */
+scaleX: number, scaleY: number, spacing: number, 
+/**
+ * degrees
+ */
+angle: number, 
+/**
+ * 1 for an outline and shadow, 3 for an opaque box
+ */
+borderStyle: number, outline: number, shadow: number, 
+/**
+ * as on a numpad, also for SSA's `V4 Styles`
+ */
+alignment: number, marginL: number, marginR: number, marginV: number, encoding: number, };
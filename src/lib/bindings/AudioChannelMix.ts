@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Selects which source channels end up in the mono signal, for when the
+ * default downmix buries the dialogue under music and effects.
+ */
+export type AudioChannelMix = { "type": "center" } | { "type": "frontLeftRight" } | { "type": "custom", weights: Array<number>, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Seconds } from "./Seconds";
+
+/**
+ * A stretch of the waveform envelope at some zoom level, one point per `step`.
+ */
+export type AudioWaveformData = { startTime: Seconds, step: Seconds, min: Array<number>, max: Array<number>, rms: Array<number>, };
@@ -2,4 +2,8 @@
 import type { BackendSubtitleRect } from "./BackendSubtitleRect";
 import type { Seconds } from "./Seconds";
 
-export type BackendSubtitleEntry = { start: Seconds, end: Seconds, rects: Array<BackendSubtitleRect>, };
+export type BackendSubtitleEntry = { start: Seconds, end: Seconds, rects: Array<BackendSubtitleRect>, 
+/**
+ * how sure OCR is of the text, from 0 to 1, if it came from there
+ */
+confidence?: number, };
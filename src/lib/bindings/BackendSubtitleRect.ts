@@ -1,3 +1,8 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AssEvent } from "./AssEvent";
 
-export type BackendSubtitleRect = { "type": "ass", content: string, } | { "type": "text", content: string, } | { "type": "unsupported" };
+export type BackendSubtitleRect = { "type": "ass" } & AssEvent | { "type": "text", content: string, } | { "type": "bitmap", x: number, y: number, width: number, height: number, 
+/**
+ * RGBA with the palette applied, rows without padding; base64 in JSON
+ */
+data: string, } | { "type": "unsupported" };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Keyframe file formats understood by Aegisub and friends. All of them list
+ * frames by number.
+ */
+export type KeyframeFormat = "aegisub" | "xvid" | "x264" | "frameList";
@@ -1,8 +1,14 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AlignedSegment } from "./AlignedSegment";
+import type { AssHeader } from "./AssHeader";
 import type { AudioSamplerDeltaData } from "./AudioSamplerDeltaData";
+import type { AudioWaveformData } from "./AudioWaveformData";
 import type { BackendSubtitleEntry } from "./BackendSubtitleEntry";
 import type { Seconds } from "./Seconds";
+import type { SpectrogramDeltaData } from "./SpectrogramDeltaData";
+import type { SpeechSegment } from "./SpeechSegment";
 import type { StreamDescription } from "./StreamDescription";
+import type { SyncEstimate } from "./SyncEstimate";
 import type { VideoSamplerDeltaData } from "./VideoSamplerDeltaData";
 
-export type MediaEvent = { "event": "done", "data": Record<string, never> } | { "event": "mediaStatus", "data": { audioIndex: number, videoIndex: number, duration: Seconds, streams: Array<StreamDescription>, } } | { "event": "audioStatus", "data": { index: number, length: number, startTime: Seconds, sampleRate: number, } } | { "event": "videoStatus", "data": { index: number, framerate: number, isVfr: boolean, startTime: Seconds, sampleAspectRatio: number, size: [number, number], } } | { "event": "debug", "data": { message: string, } } | { "event": "runtimeError", "data": { what: string, } } | { "event": "opened", "data": { id: number, } } | { "event": "noStream", "data": Record<string, never> } | { "event": "invalidId", "data": Record<string, never> } | { "event": "ffmpegVersion", "data": { value: string, } } | { "event": "frameQueryResult", "data": { time: Seconds, bytePos: number, } } | { "event": "noResult", "data": Record<string, never> } | { "event": "sampleDone", "data": { audio: AudioSamplerDeltaData | null, video: VideoSamplerDeltaData | null, isEof: boolean, } } | { "event": "progress", "data": { value: number, } } | { "event": "subtitleData", "data": { header: string | null, entries: Array<BackendSubtitleEntry>, } };
+export type MediaEvent = { "event": "done", "data": Record<string, never> } | { "event": "mediaStatus", "data": { audioIndex: number, videoIndex: number, duration: Seconds, streams: Array<StreamDescription>, } } | { "event": "audioStatus", "data": { index: number, length: number, startTime: Seconds, sampleRate: number, channels: number, } } | { "event": "videoStatus", "data": { index: number, framerate: number, isVfr: boolean, startTime: Seconds, sampleAspectRatio: number, size: [number, number], } } | { "event": "debug", "data": { message: string, } } | { "event": "runtimeError", "data": { what: string, } } | { "event": "opened", "data": { id: number, } } | { "event": "noStream", "data": Record<string, never> } | { "event": "invalidId", "data": Record<string, never> } | { "event": "ffmpegVersion", "data": { value: string, } } | { "event": "frameQueryResult", "data": { time: Seconds, bytePos: number, } } | { "event": "noResult", "data": Record<string, never> } | { "event": "sampleDone", "data": { audio: AudioSamplerDeltaData | null, spectrogram: SpectrogramDeltaData | null, video: VideoSamplerDeltaData | null, isEof: boolean, } } | { "event": "speechSegments", "data": { segments: Array<SpeechSegment>, } } | { "event": "syncEstimate", "data": { estimate: SyncEstimate, } } | { "event": "audioAlignment", "data": { segments: Array<AlignedSegment>, } } | { "event": "snappedEntries", "data": { entries: Array<[Seconds, Seconds]>, } } | { "event": "sampledData", "data": { audio: Array<AudioSamplerDeltaData>, video: Array<VideoSamplerDeltaData>, } } | { "event": "audioWaveform", "data": { waveform: AudioWaveformData, } } | { "event": "keyframes", "data": { keyframes: Array<Seconds>, } } | { "event": "progress", "data": { value: number, } } | { "event": "closedCaptions", "data": { streams: Array<StreamDescription>, } } | { "event": "subtitleData", "data": { index: number, header: AssHeader | null, entries: Array<BackendSubtitleEntry>, } };
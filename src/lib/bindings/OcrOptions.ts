@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type OcrOptions = { "type": "tesseract", 
+/**
+ * `tesseract` in the `PATH` by default
+ */
+program: string | null, 
+/**
+ * e.g. `eng`, or `chi_sim+eng`
+ */
+language: string, } | { "type": "command", program: string, args: Array<string>, };
@@ -0,0 +1,25 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Seconds } from "./Seconds";
+
+export type SnapOptions = { 
+/**
+ * how far from each boundary to look for speech onsets and offsets
+ */
+window: Seconds, 
+/**
+ * how much earlier than the onset an entry starts
+ */
+leadIn: Seconds, 
+/**
+ * how much later than the offset an entry ends
+ */
+leadOut: Seconds, 
+/**
+ * where the speech threshold sits between the quiet and loud levels
+ * around the boundary, from 0 to 1
+ */
+sensitivity: number, 
+/**
+ * louder stretches shorter than this are not taken as speech
+ */
+minSpeech: Seconds, };
@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Seconds } from "./Seconds";
+
+export type SpectrogramDeltaData = { startIndex: number, startTime: Seconds, endTime: Seconds, 
+/**
+ * number of bins per slot, evenly spaced from DC to the Nyquist frequency
+ */
+bins: number, 
+/**
+ * `bins` magnitudes in dBFS for each slot, one slot after another
+ */
+magnitude: Array<number>, };
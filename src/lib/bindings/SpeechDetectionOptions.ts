@@ -0,0 +1,16 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Seconds } from "./Seconds";
+
+export type SpeechDetectionOptions = { 
+/**
+ * frames whose speech probability exceeds this start or continue a segment
+ */
+threshold: number, 
+/**
+ * segments shorter than this are dropped
+ */
+minSpeech: Seconds, 
+/**
+ * gaps shorter than this do not split a segment
+ */
+minSilence: Seconds, };
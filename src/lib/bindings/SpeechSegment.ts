@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Seconds } from "./Seconds";
+
+export type SpeechSegment = { start: Seconds, end: Seconds, 
+/**
+ * mean speech probability of the frames in the segment, from 0 to 1
+ */
+confidence: number, };
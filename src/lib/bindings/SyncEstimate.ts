@@ -0,0 +1,17 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Seconds } from "./Seconds";
+
+/**
+ * Subtitles fit the audio best when every time `t` becomes
+ * `t * scale + offset`.
+ */
+export type SyncEstimate = { offset: Seconds, scale: number, 
+/**
+ * fraction of the subtitle timeline whose on/off state agrees with the
+ * speech after the correction, from 0 to 1
+ */
+agreement: number, 
+/**
+ * how far the best alignment stands out from a typical one, from 0 to 1
+ */
+confidence: number, };
@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Seconds } from "./Seconds";
+
+export type SyncOptions = { 
+/**
+ * largest offset to consider in either direction
+ */
+maxOffset: Seconds, 
+/**
+ * scale factors to try; common frame rate conversions if `None`
+ */
+scales: Array<number> | null, };
@@ -56,8 +56,12 @@ export class Audio {
         return this.#volume;
     }
 
-    private constructor(private ctx: AudioContext) {
-        this.#worklet = new AudioWorkletNode(ctx, "decoded-audio-loader");
+    private constructor(private ctx: AudioContext, channels: number) {
+        this.#worklet = new AudioWorkletNode(ctx, "decoded-audio-loader", {
+            outputChannelCount: [channels]
+        });
+        ctx.destination.channelCount =
+            Math.min(channels, ctx.destination.maxChannelCount);
         this.#worklet.connect(ctx.destination);
         this.#worklet.port.onmessage = (ev) => {
             if (Array.isArray(ev.data)) {
@@ -70,10 +74,10 @@ export class Audio {
         };
     }
 
-    static async create(sampleRate: number) {
+    static async create(sampleRate: number, channels: number) {
         const ctx = new AudioContext({ sampleRate });
         await ctx.audioWorklet.addModule(decodedAudioLoaderUrl);
-        return new Audio(ctx);
+        return new Audio(ctx, channels);
     }
 
     async close() {
@@ -42,6 +42,45 @@ export const MediaConfig = new PublicConfigGroup(
         bounds: [0, 20],
         default: 3
     },
+    deinterlace: {
+        localizedName: () => $_('config.deinterlace'),
+        type: 'dropdown',
+        description: () => $_('config.deinterlace-d'),
+        options: {
+            auto: { localizedName: () => $_('config.deinterlace-auto') },
+            on: { localizedName: () => $_('config.deinterlace-on') },
+            off: { localizedName: () => $_('config.deinterlace-off') },
+        },
+        default: 'auto'
+    },
+    ocrEngine: {
+        localizedName: () => $_('config.ocr-engine'),
+        type: 'dropdown',
+        description: () => $_('config.ocr-engine-d'),
+        options: {
+            tesseract: { localizedName: () => $_('config.ocr-engine-tesseract') },
+            command: { localizedName: () => $_('config.ocr-engine-command') },
+        },
+        default: 'tesseract'
+    },
+    ocrProgram: {
+        localizedName: () => $_('config.ocr-program'),
+        type: 'string',
+        description: () => $_('config.ocr-program-d'),
+        default: ''
+    },
+    ocrLanguage: {
+        localizedName: () => $_('config.ocr-language'),
+        type: 'string',
+        description: () => $_('config.ocr-language-d'),
+        default: 'eng'
+    },
+    ocrArguments: {
+        localizedName: () => $_('config.ocr-arguments'),
+        type: 'string',
+        description: () => $_('config.ocr-arguments-d'),
+        default: ''
+    },
     showBoundingBoxes: {
         localizedName: () => $_('config.show-bounding-boxes'),
         type: 'boolean',
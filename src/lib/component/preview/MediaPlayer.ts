@@ -4,12 +4,12 @@ import type { CanvasManager } from "../../CanvasManager";
 import { InterfaceConfig } from "../../config/Groups";
 import { Debug } from "../../Debug";
 import { Mutex } from "../../details/Mutex";
-import { Playback } from "../../frontend/Playback";
 import { Audio } from "./Audio";
 import { MediaConfig } from "./Config";
 import { AsyncEventHost, EventHost } from "@the_dissidents/svelte-ui";
 import { PlayerBuffer, type SeekOptions } from "./PlayerBuffer";
 import { barPlot } from "$lib/details/DebugPlot";
+import type { DeinterlaceMode } from "../../bindings/DeinterlaceMode";
 
 const DAMPING = 0.5;
 const N_LATENCY = 100;
@@ -123,6 +123,7 @@ export class MediaPlayer {
         let audioStatus: AudioStatus;
         try {
             await media.openVideo(-1, InterfaceConfig.data.useHwaccel);
+            await media.setDeinterlace(MediaConfig.data.deinterlace as DeinterlaceMode);
             audioStatus = await media.openAudio(audioId);
             await Debug.debug('VideoPlayer: opened media');
         } catch (e) {
@@ -130,7 +131,7 @@ export class MediaPlayer {
             await Debug.error(e);
             throw e;
         }
-        const audio = await Audio.create(audioStatus.sampleRate);
+        const audio = await Audio.create(audioStatus.sampleRate, audioStatus.channels);
         const player = new MediaPlayer(media, audio, manager, rawurl);
         return player;
     }
@@ -320,18 +321,35 @@ export class MediaPlayer {
         Debug.assert(this.#intent !== 'closed');
         if (this.#buffer.state === 'eof') return;
         const pos = await this.#buffer.waitForPlayPosition();
-        void this.seek(pos + 0.001);
+        // seeking lands on the frame shown at the given time, so aim at the
+        // middle of the next frame
+        void this.seek(pos + 1.5 / this.frameRate);
     }
 
     async requestPreviousFrame() {
         Debug.assert(this.#intent !== 'closed');
         const pos = await this.#buffer.waitForPlayPosition();
-        const result = await Playback.sampler?.getFrameBefore(pos);
-        if (!result) {
-            void Debug.warn('cannot find previous frame');
-            return;
+        void this.seek(pos - 0.5 / this.frameRate);
+    }
+
+    /**
+     * Steps backwards frame by frame for `duration` seconds at about the
+     * normal speed, without sound. Stops on playback or another seek.
+     */
+    async playReverse(duration = 1) {
+        Debug.assert(this.#intent !== 'closed');
+        await this.stop();
+        const steps = Math.round(duration * this.frameRate);
+        let pos = await this.#buffer.waitForPlayPosition();
+        for (let i = 0; i < steps && pos > this.startTime; i++) {
+            const t0 = performance.now();
+            await this.#buffer.seek(pos - 0.5 / this.frameRate);
+            const newPos = await this.#buffer.waitForPlayPosition();
+            // playing, or moved elsewhere, or unable to go further back
+            if (this.#intent !== 'paused' || newPos >= pos) break;
+            pos = newPos;
+            await Basic.wait(1000 / this.frameRate - (performance.now() - t0));
         }
-        void this.seek(result.time);
     }
 
     async seek(t: number, opt?: SeekOptions) {
@@ -344,4 +362,8 @@ export class MediaPlayer {
     async setAudioStream(id: number) {
         return this.#buffer.setAudioStream(id);
     }
+
+    async findClosedCaptions(duration: number | null, onProgress?: (value: number) => void) {
+        return this.#buffer.media.findClosedCaptions(duration, onProgress);
+    }
 }
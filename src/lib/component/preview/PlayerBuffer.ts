@@ -99,11 +99,12 @@ export class PlayerBuffer {
         await this.#mutex.use(async () => {
             if (this.state === 'closed') return Debug.early();
             const oldrate = this.media.audio!.sampleRate;
+            const oldchannels = this.media.audio!.channels;
             const status = await this.media.openAudio(id);
 
-            if (status.sampleRate !== oldrate) {
+            if (status.sampleRate !== oldrate || status.channels !== oldchannels) {
                 await this.audio.close();
-                this.#audio = await Audio.create(status.sampleRate);
+                this.#audio = await Audio.create(status.sampleRate, status.channels);
             }
 
             await this.#clearBufferLocked();
@@ -334,8 +335,8 @@ export class PlayerBuffer {
              && target >= this.#videoBuffer[0].time
              && target <= this.#videoBuffer.at(-1)!.time)
             {
-                // inside cache
-                while (this.#videoBuffer[0].time < target) {
+                // inside cache; keep the frame shown at `target`
+                while (this.#videoBuffer.length > 1 && this.#videoBuffer[1].time <= target) {
                     const frame = this.#videoBuffer.shift();
                     frame?.content.delete();
                 }
@@ -354,21 +355,22 @@ export class PlayerBuffer {
                 await this.#clearBufferLocked();
 
                 const realTarget = Math.max(target, this.startTime);
-                const lastKeyframe = await Playback.sampler?.getKeyframeBefore(realTarget);
-
-                if (this.#readVideoPosition === undefined
-                 || target <= this.#readVideoPosition
-                 || !lastKeyframe
-                 || lastKeyframe.time > this.#readVideoPosition)
-                {
-                    // must seek
-                    await this.media.seekVideo(realTarget);
-                    await Debug.trace(`seek: [${target.toFixed(3)}] by time (${realTarget.toFixed(3)})`);
-                    if (lastKeyframe)
-                        await Debug.trace(`seek: info: last keyframe is`, lastKeyframe);
-                } else {
-                    // no need to seek
-                    await Debug.trace(`seek: [${target.toFixed(3)}] not seeked`);
+                if (opt?.imprecise) {
+                    const lastKeyframe = await Playback.sampler?.getKeyframeBefore(realTarget);
+                    if (this.#readVideoPosition === undefined
+                     || target <= this.#readVideoPosition
+                     || !lastKeyframe
+                     || lastKeyframe.time > this.#readVideoPosition)
+                    {
+                        // must seek
+                        await this.media.seekVideo(realTarget);
+                        await Debug.trace(`seek: [${target.toFixed(3)}] by time (${realTarget.toFixed(3)})`);
+                        if (lastKeyframe)
+                            await Debug.trace(`seek: info: last keyframe is`, lastKeyframe);
+                    } else {
+                        // no need to seek
+                        await Debug.trace(`seek: [${target.toFixed(3)}] not seeked`);
+                    }
                 }
 
                 this.#readAudioPosition = undefined;
@@ -376,23 +378,9 @@ export class PlayerBuffer {
                 this.#playPosition = undefined;
 
                 if (!(opt?.imprecise)) {
-                    let frames = await this.media.skipUntil(target, this.#pool);
-                    await Debug.trace('skipUntil: arriving at',
+                    const frames = await this.media.seekExact({ time: realTarget }, this.#pool);
+                    await Debug.trace(`seek: [${target.toFixed(3)}] arriving at`,
                         frames.audio[0]?.time, frames.video[0]?.time);
-
-                    let i = 0;
-                    while (frames.audio.length == 0 || frames.video.length == 0) {
-                        // a problem in seeking caused us to arrive past the target
-                        i++;
-                        const newTarget = realTarget - i;
-                        if (newTarget < this.startTime) break;
-                        await this.#clearBufferLocked();
-                        await this.media.seekVideo(newTarget);
-                        frames = await this.media.skipUntil(target, this.#pool);
-                    }
-                    if (i > 0)
-                        await Debug.trace(`seek: retried ${i} time[s]`);
-
                     await this.#receiveLocked(frames);
                 }
                 void this.#startBuffering();
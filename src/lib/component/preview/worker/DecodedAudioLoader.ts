@@ -112,31 +112,27 @@ class DecodedAudioLoader extends AudioWorkletProcessor {
 
         try {
             const output = outputs[0];
-            let newBuffer = [...this.#buffer];
+            const newBuffer = [...this.#buffer];
             let newCurrentPosition = this.#currentPosition;
-            for (const channel of [output[0]]) {
-                newBuffer = [...this.#buffer];
-                newCurrentPosition = this.#currentPosition;
-                let fillPosition = 0;
-                while (newBuffer.length > 0) {
-                    const content = newBuffer[0].content;
-                    const end = channel.length - fillPosition + newCurrentPosition;
-                    const part = content
-                        .subarray(newCurrentPosition, Math.min(content.length, end))
-                        .map((x) => x * this.#volume);
-                    channel.set(part, fillPosition);
-                    fillPosition += part.length;
-                    if (end < content.length) {
-                        newCurrentPosition = end;
-                        break;
-                    } else {
-                        // this buffer entry is used up
-                        newBuffer.shift();
-                        newCurrentPosition = 0;
-                        if (end == content.length)
-                            break;
-                        // else, don't break
-                    }
+            let fillPosition = 0;
+            const length = output[0].length;
+            while (newBuffer.length > 0 && fillPosition < length) {
+                // positions count samples per channel; content is interleaved
+                const frame = newBuffer[0];
+                const n = Math.min(frame.length - newCurrentPosition, length - fillPosition);
+                for (let c = 0; c < output.length; c++) {
+                    const channel = output[c];
+                    const source = Math.min(c, frame.channels - 1);
+                    for (let i = 0; i < n; i++)
+                        channel[fillPosition + i] = this.#volume *
+                            frame.content[(newCurrentPosition + i) * frame.channels + source];
+                }
+                fillPosition += n;
+                newCurrentPosition += n;
+                if (newCurrentPosition >= frame.length) {
+                    // this buffer entry is used up
+                    newBuffer.shift();
+                    newCurrentPosition = 0;
                 }
             }
             this.#buffer = newBuffer;
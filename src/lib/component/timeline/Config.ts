@@ -37,6 +37,12 @@ export const TimelineConfig = new PublicConfigGroup(
         description: () => get(_)('config.show-keyframes-d'),
         default: true
     },
+    showShotChanges: {
+        localizedName: () => get(_)('config.show-shot-changes'),
+        type: 'boolean',
+        description: () => get(_)('config.show-shot-changes-d'),
+        default: true
+    },
     dragSeamArea: {
         localizedName: () => get(_)('config.seam-area-size'),
         type: 'number',
@@ -137,6 +137,11 @@ export class TimelineInput {
         this.alignmentLine = null;
         snapped = this.trySnap(data, points, Playback.position) ?? snapped;
         snapped = this.trySnap(data, points, 0) ?? snapped;
+        if (TimelineConfig.data.showShotChanges && Playback.sampler) {
+            const end = this.layout.offset + this.layout.width / this.layout.scale;
+            for (const time of Playback.sampler.shotChangesBetween(this.layout.offset, end))
+                snapped = this.trySnap(data, points, time) ?? snapped;
+        }
         for (const e of this.layout.getVisibleEntries()) {
             if (this.#selection.has(e) && !includeSelection) continue;
             snapped = this.trySnap(data, points, e.start) ?? snapped;
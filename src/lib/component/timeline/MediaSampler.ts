@@ -4,6 +4,10 @@ import { InterfaceConfig } from "../../config/Groups";
 import { Debug } from "../../Debug";
 import { AggregationTree } from "../../details/AggregationTree";
 import { Mutex } from "../../details/Mutex";
+import type { AudioSamplerDeltaData } from "../../bindings/AudioSamplerDeltaData";
+import type { VideoSamplerDeltaData } from "../../bindings/VideoSamplerDeltaData";
+import type { SnapOptions } from "../../bindings/SnapOptions";
+import type { KeyframeFormat } from "../../bindings/KeyframeFormat";
 
 class Index {
     private set = new OrderedMap<number, number>();
@@ -18,6 +22,15 @@ class Index {
         if (it.equals(this.set.rEnd())) return false;
         return it.pointer[0] <= right;
     }
+
+    /** all points in [left, right] */
+    between(left: number, right: number) {
+        const result: number[] = [];
+        for (let it = this.set.lowerBound(left);
+             !it.equals(this.set.end()) && it.pointer[0] <= right; it.next())
+            result.push(it.pointer[0]);
+        return result;
+    }
 }
 
 export class MediaSampler {
@@ -33,6 +46,7 @@ export class MediaSampler {
 
     #intensity: AggregationTree<Float32Array>;
     #videoIndex: Index;
+    #shotChanges: Index;
 
     onProgress?: () => void;
 
@@ -69,6 +83,32 @@ export class MediaSampler {
         return this.#videoIndex.query(from, to);
     }
 
+    /** returns true if a new shot starts in [from, to] */
+    shotChangeData(from: number, to: number) {
+        return this.#shotChanges.query(from, to);
+    }
+
+    shotChangesBetween(from: number, to: number) {
+        return this.#shotChanges.between(from, to);
+    }
+
+    /** indexes keyframes quickly without decoding; see `MMedia.scanKeyframes` */
+    async scanKeyframes(onProgress?: (value: number) => void) {
+        await this.#mutex.use(async () => {
+            const video = await this.media.scanKeyframes(onProgress);
+            video.forEach((x) => this.#addVideo(x));
+        });
+    }
+
+    async importKeyframes(path: string) {
+        for (const time of await this.media.importKeyframes(path))
+            this.#videoIndex.add(time, -1);
+    }
+
+    async exportKeyframes(path: string, format: KeyframeFormat) {
+        await this.media.exportKeyframes(path, format);
+    }
+
     async getKeyframeBefore(time: number) {
         return await this.media.getKeyframeBefore(time);
     }
@@ -77,6 +117,10 @@ export class MediaSampler {
         return await this.media.getFrameBefore(time);
     }
 
+    async snapToSpeech(entries: [number, number][], options: SnapOptions | null = null) {
+        return await this.media.snapToSpeech(entries, options);
+    }
+
     private constructor(
         private readonly media: MMedia,
         /** points per second */
@@ -86,13 +130,36 @@ export class MediaSampler {
         this.#intensity = new AggregationTree(Float32Array,
             Math.ceil(this.media.duration * this.resolution), Math.max);
         this.#videoIndex = new Index();
+        this.#shotChanges = new Index();
     }
 
     static async open(media: MMedia, audio: number, resolution: number) {
         await media.openAudioSampler(audio, resolution);
         await media.openVideoSampler(-1, InterfaceConfig.data.useHwaccel);
 
-        return new MediaSampler(media, resolution);
+        const sampler = new MediaSampler(media, resolution);
+        await sampler.#loadSampledData();
+        return sampler;
+    }
+
+    #addAudio(data: AudioSamplerDeltaData) {
+        const { min, max } = data;
+        const peak = max.map((x, i) => Math.max(x, -min[i]));
+        this.#intensity.set(peak, data.startIndex);
+    }
+
+    #addVideo(data: VideoSamplerDeltaData) {
+        for (const [time, pos] of data.keyframes)
+            this.#videoIndex.add(time, pos);
+        for (const time of data.shotChanges)
+            this.#shotChanges.add(time, 0);
+    }
+
+    /** picks up what the backend restored from its cache */
+    async #loadSampledData() {
+        const { audio, video } = await this.media.getSampledData();
+        audio.forEach((x) => this.#addAudio(x));
+        video.forEach((x) => this.#addVideo(x));
     }
 
     async setAudioStream(id: number) {
@@ -102,6 +169,7 @@ export class MediaSampler {
         await this.#mutex.use(async () => {
             await this.media.openAudioSampler(id, this.resolution);
             this.#intensity.clear();
+            await this.#loadSampledData();
         });
     }
 
@@ -169,12 +237,10 @@ export class MediaSampler {
                 }
                 if (result.audio) {
                     this.#sampleProgress = result.audio.endTime;
-                    this.#intensity.set(result.audio.intensity, result.audio.startIndex);
-                }
-                if (result.video) {
-                    for (const [time, pos] of result.video.keyframes)
-                        this.#videoIndex.add(time, pos);
+                    this.#addAudio(result.audio);
                 }
+                if (result.video)
+                    this.#addVideo(result.video);
                 this.onProgress?.();
                 if (result.isEof) {
                     await Debug.trace(`sampling done upon EOF`,
@@ -43,6 +43,8 @@ const PENDING_WAVEFORM_COLOR =
 	$derived(theme.isDark ? `rgb(100% 10% 10% / 30%)` : `rgb(100% 40% 40% / 40%)`);
 const KEYFRAME_COLOR =
 	$derived(theme.isDark ? `rgb(10% 40% 100% / 30%)` : `rgb(40% 40% 100% / 40%)`);
+const SHOT_CHANGE_COLOR =
+	$derived(theme.isDark ? `rgb(100% 70% 10% / 50%)` : `rgb(90% 50% 0% / 50%)`);
 const WAVEFORM_COLOR =
 	$derived(theme.isDark ? `#5bb` : 'oklch(76.37% 0.101 355.37)');
 const INOUT_AREA_OUTSIDE =
@@ -220,6 +222,15 @@ export class TimelineRenderer {
 			}
 		}
 
+		if (TimelineConfig.data.showShotChanges) {
+			const end = this.layout.offset + this.layout.width / this.layout.scale;
+			ctx.fillStyle = SHOT_CHANGE_COLOR;
+			for (const time of Playback.sampler.shotChangesBetween(this.layout.offset, end))
+				ctx.fillRect(
+					time * this.layout.scale + this.layout.leftColumnWidth, yscroll,
+					2, this.layout.height);
+		}
+
 		if (this.layout.requestedSampler)
 			void this.layout.processSampler();
 	}
@@ -71,29 +71,46 @@ export type ASSParseMessage =
     ({ category: 'invalid' } & ASSParseInvalidMessage)
   | ({ category: 'unsupported' } & ASSParseUnsupportedMessage);
 
+/** The fields of a style or dialogue line, by the names `Format:` gives them */
+export type ASSFields = ReadonlyMap<string, string>;
+
+/**
+ * An ASS script already split into its fields, such as a track the backend
+ * has decoded, to be checked and imported like a file.
+ */
+export type ASSFieldsSource = {
+    info: ReadonlyMap<string, string>,
+    /** null if there is no styles section at all */
+    styles: ASSFields[] | null,
+    events: { start: number, end: number, fields: ASSFields, text: string }[],
+};
+
 export class ASSParser implements SubtitleParser {
-    #subs: Subtitles;
-    #sections: Map<string, string>;
+    #subs = new Subtitles();
+    #sections = new Map<string, string>();
+    #source?: ASSFieldsSource;
     #warnings: ASSParseMessage[] = [];
     #parsed = false;
 
-    constructor(source: string) {
+    constructor(source: string | ASSFieldsSource) {
+        if (typeof source !== 'string') {
+            this.#source = source;
+            return;
+        }
         const sectionRegex  = /^\[(.+)\]\s*\n((?:\s*[^[\n]+.+\n)+)/gm;
         this.#sections = new Map([...source.matchAll(sectionRegex)].map((x) => [x[1], x[2]]));
         if (this.#sections.size == 0)
             throw new DeserializationError('invalid ASS');
-        this.#subs = new Subtitles();
-        this.#parseScriptInfo();
+        this.#splitScriptInfo();
     }
 
     decode() {
-        if (this.#parsed) {
-            this.#warnings = [];
-            this.#subs = new Subtitles();
-            this.#parseScriptInfo();
-        }
-        this.#parseStyles();
-        this.#parseEvents();
+        this.#warnings = [];
+        this.#subs = new Subtitles();
+        const source = this.#source;
+        this.#parseScriptInfo(source?.info ?? this.#splitScriptInfo());
+        this.#parseStyles(source ? source.styles : this.#splitStyles());
+        this.#parseEvents(source?.events ?? this.#splitEvents());
         this.#parseFonts();
         this.#subs.migrated = 'ASS';
         this.#parsed = true;
@@ -113,14 +130,80 @@ export class ASSParser implements SubtitleParser {
         this.#warnings.push({...w, category: 'unsupported'});
     }
 
-    #parseScriptInfo() {
+    #splitScriptInfo() {
         const text = this.#sections.get('Script Info');
         if (text === undefined)
             throw new DeserializationError('invalid ASS: script info not found');
 
         const entryRegex = /(?<=\n)([^;].+?): *(.*)/g;
-        const infos = new Map([...text.matchAll(entryRegex)]
+        return new Map([...text.matchAll(entryRegex)]
             .map((x) => [x[1], x[2]]));
+    }
+
+    #splitStyles(): ASSFields[] | null {
+        const text = this.#sections.get('V4 Styles')
+                ?? this.#sections.get('V4+ Styles')
+                ?? this.#sections.get('V4++ Styles');
+        if (text === undefined) return null;
+
+        const styleFieldMap = this.#sections.has('V4++ Styles')
+            ? getASSFormatFieldMap('Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginT, MarginB, Encoding, RelativeTo')
+            : getASSFormatFieldMap(text);
+        if (styleFieldMap == null)
+            throw new DeserializationError('invalid ASS');
+        if (!styleFieldMap.has('Name'))
+            throw new DeserializationError('invalid ASS');
+
+        const stylesRegex = /Style:\s*(.*)\n/g;
+        return [...text.matchAll(stylesRegex)].map((match) => {
+            const items = match[1].split(',');
+            if (items.length != styleFieldMap.size)
+                throw new DeserializationError('invalid ASS');
+            return new Map([...styleFieldMap]
+                .map(([field, i]) => [field, items[i]] as const));
+        });
+    }
+
+    #splitEvents(): ASSFieldsSource['events'] {
+        const text = this.#sections.get('Events');
+        if (text == null)
+            throw new DeserializationError('invalid ASS');
+
+        const fieldMap = getASSFormatFieldMap(text);
+        if (fieldMap == null
+         || !fieldMap.has('Start')
+         || !fieldMap.has('End')
+         || !fieldMap.has('Style')
+         || !fieldMap.has('Text'))
+            throw new DeserializationError('invalid ASS');
+
+        const regex = RegExp(
+            String.raw`^Dialogue:\s*((?:(?:[^,\n\r])*,){${fieldMap.size-1}})(.+)`, 'gm');
+        const events: ASSFieldsSource['events'] = [];
+        let i = 0;
+        for (const line of text.matchAll(regex)) {
+            const timestamp = (field: string) => {
+                const value = fields.get(field)!;
+                const t = Basic.parseTimestamp(value);
+                if (t !== null) return t;
+                this.#invalid({ type: 'invalid-event-field', line: i, field, value});
+                return null;
+            };
+
+            i++;
+            const opts = line[1].split(',');
+            const fields = new Map([...fieldMap]
+                .filter(([field]) => field != 'Text')
+                .map(([field, j]) => [field, opts[j]] as const));
+            const start = timestamp('Start'),
+                  end   = timestamp('End');
+            if (start === null || end === null) continue;
+            events.push({ start, end, fields, text: line[2] });
+        }
+        return events;
+    }
+
+    #parseScriptInfo(infos: ReadonlyMap<string, string>) {
         this.#subs.metadata.title = infos.get('Title') ?? this.#subs.metadata.title;
         if (infos.has('PlayResX')) {
             const n = Number.parseInt(infos.get('PlayResX')!);
@@ -151,78 +234,61 @@ export class ASSParser implements SubtitleParser {
         return null;
     }
 
-    #parseStyles() {
-        const text = this.#sections.get('V4 Styles')
-                ?? this.#sections.get('V4+ Styles')
-                ?? this.#sections.get('V4++ Styles');
-        if (text === undefined) {
+    #parseStyles(styles: ASSFields[] | null) {
+        if (styles === null) {
             this.#invalid({ type: 'no-styles' });
             return;
         }
-        const styleFieldMap = this.#sections.has('V4++ Styles')
-            ? getASSFormatFieldMap('Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginT, MarginB, Encoding, RelativeTo')
-            : getASSFormatFieldMap(text);
-        if (styleFieldMap == null)
-            throw new DeserializationError('invalid ASS');
-        if (!styleFieldMap.has('Name'))
-            throw new DeserializationError('invalid ASS');
 
         this.#subs.styles = [];
-
-        const stylesRegex = /Style:\s*(.*)\n/g;
-        let first: SubtitleStyle | undefined;
         const nameToStyle = new Map<string, SubtitleStyle>();
 
-        const styleMatches = text.matchAll(stylesRegex);
-        for (const match of styleMatches) {
+        for (const fields of styles) {
             const bool = (field: string, f: (x: boolean) => boolean | void) => {
-                if (!styleFieldMap.has(field)) return;
-                const value = items[styleFieldMap.get(field)!];
+                const value = fields.get(field);
+                if (value === undefined) return;
                 if (value == '-1' && f(true) !== false) return;
                 else if (value == '0' && f(false) !== false) return;
                 else this.#invalid({ type: 'invalid-style-field', name, field, value });
             };
             const float =
             (field: string, f: (x: number) => boolean | void) => {
-                if (!styleFieldMap.has(field)) return;
-                const value = items[styleFieldMap.get(field)!];
+                const value = fields.get(field);
+                if (value === undefined) return;
                 const n = Number.parseFloat(value);
                 if (!isNaN(n) && f(n) !== false) return;
                 this.#invalid({ type: 'invalid-style-field', name, field, value });
             };
             const color = (field: string, f: (x: Color.PlainColorObject) => boolean | void) => {
-                if (!styleFieldMap.has(field)) return;
-                const value = items[styleFieldMap.get(field)!];
+                const value = fields.get(field);
+                if (value === undefined) return;
                 const color = fromASSColor(value);
                 if (color !== null && f(color) !== false) return;
                 else this.#invalid({ type: 'invalid-style-field', name, field, value });
             };
             const ignore = (field: string, def: string | RegExp) => {
-                if (!styleFieldMap.has(field)) return;
-                const value = items[styleFieldMap.get(field)!];
+                const value = fields.get(field);
+                if (value === undefined) return;
                 if (typeof def == 'string'
                     ? value == def
                     : def.test(value)) return;
                 this.#unsupported({ type: 'ignored-style-field', name, field, value });
             };
 
-            const items = match[1].split(',');
-            if (items.length != styleFieldMap.size)
-                throw new DeserializationError('invalid ASS');
-            const name = items[styleFieldMap.get('Name')!];
+            const name = fields.get('Name')!;
             let style = nameToStyle.get(name);
             if (style === undefined) {
                 const newStyle = $state(SubtitleStyle.new(name));
                 style = newStyle;
                 this.#subs.styles.push(newStyle);
                 nameToStyle.set(name, newStyle);
-                if (!first) first = style;
             } else {
                 this.#invalid({ type: 'duplicate-style-definition', name });
             }
 
-            if (styleFieldMap.has('Fontname'))
-                style.font = items[styleFieldMap.get('Fontname')!];
+            const font = fields.get('Fontname');
+            if (font !== undefined)
+                style.font = font;
 
             bool('Bold',        (x) => {style.styles.bold = x});
             bool('Italic',      (x) => {style.styles.italic = x});
@@ -243,8 +309,8 @@ export class ASSParser implements SubtitleParser {
                 style.alignment = a;
             });
 
-            color('PrimaryColor', (x) => {style.color = x});
-            color('OutlineColor', (x) => {style.outlineColor = x});
+            color('PrimaryColour', (x) => {style.color = x});
+            color('OutlineColour', (x) => {style.outlineColor = x});
 
             ignore('ScaleX', /^100(\.0+)?$/);
             ignore('ScaleY', /^100(\.0+)?$/);
@@ -257,22 +323,10 @@ export class ASSParser implements SubtitleParser {
             // because we don't know when to warn, yet we don't want to warn every time
         }
 
-        if (!first) this.#invalid({ type: 'no-styles' });
+        if (this.#subs.styles.length == 0) this.#invalid({ type: 'no-styles' });
     }
 
-    #parseEvents() {
-        const text = this.#sections.get('Events');
-        if (text == null)
-            throw new DeserializationError('invalid ASS');
-
-        const fieldMap = getASSFormatFieldMap(text);
-        if (fieldMap == null
-         || !fieldMap.has('Start')
-         || !fieldMap.has('End')
-         || !fieldMap.has('Style')
-         || !fieldMap.has('Text'))
-            throw new DeserializationError('invalid ASS');
-
+    #parseEvents(events: ASSFieldsSource['events']) {
         const nameToStyle = new Map(this.#subs.styles.map((x) => [x.name, x]));
         const getStyleOrCreate = (styleName: string) => {
             let style = nameToStyle.get(styleName);
@@ -293,28 +347,13 @@ export class ASSParser implements SubtitleParser {
             ignoredSpecialCharacter: new Map<string, number>(),
         };
 
-        const regex = RegExp(
-            String.raw`^Dialogue:\s*((?:(?:[^,\n\r])*,){${fieldMap.size-1}})(.+)`, 'gm');
-        let i = 0;
-        for (const line of text.matchAll(regex)) {
-            const timestamp = (field: string) => {
-                const value = opts[fieldMap.get(field)!];
-                const t = Basic.parseTimestamp(value);
-                if (t !== null) return t;
-                this.#invalid({ type: 'invalid-event-field', line: i, field, value});
-                return null;
-            };
+        for (const { start, end, fields, text } of events) {
             const ignore = (field: string, def: string) => {
-                if (!fieldMap.has(field) || opts[fieldMap.get(field)!] === def) return;
+                const value = fields.get(field);
+                if (value === undefined || value === def) return;
                 ignoredField.set(field, (ignoredField.get(field) ?? 0) + 1);
             };
 
-            i++;
-            const opts = line[1].split(',');
-            const text = line[2];
-            const start = timestamp('Start'),
-                  end   = timestamp('End');
-            if (start === null || end === null) continue;
             ignore('Effect', '');
             ignore('Name', '');
             ignore('MarginL', '0');
@@ -326,7 +365,7 @@ export class ASSParser implements SubtitleParser {
             ignore('Layer', '0');
 
             const entry = new SubtitleEntry(start, end);
-            const style = getStyleOrCreate(opts[fieldMap.get('Style')!]);
+            const style = getStyleOrCreate(fields.get('Style')!);
             const { result, pos, alignment } = ASSString.parse(text, style, warnings);
             entry.texts.set(style, result);
             entry.positioning = pos;
@@ -335,6 +374,10 @@ export class ASSParser implements SubtitleParser {
             this.#subs.entries.push(entry);
         }
 
+        ignoredField.forEach((occurrence, field) => this.#unsupported({
+            type: 'ignored-event-field',
+            field, occurrence
+        }));
         if (warnings.ignoredDrawing > 0) this.#unsupported({
             type: 'ignored-drawing-command',
             occurrence: warnings.ignoredDrawing
@@ -517,11 +560,9 @@ Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\
 export function toASSColor(original: Color.ColorTypes) {
     const c = Color.to(original, 'srgb', { inGamut: true });
     const r = c.coords[0] ?? 0, g = c.coords[1] ?? 0, b = c.coords[2] ?? 0;
-    return '&H'
-        + ((1 - (c.alpha ?? 1)) * 255).toString(16).toUpperCase().padStart(2, '0')
-        + (b * 255).toString(16).toUpperCase().padStart(2, '0')
-        + (g * 255).toString(16).toUpperCase().padStart(2, '0')
-        + (r * 255).toString(16).toUpperCase().padStart(2, '0');
+    const hex = (x: number) =>
+        Math.round(x * 255).toString(16).toUpperCase().padStart(2, '0');
+    return '&H' + hex(1 - (c.alpha ?? 1)) + hex(b) + hex(g) + hex(r);
 }
 
 export function fromASSColor(str: string): Color.PlainColorObject | null {
@@ -539,7 +580,8 @@ export function fromASSColor(str: string): Color.PlainColorObject | null {
     return {
         space: Color.sRGB,
         coords: [r, g, b],
-        alpha
+        // ASS alpha is transparency
+        alpha: 1 - alpha
     };
 }
 
@@ -1,36 +1,84 @@
 import type { BackendSubtitleData } from "$lib/API";
-import { Basic } from "$lib/Basic";
-import { Debug } from "$lib/Debug";
+import type { AssHeader } from "$lib/bindings/AssHeader";
+import type { AssStyle } from "$lib/bindings/AssStyle";
 import { SubtitleEntry, Subtitles } from "../Subtitles.svelte";
-import { ASSParser } from "./ASS.svelte";
+import { ASSParser, type ASSFields, type ASSFieldsSource } from "./ASS.svelte";
 
+// 0xAABBGGRR, as written in a style line
+function toASSColorField(value: number) {
+    return '&H' + (value >>> 0).toString(16).toUpperCase().padStart(8, '0');
+}
+
+function styleFields(style: AssStyle): ASSFields {
+    const bool = (x: boolean) => x ? '-1' : '0';
+    return new Map([
+        ['Name',            style.name],
+        ['Fontname',        style.fontName],
+        ['Fontsize',        `${style.fontSize}`],
+        ['PrimaryColour',   toASSColorField(style.primaryColour)],
+        ['SecondaryColour', toASSColorField(style.secondaryColour)],
+        ['OutlineColour',   toASSColorField(style.outlineColour)],
+        ['BackColour',      toASSColorField(style.backColour)],
+        ['Bold',            bool(style.bold)],
+        ['Italic',          bool(style.italic)],
+        ['Underline',       bool(style.underline)],
+        ['StrikeOut',       bool(style.strikeOut)],
+        ['ScaleX',          `${style.scaleX}`],
+        ['ScaleY',          `${style.scaleY}`],
+        ['Spacing',         `${style.spacing}`],
+        ['Angle',           `${style.angle}`],
+        ['BorderStyle',     `${style.borderStyle}`],
+        ['Outline',         `${style.outline}`],
+        ['Shadow',          `${style.shadow}`],
+        // the backend has already made SSA alignments numpad ones
+        ['Alignment',       `${style.alignment}`],
+        ['MarginL',         `${style.marginL}`],
+        ['MarginR',         `${style.marginR}`],
+        ['MarginV',         `${style.marginV}`],
+        ['Encoding',        `${style.encoding}`],
+    ]);
+}
+
+/**
+ * A parser for an extracted ASS track, which checks it and reports what it
+ * ignores the same way as importing an ASS file.
+ */
+export function parseBackendASS(header: AssHeader, data: BackendSubtitleData) {
+    const source: ASSFieldsSource = {
+        info: new Map(header.scriptInfo),
+        styles: header.styles.map(styleFields),
+        events: [],
+    };
+    for (const { start, end, rects } of data.entries)
+    for (const rect of rects) {
+        if (rect.type != 'ass') continue;
+        source.events.push({
+            start, end,
+            fields: new Map([
+                ['Layer',   `${rect.layer}`],
+                ['Style',   rect.style],
+                ['Name',    rect.actor],
+                ['MarginL', `${rect.marginL}`],
+                ['MarginR', `${rect.marginR}`],
+                ['MarginV', `${rect.marginV}`],
+                ['Effect',  rect.effect],
+            ]),
+            text: rect.text,
+        });
+    }
+    return new ASSParser(source);
+}
+
+/** Converts an extracted track of plain text; null if there is none */
 export function convertBackendSubtitles(data: BackendSubtitleData) {
-    if (data.header) {
-        // ASS
-        let source = data.header.trim();
-        for (const { start, end, rects } of data.entries)
-        for (const rect of rects) {
-            if (rect.type == 'ass') {
-                const t0 = Basic.formatTimestamp(start, 2);
-                const t1 = Basic.formatTimestamp(end, 2);
-                source += '\n' +
-                    rect.content.replace(/^\d+,(\d+),/, (_, layer) => `Dialogue: ${layer},${t0},${t1},`);
-            }
-        }
-        const result = new ASSParser(source).decode();
-        void Debug.trace(result.messages);
-        return result.subs;
-    } else {
-        // text?
-        const subs = new Subtitles();
-        for (const { start, end, rects } of data.entries)
-        for (const rect of rects) {
-            if (rect.type == 'text') {
-                const entry = new SubtitleEntry(start, end);
-                entry.texts.set(subs.styles[0], rect.content);
-                subs.entries.push(entry);
-            }
+    const subs = new Subtitles();
+    for (const { start, end, rects } of data.entries)
+    for (const rect of rects) {
+        if (rect.type == 'text') {
+            const entry = new SubtitleEntry(start, end);
+            entry.texts.set(subs.styles[0], rect.content);
+            subs.entries.push(entry);
         }
-        return subs.entries.length > 0 ? subs : null;
     }
+    return subs.entries.length > 0 ? subs : null;
 }
@@ -161,9 +161,8 @@ export const ImportFormatDialogs = {
                 };
                 case 'ignored-event-field': return {
                     heading: $_('assimport.ignored-event-field'),
-                    items: map<'invalid-event-field'>((w) =>
-                        $_('assimport.in-line-a-b-equals-c',
-                            {values: {a: w.line, b: w.field, c: w.value}}))
+                    items: map<'ignored-event-field'>((x) =>
+                          x.field + $_('assimport.occurred-n-times', {values: {n: x.occurrence}})),
                 };
                 case 'ignored-special-character': return {
                     heading: $_('assimport.ignored-special-character'),
@@ -13,16 +13,23 @@ import { unwrapFunctionStore, _ } from "svelte-i18n";
 import { Frontend, guard, guardAsync } from "./Frontend";
 import { MediaPlayerInterface, type MediaPlayer } from "$lib/component/preview/MediaPlayer";
 import type { SeekOptions } from "$lib/component/preview/PlayerBuffer";
-import { convertBackendSubtitles } from "$lib/core/formats/BackendSubtitles";
+import { convertBackendSubtitles, parseBackendASS } from "$lib/core/formats/BackendSubtitles";
 import { SubtitleUtil } from "$lib/core/SubtitleUtil.svelte";
+import type { Subtitles } from "$lib/core/Subtitles.svelte";
 import { Dialog } from "$lib/dialog";
+import { ImportFormatDialogs } from "$lib/dialog/ImportFormatDialogs";
 import { openDialog } from "$lib/DialogOutlet.svelte";
 import { Editing } from "./Editing";
 import { Source, ChangeType } from "./Source";
-import { MMedia } from "$lib/API";
+import { MMedia, type BackendSubtitleData } from "$lib/API";
+import type { OcrOptions } from "$lib/bindings/OcrOptions";
+import { MediaConfig } from "$lib/component/preview/Config";
 
 const $_ = unwrapFunctionStore(_);
 
+/** OCR confidence below which an entry is worth checking by hand */
+const OCR_UNCERTAIN = 0.7;
+
 export type LoadState = 'empty' | 'loading' | 'loaded';
 
 export type PlayArea = {
@@ -169,6 +176,35 @@ export const Playback = {
     }
 }
 
+/** Merges a track from the backend into the document; false if nothing was imported */
+async function importExtracted(result: BackendSubtitleData) {
+    const header = result.header;
+    let subs: Subtitles | null;
+    if (header) {
+        // checked like an ASS file; null if the user cancels in the dialog
+        subs = await guardAsync(
+            () => ImportFormatDialogs.ASS(parseBackendASS(header, result), true),
+            $_('msg.failed-to-extract-subtitle-track'), null);
+        if (!subs) return false;
+    } else {
+        subs = guard(() => convertBackendSubtitles(result),
+            $_('msg.failed-to-extract-subtitle-track'), null);
+    }
+    if (!subs || subs.entries.length == 0) {
+        Frontend.setStatus($_('msg.failed-to-extract-subtitle-track'), 'error');
+        return false;
+    }
+
+    const options = await openDialog(
+        Dialog.importOptions, subs.migrated != 'text', subs);
+    if (!options) return false;
+
+    const entries = SubtitleUtil.merge(Source.subs, subs, options);
+    if (entries.length > 0) await Editing.setSelection(entries);
+    await Source.markChanged(ChangeType.General, $_('c.import-extracted-track'));
+    return true;
+}
+
 export const PlaybackCommands = {
     selectAudioStream: new UICommand(() => $_('category.media'),
         [ ],
@@ -194,8 +230,9 @@ export const PlaybackCommands = {
     {
         name: () => $_('action.extract-subtitle-track'),
         isApplicable: () => get(Playback.loadState) == 'loaded',
-        items: () => Playback.player?.streams.filter((x) => x.type == 'subtitle')
-            .map((x) => ({
+        items: () => {
+            const streams = Playback.player?.streams.filter((x) => x.type == 'subtitle') ?? [];
+            const items = streams.map((x) => ({
                 name: `[${x.index}] ${x.type}: ${x.codecId ?? ''} ${x.languageCode}`,
                 async call() {
                     Debug.assert(!!Playback.player);
@@ -208,22 +245,97 @@ export const PlaybackCommands = {
                         }, $_('msg.failed-to-extract-subtitle-track'), null),
                         $_('msg.extracting-subtitle-track'), null);
                     if (!result) return;
+                    if (await importExtracted(result))
+                        Frontend.setStatus($_('msg.imported-extracted-track'));
+                }
+            }));
+            if (streams.length > 1) items.push({
+                name: $_('menu.all-subtitle-tracks'),
+                async call() {
+                    Debug.assert(!!Playback.player);
+                    const results = await showProgress(
+                        (report) => guardAsync(async () => {
+                            const media = await MMedia.open(Playback.player!.source);
+                            try {
+                                return await media.extractSubtitleStreams(
+                                    streams.map((x) => x.index),
+                                    (p) => report(p, `${(p * 100).toFixed(0)}%`));
+                            } finally {
+                                await media.close();
+                            }
+                        }, $_('msg.failed-to-extract-subtitle-track'), null),
+                        $_('msg.extracting-subtitle-track'), null);
+                    if (!results) return;
+                    let n = 0;
+                    for (const result of results)
+                        if (await importExtracted(result)) n++;
+                    if (n > 0) Frontend.setStatus(
+                        $_('msg.imported-n-extracted-tracks', {values: {n}}));
+                }
+            });
+            return items;
+        },
+        emptyText: () => $_('msg.no-available-item')
+    }),
+    findClosedCaptions: new UICommand(() => $_('category.media'),
+        [ ],
+    {
+        name: () => $_('action.find-closed-captions'),
+        isApplicable: () => get(Playback.loadState) == 'loaded',
+        async call() {
+            Debug.assert(!!Playback.player);
+            const player = Playback.player;
+            const found = await showProgress(
+                (report) => guardAsync(
+                    () => player.findClosedCaptions(null,
+                        (p) => report(p, `${(p * 100).toFixed(0)}%`)),
+                    $_('msg.failed-to-find-closed-captions'), null),
+                $_('msg.finding-closed-captions'), null);
+            if (!found) return;
+            Frontend.setStatus(found.length > 0
+                ? $_('msg.found-n-closed-captions', {values: {n: found.length}})
+                : $_('msg.no-closed-captions-found'));
+        }
+    }),
+    recognizeSubtitles: new UICommand(() => $_('category.media'),
+        [ ],
+    {
+        name: () => $_('action.recognize-subtitle-track'),
+        isApplicable: () => get(Playback.loadState) == 'loaded',
+        items: () => Playback.player?.streams.filter((x) => x.type == 'subtitle')
+            .map((x) => ({
+                name: `[${x.index}] ${x.type}: ${x.codecId ?? ''} ${x.languageCode}`,
+                async call() {
+                    Debug.assert(!!Playback.player);
+                    const options: OcrOptions = MediaConfig.data.ocrEngine == 'tesseract'
+                        ? {
+                            type: 'tesseract',
+                            program: MediaConfig.data.ocrProgram || null,
+                            language: MediaConfig.data.ocrLanguage
+                        } : {
+                            type: 'command',
+                            program: MediaConfig.data.ocrProgram,
+                            args: MediaConfig.data.ocrArguments.split(/\s+/).filter((a) => a)
+                        };
+                    const result = await showProgress(
+                        (report) => guardAsync(async () => {
+                            const media = await MMedia.open(Playback.player!.source);
+                            try {
+                                return await media.recognizeSubtitles(x.index, options,
+                                    (p) => report(p, `${(p * 100).toFixed(0)}%`));
+                            } finally {
+                                await media.close();
+                            }
+                        }, $_('msg.failed-to-recognize-subtitle-track'), null),
+                        $_('msg.recognizing-subtitle-track'), null);
+                    if (!result) return;
 
-                    const subs = guard(() => convertBackendSubtitles(result),
-                        $_('msg.failed-to-extract-subtitle-track'), null);
-                    if (!subs || subs.entries.length == 0) {
-                        Frontend.setStatus($_('msg.failed-to-extract-subtitle-track'), 'error');
-                        return;
-                    }
-
-                    const options = await openDialog(
-                        Dialog.importOptions, subs.migrated != 'text', subs);
-                    if (!options) return;
-
-                    const entries = SubtitleUtil.merge(Source.subs, subs, options);
-                    if (entries.length > 0) await Editing.setSelection(entries);
-                    await Source.markChanged(ChangeType.General, $_('c.import-extracted-track'));
-                    Frontend.setStatus($_('msg.imported-extracted-track'));
+                    const uncertain = result.entries.filter(
+                        (x) => x.confidence !== undefined && x.confidence < OCR_UNCERTAIN).length;
+                    if (await importExtracted(result))
+                        Frontend.setStatus(uncertain > 0
+                            ? $_('msg.imported-recognized-track-n-uncertain', {values: {n: uncertain}})
+                            : $_('msg.imported-extracted-track'));
                 }
             })) ?? [],
         emptyText: () => $_('msg.no-available-item')
@@ -271,6 +383,13 @@ export const PlaybackCommands = {
         name: () => $_('action.previous-frame'),
         call: () => Playback.player?.requestPreviousFrame()
     }),
+    playReverse: new UICommand(() => $_('category.media'),
+        [ CommandBinding.from(['Shift+CmdOrCtrl+ArrowLeft'], ['Timeline', 'Preview']),
+          CommandBinding.from(['Alt+Shift+CmdOrCtrl+ArrowLeft']), ],
+    {
+        name: () => $_('action.play-reverse'),
+        call: () => Playback.player?.playReverse()
+    }),
     nextFrame: new UICommand(() => $_('category.media'),
         [ CommandBinding.from(['CmdOrCtrl+ArrowRight'], ['Timeline', 'Preview']),
           CommandBinding.from(['Alt+CmdOrCtrl+ArrowRight']), ],
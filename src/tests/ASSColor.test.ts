@@ -0,0 +1,24 @@
+import { expect, test } from 'vitest';
+import * as Color from 'colorjs.io/fn';
+import { fromASSColor, toASSColor } from '../lib/core/formats/ASS.svelte';
+
+Color.ColorSpace.register(Color.sRGB);
+
+test('ASS colours survive reading and writing', () => {
+    for (const str of ['&H00000000', '&HFFFFFFFF', '&H80FF8000', '&H7F123456', '&H01ABCDEF']) {
+        const color = fromASSColor(str);
+        expect(color).not.toBeNull();
+        expect(toASSColor(color!)).toBe(str);
+    }
+});
+
+test('ASS alpha is transparency', () => {
+    expect(fromASSColor('&H00FFFFFF')!.alpha).toBe(1);
+    expect(fromASSColor('&HFF000000')!.alpha).toBe(0);
+    expect(toASSColor({ space: Color.sRGB, coords: [1, 0, 0], alpha: 1 })).toBe('&H000000FF');
+});
+
+test('colours between steps are rounded', () => {
+    expect(toASSColor({ space: Color.sRGB, coords: [0.5, 0.5, 0.5], alpha: 0.5 }))
+        .toBe('&H80808080');
+});